@@ -0,0 +1,32 @@
+use websocket_std::sync::blocking::WSBlockingClient;
+use websocket_std::sync::client::Message;
+use websocket_std::result::{WebSocketError, WebSocketResult};
+use std::time::Duration;
+
+fn main() -> WebSocketResult<()> {
+    let host: &str = "localhost";
+    let port: u16 = 3000;
+    let path: &str = "/";
+
+    let mut client = WSBlockingClient::connect(host, port, path, Some(&["chat", "superchat"]))?;
+    let protocol = client.protocol().unwrap_or("--");
+    println!("Connected, accepted protocol: {protocol}");
+
+    client.send("Hello world")?;
+
+    loop {
+        match client.recv_timeout(Duration::from_secs(10)) {
+            Ok(Some(Message::TEXT(msg))) => println!("[SERVER]: {}", msg),
+            Ok(Some(Message::BINARY(data))) => println!("[SERVER]: {} bytes", data.len()),
+            Ok(None) => break,                                    // No messages in 10 seconds
+            Err(WebSocketError::ConnectionClose) => break,
+            Err(e) => return Err(e)
+        }
+    }
+
+    if let Some(reason) = client.close_reason() {
+        println!("Connection closed, code: {}, reason: {}", reason.code(), reason.reason());
+    }
+
+    Ok(())
+}
@@ -16,7 +16,10 @@ fn websocket_handler(ws: &mut WebSocket, event: &WSEvent, data: Option<WSData>)
     match event {
         WSEvent::ON_CONNECT(msg) => on_connect(ws, msg, data),
         WSEvent::ON_TEXT(msg) => on_message(ws, msg, data),
-        WSEvent::ON_CLOSE(reason) => on_close(reason, data)
+        WSEvent::ON_BINARY(_) => {}
+        WSEvent::ON_CLOSE(reason) => on_close(reason, data),
+        WSEvent::ON_DRAINED => {}
+        WSEvent::ON_FRAGMENT { .. } => {}
     }
 }
 
@@ -33,7 +36,7 @@ fn on_connect(ws: &mut WebSocket, _msg: &Option<String>, _data: Option<WSData>)
     if let Some(msg) = _msg {
         println!("Message received on connect: {}", msg);
     }
-    ws.send("Hello world");
+    if let Err(e) = ws.send("Hello world") { println!("Can't send the message: {e}"); }
 }
 
 fn on_close(reason: &Reason, _data: Option<WSData>) {
@@ -41,14 +44,14 @@ fn on_close(reason: &Reason, _data: Option<WSData>) {
     let mut _code = 0u16;
 
     match reason {
-        Reason::SERVER_CLOSE(c) => {
+        Reason::SERVER_CLOSE(c, _) => {
             _who_closed = "server";
-            _code = *c;
+            _code = u16::from(*c);
         },
 
-        Reason::CLIENT_CLOSE(c) => {
+        Reason::CLIENT_CLOSE(c, _) => {
             _who_closed = "client";
-            _code = *c;
+            _code = u16::from(*c);
         }
     }
 
@@ -66,7 +69,8 @@ fn main() -> WebSocketResult<()> {
     let config = Config {
         callback: Some(websocket_handler), 
         data: Some(data.clone()),
-        protocols: Some(&["chat", "superchat"])
+        protocols: Some(&["chat", "superchat"]),
+        random: None
     };
 
     client.init(host, port, path, Some(config));
@@ -74,7 +78,7 @@ fn main() -> WebSocketResult<()> {
     let start = time::Instant::now();
     loop {
         if start.elapsed() >  time::Duration::from_secs(10) { break };
-        client.event_loop()?;
+        client.event_loop_timeout(time::Duration::from_millis(100))?;
     }
 
     print!("Count: {}\n", data.borrow().count);
@@ -1,5 +1,5 @@
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use websocket_std::sync::client::{WSClient, Config, Reason, WSEvent};
 use websocket_std::result::WebSocketResult;
 use std::sync::{Arc, Mutex};
@@ -17,7 +17,10 @@ fn websocket_handler(ws: &mut WebSocket, event: &WSEvent, data: Option<WSData>)
     match event {
         WSEvent::ON_CONNECT(msg) => on_connect(ws, msg, data),
         WSEvent::ON_TEXT(msg) => on_message(ws, msg, data),
-        WSEvent::ON_CLOSE(reason) => on_close(reason, data)
+        WSEvent::ON_BINARY(_) => {}
+        WSEvent::ON_CLOSE(reason) => on_close(reason, data),
+        WSEvent::ON_DRAINED => {}
+        WSEvent::ON_FRAGMENT { .. } => {}
     }
 }
 
@@ -37,7 +40,7 @@ fn on_connect(ws: &mut WebSocket, _msg: &Option<String>, _data: Option<WSData>)
         println!("Message received on connect: {}", msg);
     }
 
-    ws.send("Hello world");
+    if let Err(e) = ws.send("Hello world") { println!("Can't send the message: {e}"); }
 }
 
 fn on_close(reason: &Reason, _data: Option<WSData>) {
@@ -45,14 +48,14 @@ fn on_close(reason: &Reason, _data: Option<WSData>) {
     let mut _code = 0u16;
 
     match reason {
-        Reason::SERVER_CLOSE(c) => {
+        Reason::SERVER_CLOSE(c, _) => {
             _who_closed = "server";
-            _code = *c;
+            _code = u16::from(*c);
         },
 
-        Reason::CLIENT_CLOSE(c) => {
+        Reason::CLIENT_CLOSE(c, _) => {
             _who_closed = "client";
-            _code = *c;
+            _code = u16::from(*c);
         }
     }
 
@@ -63,7 +66,7 @@ fn worker(client: &mut WebSocket) {
     let start = Instant::now();
     loop {
         if start.elapsed().as_secs() >= 10 { break }
-        let result = client.event_loop(); 
+        let result = client.event_loop_timeout(Duration::from_millis(100)); 
         if result.is_err() {
             print!("{}", result.unwrap_err());
             break;
@@ -81,7 +84,8 @@ fn main() -> WebSocketResult<()> {
     let config = Config {
         callback: Some(websocket_handler), 
         data: Some(data.clone()),
-        protocols: Some(&["chat", "superchat"])
+        protocols: Some(&["chat", "superchat"]),
+        random: None
     };
     
     println!(
@@ -102,11 +106,21 @@ fn main() -> WebSocketResult<()> {
     c1.init(host, port, path, Some(config.clone()));
     c2.init(host, port, path, Some(config));
 
+    // Handle to send messages through c1 from other threads
+    let sender = c1.sender();
+
     let t1 = thread::spawn(move || worker(&mut c1));
     let t2 =thread::spawn(move || worker(&mut c2));
+    let t3 = thread::spawn(move || {
+        for i in 0..5 {
+            thread::sleep(Duration::from_secs(1));
+            if sender.send_text(format!("Message {i} from another thread").as_str()).is_err() { break }
+        }
+    });
 
     let _ = t1.join();
     let _ = t2.join();
+    let _ = t3.join();
 
     println!("Total messages received: {}", data.lock().unwrap().count);
     Ok(())
@@ -26,10 +26,10 @@ void ws_handler(WSSClient_t* client, RustEvent rs_event, void* data) {
 
         switch (ws_reason->reason) {
             case WSREASON_SERVER_CLOSED: 
-                printf("Server close the connection C: %u\n", ws_reason->status);
+                printf("Server close the connection C: %u, reason: %s\n", ws_reason->status, ws_reason->message);
                 break;
             case WSREASON_CLIENT_CLOSED: 
-                printf("Client close the connection C: %u\n", ws_reason->status);
+                printf("Client close the connection C: %u, reason: %s\n", ws_reason->status, ws_reason->message);
                 break;
             default:
                 break;
@@ -41,6 +41,7 @@ void ws_handler(WSSClient_t* client, RustEvent rs_event, void* data) {
         wssclient_send(client, "Hello from C response");
     }
 
+    free_rust_event(event);
 }
 
 void *handler(void *arg) {
@@ -15,6 +15,9 @@ typedef enum {
     WSStatusConnectionCloseError,
     WSStatusDecodingFromUTF8Error,
     WSStatusIOError, 
+    WSStatusMessageTooBig,
+    WSStatusTimeout,
+    WSStatusQueueFull,
 } WSStatus;
 
 typedef enum {
@@ -22,17 +25,72 @@ typedef enum {
     WSREASON_CLIENT_CLOSED
 } WSReason;
 
+// Value of WSEvent_CLOSE, the struct and the message are valid until free_rust_event
 typedef struct {
     WSReason reason;
     uint16_t status;
+    const char* message;
 } WSReason_t;
 
+typedef enum {
+    WSMESSAGE_TEXT,
+    WSMESSAGE_BINARY
+} WSMessageKind;
+
+// Value of WSEvent_FRAGMENT, data is only valid during the callback (the struct until free_rust_event)
+typedef struct {
+    WSMessageKind kind;
+    const uint8_t* data;
+    size_t len;
+    bool is_final;
+} WSFragment_t;
+
+// Value of WSEvent_BINARY, data is only valid during the callback (the struct until free_rust_event)
+typedef struct {
+    const uint8_t* data;
+    size_t len;
+} WSBinary_t;
+
+// Returned by wssclient_state
+typedef enum {
+    WSSTATE_NOT_INIT,
+    WSSTATE_CONNECTING,
+    WSSTATE_HANDSHAKE,
+    WSSTATE_OPEN,
+    WSSTATE_CLOSING,
+    WSSTATE_CLOSED
+} WSState;
+
+// Statistics of the connection filled by wssclient_stats, the durations are 0 until they are measured
+typedef struct {
+    uint64_t bytes_sent;
+    uint64_t bytes_received;
+    uint64_t frames_sent;
+    uint64_t frames_received;
+    uint64_t text_messages_sent;
+    uint64_t binary_messages_sent;
+    uint64_t text_messages_received;
+    uint64_t binary_messages_received;
+    uint64_t pings_sent;
+    uint64_t pongs_sent;
+    uint64_t pings_received;
+    uint64_t pongs_received;
+    uint64_t last_rtt_us;                  // From the last ping sent to its pong
+    uint64_t queue_high_water_messages;
+    uint64_t queue_high_water_bytes;
+    uint64_t handshake_us;
+    uint64_t reconnects;                   // Times wssclient_init was called again with the same client
+} WSStats_t;
+
 typedef const void* RustEvent;
 
 typedef enum WSEventKind { 
     WSEvent_CONNECT,
     WSEvent_TEXT,
     WSEvent_CLOSE,
+    WSEvent_DRAINED,
+    WSEvent_FRAGMENT,
+    WSEvent_BINARY,
 } WSEventKind_t;
 
 typedef struct WSEvent {
@@ -79,6 +137,29 @@ void wssclient_init(WSSClient_t *client,
 */
 WSStatus wssclient_loop(WSSClient_t* client);
 
+/*
+* Same as wssclient_loop, but if there's nothing to do it waits until the socket is ready or the timeout expires
+* 
+* Parameters:
+* - WSSClient_t* client
+* - uint64_t timeout_ms: Max time to wait in milliseconds
+*
+* Return:
+* Internal state of the websocket, just to know if it is fine or something happened during some operation.
+*
+*/
+WSStatus wssclient_loop_timeout(WSSClient_t* client, uint64_t timeout_ms);
+
+/*
+* Process all the frames ready in each call to the event loop instead of one input and one output event
+* 
+* Parameters:
+* - WSSClient_t* client
+* - bool drain
+*
+*/
+void wssclient_set_drain(WSSClient_t* client, bool drain);
+
 
 /*
 * Add a new event in the websocket to send the given message (Text)
@@ -87,8 +168,202 @@ WSStatus wssclient_loop(WSSClient_t* client);
 * - WSSClient_t* client
 * - message: string to send
 *
+* Returns WSStatusQueueFull if the message doesn't fit in the outbound queue
+* and WSStatusConnectionCloseError if the connection is closed or closing
+*
+*/
+WSStatus wssclient_send(WSSClient_t* client, const char* message);
+
+
+/*
+* Set the max number of messages waiting to be sent, wssclient_send returns
+* WSStatusQueueFull once the limit is reached. There's no limit by default.
+* 
+* Parameters:
+* - WSSClient_t* client
+* - uint64_t max: max number of messages
+*
+*/
+void wssclient_set_max_queued_messages(WSSClient_t* client, uint64_t max);
+
+
+/*
+* Set the max payload bytes waiting to be sent, wssclient_send returns
+* WSStatusQueueFull if the message doesn't fit. There's no limit by default.
+* 
+* Parameters:
+* - WSSClient_t* client
+* - uint64_t max: max bytes
+*
+*/
+void wssclient_set_max_queued_bytes(WSSClient_t* client, uint64_t max);
+
+
+/*
+* Returns the payload bytes of the messages waiting to be sent. The event
+* WSEvent_DRAINED is received when the queue is empty again.
+* 
+* Parameters:
+* - WSSClient_t* client
+*
+*/
+uint64_t wssclient_queued_bytes(const WSSClient_t* client);
+
+
+/*
+* Deliver each frame of the messages received with the event WSEvent_FRAGMENT
+* as it arrives, the messages are not kept in memory and the max message size
+* doesn't apply. Compressed messages are delivered in a single fragment.
+* 
+* Parameters:
+* - WSSClient_t* client
+* - bool stream: true to receive the fragments
+*
+*/
+void wssclient_set_stream_fragments(WSSClient_t* client, bool stream);
+
+
+/*
+* Use receive and send buffers of a fixed size, allocated in wssclient_init.
+* Nothing is allocated to send and receive messages once the connection is
+* open (without extensions). Must be called before wssclient_init.
+* The receive buffer must hold the handshake response and a whole frame,
+* bigger frames or messages close the connection (1009). A message that
+* doesn't fit in the free space of the send buffer fails with
+* WSStatusQueueFull and one that never fits with WSStatusMessageTooBig.
+* 
+* Parameters:
+* - WSSClient_t* client
+* - size_t recv_size: bytes of the receive buffer
+* - size_t send_size: bytes of the send buffer (278 at least, a smaller size is raised)
+*
+*/
+void wssclient_set_fixed_buffers(WSSClient_t* client, size_t recv_size, size_t send_size);
+
+
+/*
+* Set the max payload length accepted for a frame received, if the server
+* sends a bigger frame the connection is closed with status 1009.
+* 
+* Parameters:
+* - WSSClient_t* client
+* - uint64_t size: max payload length in bytes
+*
 */
-void wssclient_send(WSSClient_t* client, const char* message);
+void wssclient_set_max_frame_size(WSSClient_t* client, uint64_t size);
+
+
+/*
+* Set the max length accepted for a message received (all the frames of the message),
+* if the server sends a bigger message the connection is closed with status 1009.
+* 
+* Parameters:
+* - WSSClient_t* client
+* - uint64_t size: max message length in bytes
+*
+*/
+void wssclient_set_max_message_size(WSSClient_t* client, uint64_t size);
+
+
+/*
+* Returns the state of the connection
+* 
+* Parameters:
+* - const WSSClient_t* client
+*
+*/
+WSState wssclient_state(const WSSClient_t* client);
+
+
+/*
+* Returns true once the handshake is done until the close handshake starts
+* 
+* Parameters:
+* - const WSSClient_t* client
+*
+*/
+bool wssclient_is_open(const WSSClient_t* client);
+
+
+/*
+* Write the address of the server ("ip:port") in the buffer, ending with \0.
+* Returns false if the socket is not connected or the address doesn't fit.
+* 
+* Parameters:
+* - const WSSClient_t* client
+* - char* buffer
+* - size_t len: bytes of the buffer (48 fit any address)
+*
+*/
+bool wssclient_peer_addr(const WSSClient_t* client, char* buffer, size_t len);
+
+
+/*
+* Same as wssclient_peer_addr with the address of the socket of the client
+*
+*/
+bool wssclient_local_addr(const WSSClient_t* client, char* buffer, size_t len);
+
+
+/*
+* Returns when the handshake was done in milliseconds since the unix epoch,
+* 0 if the connection was never open
+* 
+* Parameters:
+* - const WSSClient_t* client
+*
+*/
+uint64_t wssclient_connect_time_ms(const WSSClient_t* client);
+
+
+/*
+* Returns the milliseconds since the handshake was done (the time the
+* connection was open once it is closed), 0 if the connection was never open
+* 
+* Parameters:
+* - const WSSClient_t* client
+*
+*/
+uint64_t wssclient_uptime_ms(const WSSClient_t* client);
+
+
+/*
+* Write the protocol accepted by the server in the buffer, ending with \0.
+* Returns false if the server didn't select a protocol or it doesn't fit.
+* 
+* Parameters:
+* - const WSSClient_t* client
+* - char* buffer
+* - size_t len: bytes of the buffer
+*
+*/
+bool wssclient_protocol(const WSSClient_t* client, char* buffer, size_t len);
+
+
+/*
+* Write the extensions accepted by the server in the buffer, ending with \0,
+* in the format of the header Sec-WebSocket-Extensions (empty without extensions).
+* Returns false if they don't fit.
+* 
+* Parameters:
+* - const WSSClient_t* client
+* - char* buffer
+* - size_t len: bytes of the buffer
+*
+*/
+bool wssclient_extensions(const WSSClient_t* client, char* buffer, size_t len);
+
+
+/*
+* Copy the statistics of the connection (bytes, frames and messages sent and
+* received, pings, round trip time...). Returns false if stats is NULL.
+* 
+* Parameters:
+* - const WSSClient_t* client
+* - WSStats_t* stats
+*
+*/
+bool wssclient_stats(const WSSClient_t* client, WSStats_t* stats);
 
 
 /*
@@ -102,4 +377,15 @@ void wssclient_drop(WSSClient_t* client);
 
 WSEvent_t from_rust_event(RustEvent event);
 
+/*
+* Free the memory of an event returned by from_rust_event (the text, the
+* fragment, the close reason...). Call it once the event is handled, the value of the event
+* can't be used after.
+* 
+* Parameters:
+* - WSEvent_t event
+*
+*/
+void free_rust_event(WSEvent_t event);
+
 #endif
\ No newline at end of file
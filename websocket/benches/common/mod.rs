@@ -0,0 +1,31 @@
+// Helpers shared by the benchmarks
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use sha1_smol::Sha1;
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// Accept the connection of the client and answer its handshake
+pub fn accept(listener: TcpListener) -> TcpStream {
+    let (mut conn, _) = listener.accept().unwrap();
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+
+    while !request.ends_with(b"\r\n\r\n") {
+        let n = conn.read(&mut buf).unwrap();
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8(request).unwrap();
+    let key = request.lines()
+        .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+        .unwrap();
+
+    let mut sha1 = Sha1::new();
+    sha1.update((key.trim().to_owned() + GUID).as_bytes());
+    let accept = base64::encode(&sha1.digest().bytes());
+
+    let response = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept);
+    conn.write_all(response.as_bytes()).unwrap();
+    conn
+}
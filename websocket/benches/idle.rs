@@ -0,0 +1,77 @@
+// CPU used by an idle connection running event_loop in a loop vs event_loop_timeout.
+// The time reported is the CPU time of the process for every 10ms of wall time.
+// Only on unix, the CPU time is measured with libc; on other targets the bench is empty.
+//
+// cargo bench --bench idle
+#![cfg_attr(not(unix), allow(dead_code, unused_imports))]
+
+#[cfg(unix)]
+mod common;
+
+#[cfg(unix)]
+use common::accept;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::io::Read;
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+use websocket_std::sync::blocking::WSBlockingClient;
+
+const ROUND: Duration = Duration::from_millis(10);
+
+// CPU time used by the process (user + system)
+#[cfg(unix)]
+fn cpu_time() -> Duration {
+    let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+// CPU time used by the client during the rounds, the server doesn't send anything
+#[cfg(unix)]
+fn idle(rounds: u64, wait: bool) -> Duration {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let server = thread::spawn(move || {
+        let mut conn = accept(listener);
+        // Keep the connection open until the client is done
+        let _ = conn.read(&mut [0u8; 1]);
+    });
+
+    let mut ws = WSBlockingClient::connect("127.0.0.1", port, "/", None).unwrap();
+    let client = ws.client();
+
+    let start = Instant::now();
+    let ticks = cpu_time();
+    while start.elapsed() < ROUND * rounds as u32 {
+        if wait { client.event_loop_timeout(ROUND).unwrap() } else { client.event_loop().unwrap() }
+    }
+    let elapsed = cpu_time() - ticks;
+
+    drop(ws);
+    server.join().unwrap();
+    elapsed
+}
+
+#[cfg(unix)]
+fn bench_idle(c: &mut Criterion) {
+    let mut group = c.benchmark_group("idle");
+    group.sample_size(10);
+
+    for (name, wait) in [("event_loop", false), ("event_loop_timeout", true)] {
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter_custom(|rounds| idle(rounds, wait))
+        });
+    }
+
+    group.finish();
+}
+
+#[cfg(unix)]
+criterion_group!(benches, bench_idle);
+#[cfg(unix)]
+criterion_main!(benches);
+
+#[cfg(not(unix))]
+fn main() {}
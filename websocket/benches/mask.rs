@@ -0,0 +1,35 @@
+// Masking a payload in place, word at a time against the byte by byte reference.
+//
+// cargo bench --bench mask
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use websocket_std::mask::{apply_mask, apply_mask_reference};
+
+const SIZES: [usize; 4] = [16, 1024, 64 * 1024, 1024 * 1024];
+const MASK: [u8; 4] = [0x12, 0x34, 0x56, 0x78];
+
+fn bench_mask(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mask");
+
+    for size in SIZES {
+        let mut payload = vec![b'a'; size];
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("word", size), &size, |b, _| {
+            b.iter(|| apply_mask(black_box(&mut payload), MASK, 0))
+        });
+
+        group.bench_with_input(BenchmarkId::new("reference", size), &size, |b, _| {
+            b.iter(|| apply_mask_reference(black_box(&mut payload), MASK, 0))
+        });
+
+        // Payload that doesn't start at a word boundary
+        group.bench_with_input(BenchmarkId::new("word_unaligned", size), &size, |b, _| {
+            b.iter(|| apply_mask(black_box(&mut payload[1..]), MASK, 1))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_mask);
+criterion_main!(benches);
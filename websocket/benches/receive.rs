@@ -0,0 +1,99 @@
+// Throughput of the receive path: a local server writes the frames as fast as it can and the client parses them.
+//
+// cargo bench --bench receive
+mod common;
+
+use common::accept;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+use websocket_std::sync::blocking::WSBlockingClient;
+use websocket_std::sync::client::{WSClient, Message};
+
+// Message size and size of the frames the server splits it into
+const CASES: [(&str, usize, usize); 4] = [
+    ("small", 128, 128),
+    ("medium", 16 * 1024, 16 * 1024),
+    ("large", 1024 * 1024, 1024 * 1024),
+    ("fragmented", 4 * 1024 * 1024, 64 * 1024),
+];
+
+fn serialize_message(size: usize, frame_size: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(size + 16 * (size / frame_size + 1));
+    let mut sent = 0;
+
+    while sent < size {
+        let len = frame_size.min(size - sent);
+        let fin = if sent + len == size { 0x80 } else { 0x00 };
+        let opcode = if sent == 0 { 0x01 } else { 0x00 };
+        bytes.push(fin | opcode);
+
+        if len < 126 {
+            bytes.push(len as u8);
+        } else if len <= u16::MAX as usize {
+            bytes.push(126);
+            bytes.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            bytes.push(127);
+            bytes.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+
+        bytes.resize(bytes.len() + len, b'a');
+        sent += len;
+    }
+
+    bytes
+}
+
+// Time to receive the messages once the connection is open
+fn receive(messages: u64, size: usize, frame_size: usize) -> Duration {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let server = thread::spawn(move || {
+        let mut conn = accept(listener);
+        let message = serialize_message(size, frame_size);
+
+        // Wait for the client to be ready (masked text frame "go")
+        conn.read_exact(&mut [0u8; 8]).unwrap();
+        for _ in 0..messages { conn.write_all(&message).unwrap(); }
+        // Keep the connection open until the client is done
+        let _ = conn.read(&mut [0u8; 1]);
+    });
+
+    let mut client = WSClient::new();
+    client.set_max_message_size(size as u64);
+    let mut ws = WSBlockingClient::connect_with(client, "127.0.0.1", port, "/", None).unwrap();
+
+    let start = Instant::now();
+    ws.send("go").unwrap();
+    for _ in 0..messages {
+        match ws.recv().unwrap() {
+            Message::TEXT(text) => assert_eq!(text.len(), size),
+            Message::BINARY(_) => panic!("text message expected")
+        }
+    }
+    let elapsed = start.elapsed();
+
+    drop(ws);
+    server.join().unwrap();
+    elapsed
+}
+
+fn bench_receive(c: &mut Criterion) {
+    let mut group = c.benchmark_group("receive");
+
+    for (name, size, frame_size) in CASES {
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter_custom(|iters| receive(iters, size, frame_size))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_receive);
+criterion_main!(benches);
@@ -0,0 +1,540 @@
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::future::{Future, poll_fn};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::time::Sleep;
+use futures_core::Stream;
+use futures_sink::Sink;
+use crate::core::buffer::RecvBuffer;
+use crate::core::handshake::{handshake_request, verify_response, negotiate_protocol, negotiate_extensions};
+use crate::core::recv::{DEFAULT_MESSAGE_SIZE, DEFAULT_MAX_FRAME_SIZE, DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_TIMEOUT, DEFAULT_READ_CHUNK_SIZE, check_recv_limits, rsv_allowed, fragment_allowed, into_message};
+use crate::core::traits::{Serialize, Parse};
+use crate::http::response::Response;
+use crate::result::{WebSocketError, WebSocketResult, HandShakeError, ProtocolError};
+use crate::sync::client::{Reason, Message, parse_close_frame};
+use crate::ws_basic::header::{OPCODE, FLAG};
+use crate::ws_basic::mask::{Mask, gen_mask};
+use crate::codec::{OsRandom, SharedRandom, shared_random};
+use crate::ws_basic::frame::{DataFrame, ControlFrame, Frame, FrameKind, parse_frame};
+use crate::ws_basic::status_code::CloseCode;
+
+const SEND_BUFFER_SIZE: usize = 64 * 1024;                  // The sink waits for the socket when there are more bytes waiting to be sent
+const END_HEADER: &[u8] = b"\r\n\r\n";
+const MAX_CONTROL_PAYLOAD: usize = 125;                     // Max payload of a control frame (RFC 6455 5.5)
+
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+#[derive(PartialEq)]
+enum ConnectionStatus {
+    OPEN,
+    CLIENT_WANTS_TO_CLOSE,
+    SERVER_WANTS_TO_CLOSE,
+    CLOSE
+}
+
+// Options of AsyncWSClient::connect_with, the default has the values used by connect
+#[derive(Clone)]
+pub struct AsyncConfig<'a> {
+    pub protocols: Option<&'a [&'a str]>,
+    pub timeout: Duration,                                   // Max time to wait for the handshake and for the response to the close
+    pub random: Option<SharedRandom>,                        // Masking keys and Sec-WebSocket-Key, the entropy of the OS (OsRandom) if None
+}
+
+impl Default for AsyncConfig<'_> {
+    fn default() -> Self {
+        AsyncConfig { protocols: None, timeout: DEFAULT_TIMEOUT, random: None }
+    }
+}
+
+// Client for tokio, uses the same frames and handshake as the sync client.
+// The messages received are returned by the Stream and the messages to send (Message or String for text) are taken by the Sink,
+// the sink only accepts more messages while the socket accepts the bytes already sent (backpressure).
+//
+// let mut ws = AsyncWSClient::connect("localhost", 3000, "/", None).await?;
+// ws.send("Hello").await?;
+// while let Some(msg) = ws.next().await { ... }
+pub struct AsyncWSClient {
+    stream: TcpStream,
+    connection_status: ConnectionStatus,
+    message_size: u64,
+    max_frame_size: u64,                                     // Max payload length accepted in a single frame received
+    max_message_size: u64,                                   // Max length accepted for a message received (sum of all the frames of the message)
+    protocol: Option<String>,
+    recv_storage: RecvBuffer,                                // Bytes received from the socket that are not a whole frame yet
+    recv_data: Vec<u8>,                                      // Data of the frames of the message being received
+    recv_opcode: OPCODE,                                     // Opcode of the first frame of the message being received
+    recv_in_message: bool,                                   // A data frame without FIN was received, the next data frames must be CONTINUATION
+    send_buffer: Vec<u8>,                                    // Frames serialized waiting for the socket, the bytes before send_pos are already written
+    send_pos: usize,
+    random: SharedRandom,                                    // Masking keys of the frames
+    timeout: Duration,                                       // Max time to wait for the response to the close
+    close_deadline: Option<Pin<Box<Sleep>>>,                 // Started with the close handshake of the client
+    close_reason: Option<Reason>,
+}
+
+impl AsyncWSClient {
+    // Connect and wait until the handshake is done, fails with Timeout if the server doesn't answer in 30 seconds
+    pub async fn connect(host: &str, port: u16, path: &str, protocols: Option<&[&str]>) -> WebSocketResult<Self> {
+        AsyncWSClient::connect_with(host, port, path, AsyncConfig { protocols, ..AsyncConfig::default() }).await
+    }
+
+    // Connect and wait until the handshake is done or the timeout expires
+    pub async fn connect_timeout(host: &str, port: u16, path: &str, protocols: Option<&[&str]>, timeout: Duration) -> WebSocketResult<Self> {
+        AsyncWSClient::connect_with(host, port, path, AsyncConfig { protocols, timeout, random: None }).await
+    }
+
+    // Connect with the options of the config and wait until the handshake is done or the timeout expires
+    pub async fn connect_with(host: &str, port: u16, path: &str, config: AsyncConfig<'_>) -> WebSocketResult<Self> {
+        let random = config.random.unwrap_or_else(|| shared_random(OsRandom));
+        match tokio::time::timeout(config.timeout, AsyncWSClient::handshake(host, port, path, config.protocols, random)).await {
+            Ok(result) => result.map(|mut client| { client.timeout = config.timeout; client }),
+            Err(_) => Err(WebSocketError::Timeout)
+        }
+    }
+
+    async fn handshake(host: &str, port: u16, path: &str, protocols: Option<&[&str]>, mut random: SharedRandom) -> WebSocketResult<Self> {
+        let stream = TcpStream::connect((host, port)).await;
+        if stream.is_err() { return Err(WebSocketError::UnreachableHost(stream.err().unwrap())) }
+        let mut stream = stream.unwrap();
+
+        // Extensions are not offered, the server can't accept any
+        let (request, sec_websocket_key) = handshake_request(path, protocols, &[], &mut random)?;
+        stream.write_all(request.serialize().as_slice()).await?;
+
+        // Read until the end of the response, the bytes after it are frames sent by the server
+        let mut recv_storage = RecvBuffer::new();
+        let header_len = loop {
+            if let Some(end) = recv_storage.as_slice().windows(END_HEADER.len()).position(|w| w == END_HEADER) {
+                break end + END_HEADER.len();
+            }
+
+            let bytes_read = stream.read(recv_storage.chunk_mut(DEFAULT_READ_CHUNK_SIZE)).await?;
+            if bytes_read == 0 { return Err(WebSocketError::ConnectionClose) }
+            recv_storage.advance(bytes_read);
+        };
+
+        let response = Response::parse(&recv_storage.as_slice()[0..header_len]);
+        if response.is_err() { return Err(WebSocketError::HandShake(HandShakeError::InvalidHeader(String::from("Status-Line")))) }
+        let response = response.unwrap();
+
+        verify_response(&response, &sec_websocket_key)?;
+        let protocol = negotiate_protocol(&response, protocols, false)?;
+        negotiate_extensions(&response, &[])?;
+        recv_storage.consume(header_len);
+
+        Ok(AsyncWSClient {
+            stream,
+            connection_status: ConnectionStatus::OPEN,
+            message_size: DEFAULT_MESSAGE_SIZE,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            protocol,
+            recv_storage,
+            recv_data: Vec::new(),
+            recv_opcode: OPCODE::TEXT,
+            recv_in_message: false,
+            send_buffer: Vec::new(),
+            send_pos: 0,
+            random,
+            timeout: DEFAULT_TIMEOUT,
+            close_deadline: None,
+            close_reason: None,
+        })
+    }
+
+    // Returns the protocol accepted by the server
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    // Returns who closed the connection, the status code and the reason once the connection is closed
+    pub fn close_reason(&self) -> Option<&Reason> {
+        if self.connection_status != ConnectionStatus::CLOSE { return None }
+        self.close_reason.as_ref()
+    }
+
+    // Max payload length of the frames sent, the messages are split in frames of this size (at least 1 byte)
+    pub fn set_message_size(&mut self, size: u64) {
+        self.message_size = size.max(1);
+    }
+
+    // Max time to wait for the response of the server to the close, the connection is closed with 1006 after it
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    // Max payload length of a frame received, if the server announces a bigger frame the connection will be closed (1009)
+    pub fn set_max_frame_size(&mut self, size: u64) {
+        self.max_frame_size = size;
+    }
+
+    // Max length of a message received, if the server sends a bigger message the connection will be closed (1009)
+    pub fn set_max_message_size(&mut self, size: u64) {
+        self.max_message_size = size;
+    }
+
+    // Send the message and wait until is written in the socket
+    pub async fn send(&mut self, payload: &str) -> WebSocketResult<()> {
+        self.queue_message(OPCODE::TEXT, payload.as_bytes())?;
+        poll_fn(|cx| self.poll_send(cx)).await
+    }
+
+    // Send the binary message and wait until is written in the socket
+    pub async fn send_binary(&mut self, payload: &[u8]) -> WebSocketResult<()> {
+        self.queue_message(OPCODE::BINARY, payload)?;
+        poll_fn(|cx| self.poll_send(cx)).await
+    }
+
+    // Send a ping and wait until is written in the socket, the payload can't be longer than 125 bytes.
+    // The pong of the server is not returned by the stream
+    pub async fn ping(&mut self, payload: &[u8]) -> WebSocketResult<()> {
+        if payload.len() > MAX_CONTROL_PAYLOAD { return Err(WebSocketError::Protocol(ProtocolError::InvalidFrame)) }
+        if self.connection_status != ConnectionStatus::OPEN { return Err(WebSocketError::ConnectionClose) }
+
+        let ping_frame = ControlFrame::new(FLAG::FIN, OPCODE::PING, None, payload.to_vec(), Some(self.new_mask()?));
+        self.queue_bytes(ping_frame.serialize().as_slice());
+        poll_fn(|cx| self.poll_send(cx)).await
+    }
+
+    // Wait for the next message, None once the connection is closed
+    pub async fn recv(&mut self) -> Option<WebSocketResult<Message>> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    // Start the close handshake and wait for the response of the server, fails with Timeout if it doesn't answer in time (set_timeout)
+    pub async fn close(&mut self) -> WebSocketResult<()> {
+        let result = poll_fn(|cx| self.poll_close_handshake(cx)).await;
+        let _ = self.stream.shutdown().await;
+        result
+    }
+
+    // Split the message in frames of message_size and keep them until the socket accepts them
+    fn queue_message(&mut self, opcode: OPCODE, payload: &[u8]) -> WebSocketResult<()> {
+        if self.connection_status != ConnectionStatus::OPEN { return Err(WebSocketError::ConnectionClose) }
+
+        let mut data_sent = 0;
+
+        loop {
+            let end = (data_sent + self.message_size as usize).min(payload.len());
+            let flag = if end == payload.len() { FLAG::FIN } else { FLAG::NOFLAG };
+            let code = if data_sent == 0 { opcode.clone() } else { OPCODE::CONTINUATION };
+            let frame = DataFrame::new(flag, code, payload[data_sent..end].to_vec(), Some(self.new_mask()?));
+            self.queue_bytes(frame.serialize().as_slice());
+
+            data_sent = end;
+            if data_sent >= payload.len() { break }
+        }
+
+        Ok(())
+    }
+
+    // Masking key of a frame, fails if the random source can't generate it
+    fn new_mask(&mut self) -> WebSocketResult<Mask> {
+        Ok(gen_mask(&mut self.random)?)
+    }
+
+    // Bytes waiting for the socket
+    fn pending_bytes(&self) -> usize {
+        self.send_buffer.len() - self.send_pos
+    }
+
+    // Append the bytes after the ones waiting for the socket. The bytes already written are dropped
+    // when they are more than the ones waiting, so each byte is moved at most once
+    fn queue_bytes(&mut self, bytes: &[u8]) {
+        if self.send_pos > self.pending_bytes() {
+            self.send_buffer.copy_within(self.send_pos.., 0);
+            self.send_buffer.truncate(self.pending_bytes());
+            self.send_pos = 0;
+        }
+        self.send_buffer.extend_from_slice(bytes);
+    }
+
+    // Drop the bytes waiting for the socket, the memory is kept
+    fn clear_send_buffer(&mut self) {
+        self.send_buffer.clear();
+        self.send_pos = 0;
+    }
+
+    // Write the frames queued, the connection is closed if the socket fails
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<WebSocketResult<()>> {
+        match ready!(self.poll_write_buffer(cx)) {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(e) => Poll::Ready(Err(self.connection_lost(e)))
+        }
+    }
+
+    fn poll_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<WebSocketResult<()>> {
+        while self.pending_bytes() > 0 {
+            let bytes_written = ready!(Pin::new(&mut self.stream).poll_write(cx, &self.send_buffer[self.send_pos..]))?;
+            if bytes_written == 0 { return Poll::Ready(Err(WebSocketError::ConnectionClose)) }
+            self.send_pos += bytes_written;
+        }
+        self.clear_send_buffer();
+
+        Pin::new(&mut self.stream).poll_flush(cx).map_err(WebSocketError::from)
+    }
+
+    // Returns the next message received, None once the connection is closed.
+    // The responses to the control frames of the server are sent while waiting for messages
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<WebSocketResult<Message>>> {
+        loop {
+            match self.connection_status {
+                ConnectionStatus::CLOSE => return Poll::Ready(None),
+                // The close handshake is done once the response to the server is sent
+                ConnectionStatus::SERVER_WANTS_TO_CLOSE => {
+                    let result = ready!(self.poll_write_buffer(cx));
+                    let reason = self.close_reason.take().unwrap_or(Reason::SERVER_CLOSE(CloseCode::NoStatusReceived, String::new()));
+                    self.close_connection(reason);
+                    if let Err(e) = result { return Poll::Ready(Some(Err(e))) }
+                    return Poll::Ready(None);
+                },
+                ConnectionStatus::OPEN | ConnectionStatus::CLIENT_WANTS_TO_CLOSE => {}
+            }
+
+            if self.pending_bytes() > 0 {
+                if let Poll::Ready(Err(e)) = self.poll_write_buffer(cx) { return Poll::Ready(Some(Err(self.connection_lost(e)))) }
+            }
+
+            match self.next_message() {
+                Ok(Some(message)) => return Poll::Ready(Some(Ok(message))),
+                Ok(None) => {},
+                Err(e) => return Poll::Ready(Some(Err(e)))
+            }
+
+            // A close frame was received
+            if self.connection_status == ConnectionStatus::SERVER_WANTS_TO_CLOSE || self.connection_status == ConnectionStatus::CLOSE { continue }
+
+            // Read directly in the free space of the receive buffer
+            let mut buffer = ReadBuf::new(self.recv_storage.chunk_mut(DEFAULT_READ_CHUNK_SIZE));
+            let result = ready!(Pin::new(&mut self.stream).poll_read(cx, &mut buffer));
+            let bytes_read = buffer.filled().len();
+            if let Err(e) = result { return Poll::Ready(Some(Err(self.connection_lost(e.into())))) }
+
+            // The connection was lost without the close handshake
+            if bytes_read == 0 { return Poll::Ready(Some(Err(self.connection_lost(WebSocketError::ConnectionClose)))) }
+            self.recv_storage.advance(bytes_read);
+        }
+    }
+
+    // Parse the frames received until a whole message is ready
+    fn next_message(&mut self) -> WebSocketResult<Option<Message>> {
+        loop {
+            // Check the limits with the header before buffering the payload
+            if let Err(e) = check_recv_limits(self.recv_storage.as_slice(), self.recv_data.len(), true, self.max_frame_size, self.max_message_size) {
+                return self.fail_connection(e);
+            }
+
+            let frame = match parse_frame(self.recv_storage.as_slice()) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return Ok(None),
+                Err(e) => return self.fail_connection(e)
+            };
+
+            let len = frame.frame_len();
+            let flag = frame.get_flag();
+            let opcode = frame.get_opcode();
+
+            // Extensions are not negotiated, the reserved bits can't be set
+            if !rsv_allowed(flag, &opcode, 0) {
+                return self.fail_connection(ProtocolError::ReservedBits.into());
+            }
+
+            match frame.kind() {
+                FrameKind::Data => {
+                    if !fragment_allowed(&opcode, self.recv_in_message) {
+                        return self.fail_connection(ProtocolError::InvalidFrame.into());
+                    }
+                    self.recv_in_message = !flag.contains(FLAG::FIN);
+
+                    if opcode != OPCODE::CONTINUATION { self.recv_opcode = opcode }
+
+                    // The payload goes from the receive buffer to the message, the frame is not copied
+                    self.recv_data.extend_from_slice(frame.payload());
+                    self.recv_storage.consume(len);
+
+                    // Wait for the rest of the frames of the message
+                    if !flag.contains(FLAG::FIN) { continue }
+
+                    let data = std::mem::take(&mut self.recv_data);
+                    return match into_message(&self.recv_opcode, data) {
+                        Ok(message) => Ok(Some(message)),
+                        Err(e) => self.fail_connection(e.into())
+                    };
+                },
+                FrameKind::Control => {
+                    let frame = frame.to_control_frame();
+                    self.recv_storage.consume(len);
+
+                    let frame = match frame {
+                        Ok(frame) => frame,
+                        Err(e) => return self.fail_connection(e)
+                    };
+
+                    self.handle_control_frame(&frame)?;
+                    if self.connection_status != ConnectionStatus::OPEN && self.connection_status != ConnectionStatus::CLIENT_WANTS_TO_CLOSE { return Ok(None) }
+                },
+                FrameKind::NotDefine => return self.fail_connection(ProtocolError::InvalidFrame.into())
+            }
+        }
+    }
+
+    fn handle_control_frame(&mut self, frame: &ControlFrame) -> WebSocketResult<()> {
+        match frame.get_header().get_opcode() {
+            OPCODE::PING => {
+                let pong_frame = ControlFrame::new(FLAG::FIN, OPCODE::PONG, None, frame.get_data().to_vec(), Some(self.new_mask()?));
+                self.queue_bytes(pong_frame.serialize().as_slice());
+            },
+            OPCODE::PONG => {},
+            OPCODE::CLOSE => {
+                let (status_code, reason) = match parse_close_frame(frame) {
+                    Ok(close) => close,
+                    Err(e) => return self.fail_connection(e.into())
+                };
+
+                match self.connection_status {
+                    // Server wants to close the connection, echo the status code received after the frames already queued
+                    ConnectionStatus::OPEN => {
+                        let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, frame.get_status_code(), Vec::new(), Some(self.new_mask()?));
+                        self.queue_bytes(close_frame.serialize().as_slice());
+                        self.close_reason = Some(Reason::SERVER_CLOSE(status_code, reason));
+                        self.connection_status = ConnectionStatus::SERVER_WANTS_TO_CLOSE;
+                    },
+                    // Received a response to the client close handshake
+                    ConnectionStatus::CLIENT_WANTS_TO_CLOSE => self.close_connection(Reason::CLIENT_CLOSE(status_code, reason)),
+                    ConnectionStatus::SERVER_WANTS_TO_CLOSE => {}  // Already closing, ignore
+                    ConnectionStatus::CLOSE => {}                  // Unreachable
+                }
+            },
+            _ => return self.fail_connection(ProtocolError::InvalidFrame.into())
+        }
+
+        Ok(())
+    }
+
+    // Send the close frame and read until the server answers, the messages received meanwhile are discarded
+    fn poll_close_handshake(&mut self, cx: &mut Context<'_>) -> Poll<WebSocketResult<()>> {
+        if self.connection_status == ConnectionStatus::OPEN {
+            let mask = match self.new_mask() {
+                Ok(mask) => mask,
+                Err(e) => return Poll::Ready(self.fail_connection(e))
+            };
+            let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, Some(CloseCode::NormalClosure.into()), Vec::new(), Some(mask));
+            self.queue_bytes(close_frame.serialize().as_slice());
+            self.connection_status = ConnectionStatus::CLIENT_WANTS_TO_CLOSE;
+            self.close_deadline = Some(Box::pin(tokio::time::sleep(self.timeout)));
+        }
+
+        // The server didn't answer the close handshake in time
+        if let Some(deadline) = self.close_deadline.as_mut() {
+            if self.connection_status == ConnectionStatus::CLIENT_WANTS_TO_CLOSE && deadline.as_mut().poll(cx).is_ready() {
+                self.close_connection(Reason::CLIENT_CLOSE(CloseCode::AbnormalClosure, String::new()));
+                return Poll::Ready(Err(WebSocketError::Timeout));
+            }
+        }
+
+        loop {
+            match ready!(self.poll_recv(cx)) {
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Poll::Ready(Err(e)),
+                None => return Poll::Ready(Ok(()))
+            }
+        }
+    }
+
+    // Send a close frame with the close code of the error if the socket accepts it, close the connection and return the error
+    fn fail_connection<R>(&mut self, error: WebSocketError) -> WebSocketResult<R> {
+        let status = error.close_code().unwrap_or(CloseCode::InternalError);
+
+        // A frame partially written can't be interrupted, and the frame isn't sent if the random source can't mask it
+        if let (0, Ok(mask)) = (self.pending_bytes(), gen_mask(&mut self.random)) {
+            let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, Some(status.into()), Vec::new(), Some(mask));
+            let _ = self.stream.try_write(close_frame.serialize().as_slice()); // Ignore result, the connection will be closed anyway
+        }
+
+        self.clear_send_buffer();
+        self.recv_storage.clear();
+        self.recv_data.clear();
+        self.close_connection(Reason::CLIENT_CLOSE(status, String::new()));
+
+        Err(error)
+    }
+
+    // The socket failed or was closed without the close handshake (1006)
+    fn connection_lost(&mut self, error: WebSocketError) -> WebSocketError {
+        let reason = match self.connection_status {
+            ConnectionStatus::CLIENT_WANTS_TO_CLOSE => Reason::CLIENT_CLOSE(CloseCode::AbnormalClosure, String::new()),
+            _ => Reason::SERVER_CLOSE(CloseCode::AbnormalClosure, String::new())
+        };
+        self.close_connection(reason);
+        error
+    }
+
+    // Only the first close of the connection is kept
+    fn close_connection(&mut self, reason: Reason) {
+        if self.connection_status == ConnectionStatus::CLOSE { return }
+        self.connection_status = ConnectionStatus::CLOSE;
+        self.clear_send_buffer();
+        self.close_reason = Some(reason);
+    }
+}
+
+impl Stream for AsyncWSClient {
+    type Item = WebSocketResult<Message>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+impl Sink<Message> for AsyncWSClient {
+    type Error = WebSocketError;
+
+    // Wait for the socket if there are too many bytes waiting to be sent
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WebSocketResult<()>> {
+        let client = self.get_mut();
+        if client.connection_status != ConnectionStatus::OPEN { return Poll::Ready(Err(WebSocketError::ConnectionClose)) }
+        if client.pending_bytes() < SEND_BUFFER_SIZE { return Poll::Ready(Ok(())) }
+        client.poll_send(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message) -> WebSocketResult<()> {
+        match item {
+            Message::TEXT(text) => self.get_mut().queue_message(OPCODE::TEXT, text.as_bytes()),
+            Message::BINARY(data) => self.get_mut().queue_message(OPCODE::BINARY, &data)
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WebSocketResult<()>> {
+        self.get_mut().poll_send(cx)
+    }
+
+    // Close handshake with the timeout of set_timeout, then the socket is shut down
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WebSocketResult<()>> {
+        let client = self.get_mut();
+        let result = ready!(client.poll_close_handshake(cx));
+        ready!(Pin::new(&mut client.stream).poll_shutdown(cx)).ok();
+        Poll::Ready(result)
+    }
+}
+
+// Text messages, same as Sink<Message> with Message::TEXT
+impl Sink<String> for AsyncWSClient {
+    type Error = WebSocketError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WebSocketResult<()>> {
+        Sink::<Message>::poll_ready(self, cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: String) -> WebSocketResult<()> {
+        Sink::<Message>::start_send(self, Message::TEXT(item))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WebSocketResult<()>> {
+        Sink::<Message>::poll_flush(self, cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WebSocketResult<()>> {
+        Sink::<Message>::poll_close(self, cx)
+    }
+}
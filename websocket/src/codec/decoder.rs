@@ -0,0 +1,140 @@
+use crate::ws_basic::binary::{bytes_to_u16, bytes_to_u64};
+use super::{CodecError, OPCODE, FLAG};
+
+// Max payload length of a frame accepted by FrameDecoder::new
+pub const DEFAULT_MAX_FRAME_SIZE: u64 = 16 * 1024 * 1024;
+
+// The most significant bit of the 64 bits payload length must be 0 (RFC 6455 5.2)
+const MAX_PAYLOAD_LEN: u64 = i64::MAX as u64;
+
+#[derive(PartialEq)]
+pub enum FrameKind {
+    Data,
+    Control,
+    NotDefine
+}
+
+// Return the payload length announced by the header of the frame, the payload doesn't need to be received.
+// Useful to reject a frame before buffering it. None if the header was not completelly received.
+pub fn peek_payload_len(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() < 2 { return None }
+
+    match bytes[1] & 0b01111111 {
+        126 => bytes.get(2..4).map(|len| bytes_to_u16(len).unwrap() as u64),
+        127 => bytes.get(2..10).map(|len| bytes_to_u64(len).unwrap()),
+        payload_len => Some(payload_len as u64)
+    }
+}
+
+// Index where the header (without the mask) ends, the payload length can take 2 or 8 more bytes
+fn unmasked_header_len(bytes: &[u8]) -> usize {
+    match bytes[1] & 0b01111111 {
+        126 => 4,
+        127 => 10,
+        _ => 2
+    }
+}
+
+// Returns if the bytes contain a whole frame (header, mask and payload)
+pub fn is_frame_complete(bytes: &[u8]) -> bool {
+    match peek_payload_len(bytes) {
+        Some(payload_len) => {
+            let mask_len = if bytes[1] & 0b10000000 != 0 { 4 } else { 0 };
+            // A length that overflows can never be received
+            match payload_len.checked_add(unmasked_header_len(bytes) as u64 + mask_len) {
+                Some(frame_len) => bytes.len() as u64 >= frame_len,
+                None => false
+            }
+        },
+        None => false
+    }
+}
+
+// Frame parsed in place, the payload borrows the bytes received. The frames of the server are not masked,
+// so the payload can be used without copying it
+pub struct FrameRef<'a> {
+    flag: FLAG,
+    code: OPCODE,
+    payload: &'a [u8],
+    len: usize                                              // Bytes used by the frame (header and payload)
+}
+
+impl<'a> FrameRef<'a> {
+    pub fn get_flag(&self) -> FLAG {
+        self.flag
+    }
+
+    pub fn get_opcode(&self) -> OPCODE {
+        self.code.clone()
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    // Bytes to consume from the buffer after the frame is handled
+    pub fn frame_len(&self) -> usize {
+        self.len
+    }
+
+    pub fn kind(&self) -> FrameKind {
+        if self.code == OPCODE::CLOSE || self.code == OPCODE::PING || self.code == OPCODE::PONG {
+            FrameKind::Control
+        } else if self.code == OPCODE::BINARY || self.code == OPCODE::TEXT || self.code == OPCODE::CONTINUATION {
+            FrameKind::Data
+        } else {
+            FrameKind::NotDefine
+        }
+    }
+}
+
+// Parser of the frames received by a client. The bytes can hold several frames or part of one,
+// the caller consumes the len of each frame decoded and keeps the rest for the next call
+pub struct FrameDecoder {
+    max_frame_size: u64
+}
+
+impl FrameDecoder {
+    // Decoder with a max payload of 16 MB (DEFAULT_MAX_FRAME_SIZE)
+    pub fn new() -> Self {
+        FrameDecoder { max_frame_size: DEFAULT_MAX_FRAME_SIZE }
+    }
+
+    // Max payload length of a frame, a bigger frame fails with FrameTooBig as soon as its header is received
+    pub fn set_max_frame_size(&mut self, size: u64) {
+        self.max_frame_size = size;
+    }
+
+    // Parse the frame at the start of the bytes without copying the payload.
+    // None if the frame was not completelly received (bytes could arrive later)
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Result<Option<FrameRef<'a>>, CodecError> {
+        if bytes.len() < 2 { return Ok(None) }
+
+        let flag = FLAG::from_bits(bytes[0] & 0b11110000);
+        let code = OPCODE::from_bits(bytes[0] & 0b00001111);
+        if flag.is_none() || code.is_none() { return Err(CodecError::InvalidFrame) }
+
+        // The server must not mask the frames (RFC 6455 5.1)
+        if bytes[1] & 0b10000000 != 0 { return Err(CodecError::InvalidFrame) }
+
+        let payload_len = match peek_payload_len(bytes) {
+            Some(payload_len) => payload_len,
+            None => return Ok(None)
+        };
+        let header_len = unmasked_header_len(bytes);
+        if payload_len > MAX_PAYLOAD_LEN { return Err(CodecError::InvalidFrame) }
+        if payload_len > self.max_frame_size { return Err(CodecError::FrameTooBig { max: self.max_frame_size }) }
+
+        // bytes not received completelly due to buffers from the OS (the length is less than 2^63, the sum doesn't overflow)
+        if payload_len + header_len as u64 > bytes.len() as u64 { return Ok(None) }
+
+        let len = header_len + payload_len as usize;
+        Ok(Some(FrameRef { flag: flag.unwrap(), code: code.unwrap(), payload: &bytes[header_len..len], len }))
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        FrameDecoder::new()
+    }
+}
@@ -0,0 +1,57 @@
+use alloc::vec::Vec;
+use crate::ws_basic::header::{Header, header_len};
+use super::{CodecError, RandomSource, OPCODE, FLAG, MAX_HEADER_LEN, Mask, apply_mask, gen_mask};
+
+// Serializer of the frames sent by a client, each frame is masked with a new key of the random source
+pub struct FrameEncoder<R: RandomSource> {
+    random: R,
+    mask: bool                                               // The frames of a client are masked, a server doesn't mask them
+}
+
+impl<R: RandomSource> FrameEncoder<R> {
+    pub fn new(random: R) -> Self {
+        FrameEncoder { random, mask: true }
+    }
+
+    // Send the frames without mask, only for the frames of a server (RFC 6455 5.1)
+    pub fn set_mask(&mut self, mask: bool) {
+        self.mask = mask;
+    }
+
+    // Bytes of the frame with the payload length (header and payload)
+    pub fn frame_len(&self, payload_len: usize) -> usize {
+        header_len(payload_len as u64, self.mask) + payload_len
+    }
+
+    // Write the header of a frame in the array and return the bytes used and the mask to apply to the payload.
+    // For payloads that are masked in place by the caller (apply_mask) or written in several parts
+    pub fn encode_header(&mut self, flag: FLAG, opcode: OPCODE, payload_len: u64, head: &mut [u8; MAX_HEADER_LEN]) -> Result<(usize, Option<Mask>), CodecError> {
+        let mask = if self.mask { Some(gen_mask(&mut self.random)?) } else { None };
+        let header = Header::new(flag, opcode, mask, payload_len);
+        Ok((header.write_to(head), mask))
+    }
+
+    // Serialize the frame in the buffer without allocating and return the bytes written.
+    // Fails with BufferTooSmall if the frame doesn't fit (nothing is written)
+    pub fn encode_into(&mut self, flag: FLAG, opcode: OPCODE, payload: &[u8], buffer: &mut [u8]) -> Result<usize, CodecError> {
+        let needed = self.frame_len(payload.len());
+        if buffer.len() < needed { return Err(CodecError::BufferTooSmall { needed }) }
+
+        let mut head = [0u8; MAX_HEADER_LEN];
+        let (len, mask) = self.encode_header(flag, opcode, payload.len() as u64, &mut head)?;
+        buffer[..len].copy_from_slice(&head[..len]);
+        buffer[len..needed].copy_from_slice(payload);
+        if let Some(mask) = mask { apply_mask(&mut buffer[len..needed], mask, 0) }
+        Ok(needed)
+    }
+
+    // Serialize the frame at the end of the vector
+    pub fn encode(&mut self, flag: FLAG, opcode: OPCODE, payload: &[u8], out: &mut Vec<u8>) -> Result<(), CodecError> {
+        let start = out.len();
+        out.resize(start + self.frame_len(payload.len()), 0);
+        match self.encode_into(flag, opcode, payload, &mut out[start..]) {
+            Ok(_) => Ok(()),
+            Err(e) => { out.truncate(start); Err(e) }
+        }
+    }
+}
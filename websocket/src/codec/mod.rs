@@ -0,0 +1,44 @@
+// Frame codec of RFC 6455 that works on byte slices: it doesn't know about sockets, so it can be used with any I/O stack.
+// It builds without std (no_std + alloc) with default-features = false, the clients are built on top of it.
+// - FrameDecoder parses the frames received (by a client, not masked) in place, the payload borrows the bytes.
+// - FrameEncoder serializes the frames to send, the masking keys come from a RandomSource.
+// - SeededRandom is a deterministic RandomSource to compare the output of the clients byte by byte in the tests.
+mod decoder;
+mod encoder;
+mod random;
+#[cfg(test)]
+mod test;
+
+use core::fmt;
+
+pub use decoder::{FrameDecoder, FrameRef, FrameKind, DEFAULT_MAX_FRAME_SIZE, peek_payload_len, is_frame_complete};
+pub use encoder::FrameEncoder;
+pub use random::{RandomSource, SeededRandom};
+#[cfg(feature = "std")]
+pub use random::{OsRandom, SharedRandom, shared_random};
+pub use crate::ws_basic::header::{OPCODE, FLAG, MAX_HEADER_LEN, header_len};
+pub use crate::ws_basic::mask::{Mask, apply_mask, gen_mask};
+pub use crate::ws_basic::status_code::CloseCode;
+
+// Errors of the codec, the clients convert them to WebSocketError
+#[derive(Debug, PartialEq)]
+pub enum CodecError {
+    InvalidFrame,                                            // The header can't be parsed (unknown opcode or masked frame)
+    FrameTooBig { max: u64 },                                // The header announces a payload bigger than the max frame size of the decoder
+    BufferTooSmall { needed: usize },                        // The frame doesn't fit in the output buffer, nothing is written
+    Random,                                                  // The random source couldn't generate a masking key
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodecError::InvalidFrame => fmt.write_str("invalid frame"),
+            CodecError::FrameTooBig { max } => write!(fmt, "frame exceeds the maximum size allowed ({} bytes)", max),
+            CodecError::BufferTooSmall { needed } => write!(fmt, "the frame needs {} bytes in the output buffer", needed),
+            CodecError::Random => fmt.write_str("the random source failed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CodecError {}
@@ -0,0 +1,71 @@
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+use super::CodecError;
+
+// Source of the random bytes of the masking keys and the Sec-WebSocket-Key. On targets without an entropy source of the OS
+// implement it with the generator of the hardware, an error fails the frame (or the handshake) instead of sending it with a
+// predictable key
+pub trait RandomSource {
+    fn fill(&mut self, bytes: &mut [u8]) -> Result<(), CodecError>;
+}
+
+// Random bytes of the OS (getrandom)
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OsRandom;
+
+#[cfg(feature = "std")]
+impl RandomSource for OsRandom {
+    fn fill(&mut self, bytes: &mut [u8]) -> Result<(), CodecError> {
+        getrandom::getrandom(bytes).map_err(|_| CodecError::Random)
+    }
+}
+
+// Deterministic generator (splitmix64): the same seed gives the same bytes, so the frames and the handshake
+// can be compared byte by byte in the tests. The keys can be predicted, don't use it in a real connection
+#[derive(Clone, Debug)]
+pub struct SeededRandom {
+    state: u64
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        SeededRandom { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn fill(&mut self, bytes: &mut [u8]) -> Result<(), CodecError> {
+        for chunk in bytes.chunks_mut(8) {
+            let value = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&value[..chunk.len()]);
+        }
+        Ok(())
+    }
+}
+
+// Random source of a client, shared with Config and the halves of the split (Config is Clone and the client is Send)
+#[cfg(feature = "std")]
+pub type SharedRandom = Arc<Mutex<dyn RandomSource + Send>>;
+
+#[cfg(feature = "std")]
+impl RandomSource for SharedRandom {
+    fn fill(&mut self, bytes: &mut [u8]) -> Result<(), CodecError> {
+        let mut random = self.lock().map_err(|_| CodecError::Random)?;
+        random.fill(bytes)
+    }
+}
+
+// Share a random source to give it to Config
+#[cfg(feature = "std")]
+pub fn shared_random<R: RandomSource + Send + 'static>(random: R) -> SharedRandom {
+    Arc::new(Mutex::new(random))
+}
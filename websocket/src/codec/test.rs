@@ -0,0 +1,173 @@
+use alloc::vec::Vec;
+use super::*;
+
+// Masking key always the same, the frames can be compared byte by byte
+struct FixedRandom([u8; 4]);
+
+impl RandomSource for FixedRandom {
+    fn fill(&mut self, bytes: &mut [u8]) -> Result<(), CodecError> {
+        for (i, byte) in bytes.iter_mut().enumerate() { *byte = self.0[i % 4] }
+        Ok(())
+    }
+}
+
+struct FailingRandom;
+
+impl RandomSource for FailingRandom {
+    fn fill(&mut self, _bytes: &mut [u8]) -> Result<(), CodecError> {
+        Err(CodecError::Random)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------------- //
+//                                               decoder.rs
+// -------------------------------------------------------------------------------------------------------- //
+
+#[test]
+fn decoder_parses_the_frames_one_after_another() {
+    let bytes = [0x01, 0x02, b'h', b'i', 0x80, 0x01, b'!', 0x89];
+    let decoder = FrameDecoder::new();
+
+    let frame = decoder.decode(&bytes).unwrap().unwrap();
+    assert!(frame.get_opcode() == OPCODE::TEXT && !frame.get_flag().contains(FLAG::FIN));
+    assert_eq!(frame.payload(), b"hi");
+    let len = frame.frame_len();
+
+    let frame = decoder.decode(&bytes[len..]).unwrap().unwrap();
+    assert!(frame.get_opcode() == OPCODE::CONTINUATION && frame.kind() == FrameKind::Data);
+    assert_eq!(frame.payload(), b"!");
+
+    // Only the first byte of the next frame
+    assert!(decoder.decode(&bytes[len + frame.frame_len()..]).unwrap().is_none());
+}
+
+#[test]
+fn decoder_rejects_big_frames_from_the_header() {
+    let mut decoder = FrameDecoder::new();
+    decoder.set_max_frame_size(1024);
+
+    // Header of a frame of 4 GB, the payload didn't arrive
+    let header = [0x82, 0x7F, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00];
+    assert_eq!(decoder.decode(&header).err(), Some(CodecError::FrameTooBig { max: 1024 }));
+    assert_eq!(peek_payload_len(&header), Some(4294967296));
+    assert!(!is_frame_complete(&header));
+}
+
+#[test]
+fn decoder_skips_the_extended_payload_length() {
+    let bytes = [0x82, 0x7E, 0x00, 0x03, 0x01, 0x02, 0x03];
+    let decoder = FrameDecoder::new();
+
+    let frame = decoder.decode(&bytes).unwrap().unwrap();
+    assert_eq!(frame.payload(), &[0x01, 0x02, 0x03]);
+    assert_eq!(frame.frame_len(), 7);
+    assert!(is_frame_complete(&bytes));
+    assert!(decoder.decode(&bytes[..6]).unwrap().is_none());
+}
+
+#[test]
+fn decoder_rejects_lengths_with_the_most_significant_bit() {
+    // Length of 2^64 - 1, the sums with the header length can't overflow
+    let header = [0x82, 0x7F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+    let mut decoder = FrameDecoder::new();
+    assert_eq!(decoder.decode(&header).err(), Some(CodecError::InvalidFrame));
+    decoder.set_max_frame_size(u64::MAX);
+    assert_eq!(decoder.decode(&header).err(), Some(CodecError::InvalidFrame));
+    assert!(!is_frame_complete(&header));
+
+    // Biggest length allowed, the payload didn't arrive
+    let header = [0x82, 0x7F, 0x7F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+    assert!(decoder.decode(&header).unwrap().is_none());
+    assert!(!is_frame_complete(&header));
+}
+
+#[test]
+fn decoder_has_a_default_max_frame_size() {
+    let header = [0x82, 0x7F, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x01];
+    assert_eq!(FrameDecoder::new().decode(&header).err(), Some(CodecError::FrameTooBig { max: DEFAULT_MAX_FRAME_SIZE }));
+}
+
+#[test]
+fn decoder_rejects_masked_and_unknown_frames() {
+    let decoder = FrameDecoder::new();
+    assert_eq!(decoder.decode(&[0x81, 0x81, 0x01, 0x02, 0x03, 0x04, b'h']).err(), Some(CodecError::InvalidFrame));
+    assert_eq!(decoder.decode(&[0x84, 0x00]).err(), Some(CodecError::InvalidFrame));
+}
+
+// -------------------------------------------------------------------------------------------------------- //
+//                                               encoder.rs
+// -------------------------------------------------------------------------------------------------------- //
+
+#[test]
+fn encoder_masks_the_frames_with_the_random_source() {
+    let mut encoder = FrameEncoder::new(FixedRandom([0x01, 0x02, 0x03, 0x04]));
+    let mut buffer = [0u8; 16];
+
+    let len = encoder.encode_into(FLAG::FIN, OPCODE::TEXT, b"hello", &mut buffer).unwrap();
+    let expected = [0x81, 0x85, 0x01, 0x02, 0x03, 0x04, b'h' ^ 0x01, b'e' ^ 0x02, b'l' ^ 0x03, b'l' ^ 0x04, b'o' ^ 0x01];
+    assert_eq!(&buffer[..len], &expected);
+    assert_eq!(len, encoder.frame_len(5));
+}
+
+#[test]
+fn encoder_without_mask_and_into_a_vector() {
+    let mut encoder = FrameEncoder::new(FailingRandom);
+    encoder.set_mask(false);
+
+    let mut out = Vec::from(&b"prefix"[..]);
+    encoder.encode(FLAG::FIN, OPCODE::BINARY, &[0xAA; 200], &mut out).unwrap();
+    assert_eq!(&out[6..10], &[0x82, 0x7E, 0x00, 0xC8]);
+    assert_eq!(out.len(), 6 + 4 + 200);
+}
+
+#[test]
+fn encoder_uses_the_minimal_length_encoding() {
+    let mut encoder = FrameEncoder::new(FailingRandom);
+    encoder.set_mask(false);
+
+    // 125 bytes fit in the 7 bits of the length, 126 need the 16 bits extended length
+    let mut out = Vec::new();
+    encoder.encode(FLAG::FIN, OPCODE::BINARY, &[0xAA; 125], &mut out).unwrap();
+    let mut expected = vec![0x82, 0x7D];
+    expected.extend_from_slice(&[0xAA; 125]);
+    assert_eq!(out, expected);
+    assert_eq!(encoder.frame_len(125), 127);
+
+    let mut out = Vec::new();
+    encoder.encode(FLAG::FIN, OPCODE::BINARY, &[0xAA; 126], &mut out).unwrap();
+    assert_eq!(&out[..4], &[0x82, 0x7E, 0x00, 0x7E]);
+    assert_eq!(out.len(), 4 + 126);
+}
+
+#[test]
+fn encoder_errors_write_nothing() {
+    let mut encoder = FrameEncoder::new(FixedRandom([0; 4]));
+    let mut buffer = [0xFFu8; 8];
+    assert_eq!(encoder.encode_into(FLAG::FIN, OPCODE::TEXT, b"hello", &mut buffer), Err(CodecError::BufferTooSmall { needed: 11 }));
+    assert_eq!(buffer, [0xFF; 8]);
+
+    // The frame is not sent with a predictable mask
+    let mut encoder = FrameEncoder::new(FailingRandom);
+    let mut out = Vec::new();
+    assert_eq!(encoder.encode(FLAG::FIN, OPCODE::TEXT, b"hello", &mut out), Err(CodecError::Random));
+    assert!(out.is_empty());
+}
+
+// -------------------------------------------------------------------------------------------------------- //
+//                                               random.rs
+// -------------------------------------------------------------------------------------------------------- //
+
+#[test]
+fn seeded_random_repeats_the_bytes_of_the_seed() {
+    let mut a = SeededRandom::new(7);
+    let mut b = SeededRandom::new(7);
+    let (mut bytes_a, mut bytes_b) = ([0u8; 11], [0u8; 11]);
+    a.fill(&mut bytes_a).unwrap();
+    b.fill(&mut bytes_b).unwrap();
+    assert_eq!(bytes_a, bytes_b);
+    assert_eq!(gen_mask(&mut a), gen_mask(&mut b));
+
+    let mut other = [0u8; 11];
+    SeededRandom::new(8).fill(&mut other).unwrap();
+    assert_ne!(bytes_a, other);
+}
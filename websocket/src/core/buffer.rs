@@ -0,0 +1,160 @@
+use std::io::Read;
+use crate::result::WebSocketResult;
+use super::net::read_into_buffer;
+
+/// Buffer for the bytes received from the socket.
+/// - The socket writes directly in the free space after the data, there's no intermediate buffer.
+/// - The frames are parsed in place, ``consume`` only moves the start of the data.
+/// - The data left is moved to the beginning only when there's no room for the next read, so each byte is moved at most once
+///   per read and big messages don't shift the whole buffer for every frame.
+pub struct RecvBuffer {
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
+    fixed: bool                                              // The buffer never grows, the reads are limited to the free space
+}
+
+impl RecvBuffer {
+    pub fn new() -> Self {
+        RecvBuffer { buf: Vec::new(), start: 0, end: 0, fixed: false }
+    }
+
+    /// Buffer of ``capacity`` bytes allocated once, it never grows
+    pub fn fixed(capacity: usize) -> Self {
+        RecvBuffer { buf: vec![0; capacity], start: 0, end: 0, fixed: true }
+    }
+
+    /// Bytes received and not consumed
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[self.start..self.end]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Drop the first ``amount`` bytes (the bytes of a frame already parsed)
+    pub fn consume(&mut self, amount: usize) {
+        self.start = (self.start + amount).min(self.end);
+        // Empty, the next read starts at the beginning without moving anything
+        if self.start == self.end { self.start = 0; self.end = 0; }
+    }
+
+    /// Drop all the bytes, the memory is kept for the next reads
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.end = 0;
+    }
+
+    /// Make room for at least ``additional`` bytes after the data
+    pub fn reserve(&mut self, additional: usize) {
+        if self.buf.len() - self.end >= additional { return }
+
+        // Reuse the space of the bytes consumed before growing
+        if self.start > 0 {
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+
+        if self.buf.len() - self.end < additional && !self.fixed {
+            let len = (self.end + additional).max(self.buf.len() * 2);
+            self.buf.resize(len, 0);
+        }
+    }
+
+    /// Free space of ``size`` bytes after the data, to read into it. Call ``advance`` with the bytes written.
+    /// A fixed buffer returns less bytes if there's no room (empty if it's full)
+    pub fn chunk_mut(&mut self, size: usize) -> &mut [u8] {
+        self.reserve(size);
+        let end = (self.end + size).min(self.buf.len());
+        &mut self.buf[self.end..end]
+    }
+
+    /// Add to the data the bytes written in the last ``chunk_mut``
+    pub fn advance(&mut self, amount: usize) {
+        self.end = (self.end + amount).min(self.buf.len());
+    }
+
+    /// Read up to ``chunk`` bytes from the reader, same results as ``read_into_buffer``
+    pub fn read_from(&mut self, reader: &mut dyn Read, chunk: usize) -> WebSocketResult<usize> {
+        let buf = self.chunk_mut(chunk);
+        // Fixed buffer full, the data must be consumed before reading more
+        if buf.is_empty() { return Ok(0) }
+        let amount = read_into_buffer(reader, buf)?;
+        self.advance(amount);
+        Ok(amount)
+    }
+}
+
+impl Default for RecvBuffer {
+    fn default() -> Self {
+        RecvBuffer::new()
+    }
+}
+
+/// Buffer for the frames to send with a fixed capacity, allocated once.
+/// - The frames are serialized (and masked) in the buffer when they are queued and written from it.
+/// - ``append`` fails instead of growing, the space is reused once all the bytes are written.
+pub struct SendBuffer {
+    buf: Vec<u8>,
+    sent: usize
+}
+
+impl SendBuffer {
+    pub fn with_capacity(capacity: usize) -> Self {
+        SendBuffer { buf: Vec::with_capacity(capacity), sent: 0 }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Bytes that can be appended
+    pub fn free(&self) -> usize {
+        self.buf.capacity() - (self.buf.len() - self.sent)
+    }
+
+    /// Bytes not written yet
+    pub fn pending(&self) -> &[u8] {
+        &self.buf[self.sent..]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sent == self.buf.len()
+    }
+
+    /// Part of the bytes were written, the rest must be written before anything else
+    pub fn is_writing(&self) -> bool {
+        self.sent > 0
+    }
+
+    /// Mark the first ``amount`` bytes pending as written
+    pub fn advance(&mut self, amount: usize) {
+        self.sent = (self.sent + amount).min(self.buf.len());
+        if self.is_empty() { self.clear() }
+    }
+
+    /// Append the bytes and return them to modify them in place (mask the payload).
+    /// None if they don't fit, nothing is appended
+    pub fn append(&mut self, bytes: &[u8]) -> Option<&mut [u8]> {
+        if bytes.len() > self.free() { return None }
+
+        // Reuse the space of the bytes written
+        if self.buf.len() + bytes.len() > self.buf.capacity() {
+            self.buf.copy_within(self.sent.., 0);
+            self.buf.truncate(self.buf.len() - self.sent);
+            self.sent = 0;
+        }
+
+        let start = self.buf.len();
+        self.buf.extend_from_slice(bytes);
+        Some(&mut self.buf[start..])
+    }
+
+    /// Drop the bytes not written, the memory is kept
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.sent = 0;
+    }
+}
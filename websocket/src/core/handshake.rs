@@ -0,0 +1,120 @@
+// Opening handshake shared by the sync and async clients (RFC 6455 4.1)
+use std::collections::HashMap;
+use crate::result::{WebSocketError, WebSocketResult, HandShakeError};
+use crate::http::request::{Request, Method};
+use crate::http::response::Response;
+use crate::ws_basic::key::{gen_key, verify_key};
+use crate::extension::{Extension, WebSocketExtension};
+use crate::codec::RandomSource;
+use crate::http::extension::{parse_extensions, extensions_to_header};
+
+const SWITCHING_PROTOCOLS: u16 = 101;
+
+// Returns the handshake request and the Sec-WebSocket-Key sent, used to verify the response.
+// Fails if the random source can't generate the key
+pub fn handshake_request<R: RandomSource + ?Sized>(path: &str, protocols: Option<&[&str]>, extensions: &[Box<dyn WebSocketExtension>], random: &mut R) -> WebSocketResult<(Request, String)> {
+    let sec_websocket_key = gen_key(random)?;
+
+    let mut headers: HashMap<String, String> = HashMap::from([
+        (String::from("Upgrade"), String::from("websocket")),
+        (String::from("Connection"), String::from("Upgrade")),
+        (String::from("Sec-WebSocket-Key"), sec_websocket_key.clone()),
+        (String::from("Sec-WebSocket-Version"), String::from("13")),
+        (String::from("User-agent"), String::from("rust-websocket-std")),
+    ]);
+
+    // Add protocols to request
+    let mut protocols_value = String::new();
+    if let Some(protocols) = protocols {
+        for p in protocols {
+            protocols_value.push_str(p);
+            protocols_value.push_str(", ");
+        }
+        headers.insert(String::from("Sec-WebSocket-Protocol"), protocols_value[0..protocols_value.len()-2].to_string());
+    }
+
+    // Add extensions to request
+    let extensions: Vec<Extension> = extensions.iter().map(|e| e.offer()).collect();
+    if !extensions.is_empty() {
+        headers.insert(String::from("Sec-WebSocket-Extensions"), extensions_to_header(&extensions));
+    }
+
+    Ok((Request::new(Method::GET, path, "HTTP/1.1", Some(headers)), sec_websocket_key))
+}
+
+// Check the status code and the Sec-WebSocket-Accept of the response
+pub fn verify_response(response: &Response, sec_websocket_key: &str) -> WebSocketResult<()> {
+    if response.get_status_code() != SWITCHING_PROTOCOLS {
+        return Err(WebSocketError::HandShake(HandShakeError::StatusCode(response.get_status_code())))
+    }
+
+    let sec_websocket_accept = response.header("Sec-WebSocket-Accept");
+    if sec_websocket_accept.is_none() { return Err(WebSocketError::HandShake(HandShakeError::MissingAcceptKey)) }
+
+    if !verify_key(sec_websocket_key, &sec_websocket_accept.unwrap()) {
+        return Err(WebSocketError::HandShake(HandShakeError::InvalidAcceptKey));
+    }
+
+    Ok(())
+}
+
+// Return the protocol selected by the server, it must be one of the protocols offered by the client
+pub fn negotiate_protocol(response: &Response, offered: Option<&[&str]>, require_protocol: bool) -> WebSocketResult<Option<String>> {
+    let protocol = response.header("Sec-WebSocket-Protocol");
+
+    if protocol.is_none() {
+        if require_protocol { return Err(WebSocketError::HandShake(HandShakeError::ProtocolRequired)) }
+        return Ok(None);
+    }
+
+    let protocol = protocol.unwrap();
+    if !offered.unwrap_or(&[]).contains(&protocol.as_str()) {
+        return Err(WebSocketError::HandShake(HandShakeError::ProtocolNotOffered(protocol)));
+    }
+
+    Ok(Some(protocol))
+}
+
+// Extensions accepted by the server
+pub struct Negotiated {
+    pub handlers: Vec<Box<dyn WebSocketExtension>>,              // In the order of the response
+    pub extensions: Vec<Extension>,
+    pub rsv_bits: u8                                             // Reserved bits claimed by the handlers
+}
+
+// Accept the extensions of the handshake response, the server can only accept extensions offered by the client.
+// The handlers are new copies of the offers accepted, the offers are kept for the next handshake
+pub fn negotiate_extensions(response: &Response, offers: &[Box<dyn WebSocketExtension>]) -> WebSocketResult<Negotiated> {
+    let mut negotiated = Negotiated { handlers: Vec::new(), extensions: Vec::new(), rsv_bits: 0 };
+
+    let value = response.header("Sec-WebSocket-Extensions");
+    if value.is_none() { return Ok(negotiated) }
+
+    let extensions = parse_extensions(value.unwrap().as_str());
+    if extensions.is_err() {
+        return Err(WebSocketError::HandShake(HandShakeError::InvalidHeader(String::from("Sec-WebSocket-Extensions"))));
+    }
+
+    for extension in extensions.unwrap() {
+        let offer = offers.iter().find(|e| e.name() == extension.name());
+        if offer.is_none() { return Err(WebSocketError::HandShake(HandShakeError::ExtensionNotOffered(extension.name().to_string()))) }
+
+        // The server can't accept the same extension twice
+        if negotiated.extensions.iter().any(|e| e.name() == extension.name()) {
+            return Err(WebSocketError::HandShake(HandShakeError::ExtensionRejected(extension.name().to_string())));
+        }
+
+        let mut handler = offer.unwrap().box_clone();
+        handler.accept(&extension)?;
+
+        // Two extensions can't use the same reserved bit
+        let rsv_bits = handler.rsv_bits();
+        if negotiated.rsv_bits & rsv_bits != 0 { return Err(WebSocketError::HandShake(HandShakeError::ExtensionRejected(extension.name().to_string()))) }
+
+        negotiated.rsv_bits |= rsv_bits;
+        negotiated.handlers.push(handler);
+        negotiated.extensions.push(extension);
+    }
+
+    Ok(negotiated)
+}
@@ -1,5 +1,8 @@
 pub mod traits;
 pub mod net;
-pub mod binary;
+pub mod buffer;
+pub mod poll;
+pub mod handshake;
+pub mod recv;
 #[cfg(test)]
 mod test;
\ No newline at end of file
@@ -1,11 +1,14 @@
-use std::io::{Read, ErrorKind};
+use std::io::{self, Read, Write, ErrorKind, IoSlice};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
 use crate::result::{WebSocketResult, WebSocketError};
+use super::poll::wait_writable;
 
 /// Copy bytes from the reader into the buffer and return amount of data read.
-/// - If an EOF is reached the function will return a ``WebSocketError::Custom``because no more bytes can be read.
+/// - If an EOF is reached the function will return a ``WebSocketError::ConnectionClose`` because no more bytes can be read.
 /// - If there's no bytes ready to read from the reader the function will return ``Ok(0)`` bytes readed and the buffer will not be modified.
 /// - If there's bytes the function will return ``Ok(n)`` where 0 < n <= buf.len()
-/// - Otherwise a ``WebSocketError::IOError`` will be return.
+/// - Otherwise a ``WebSocketError::IOError`` with the error of the reader will be return.
 pub fn read_into_buffer<'a>(reader: &mut dyn Read, buf: &mut [u8]) -> WebSocketResult<usize> {
     match reader.read(buf) {
         Ok(amount) => {
@@ -18,8 +21,48 @@ pub fn read_into_buffer<'a>(reader: &mut dyn Read, buf: &mut [u8]) -> WebSocketR
         },
         Err(e) => {
             if e.kind() == ErrorKind::WouldBlock { return Ok(0) }
-            return Err(WebSocketError::IOError)
-            // return Err(WebSocketError::IOError(e.to_string().as_str())); // grcov-excl-line
+            return Err(WebSocketError::IOError(e))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Write all the bytes in a non blocking socket, waiting until the socket accepts more data.
+/// - Returns ``WebSocketError::Timeout`` if the socket doesn't accept the bytes before the timeout expires, part of the bytes could be written.
+/// - Otherwise a ``WebSocketError::IOError`` with the error of the socket will be return.
+pub fn write_all_wait(stream: &mut TcpStream, bytes: &[u8], timeout: Duration) -> WebSocketResult<()> {
+    let deadline = Instant::now() + timeout;
+    let mut written = 0;
+
+    while written < bytes.len() {
+        match stream.write(&bytes[written..]) {
+            Ok(0) => return Err(WebSocketError::ConnectionClose),
+            Ok(amount) => written += amount,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if !wait_writable(stream, remaining)? { return Err(WebSocketError::Timeout) }
+            },
+            Err(e) if e.kind() == ErrorKind::Interrupted => {},
+            Err(e) => return Err(WebSocketError::IOError(e))
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the two buffers (header and payload) with a single ``write_vectored``, starting ``written`` bytes after the beginning of the first one.
+/// - Returns the bytes written in this call, the buffers are not modified and nothing is allocated.
+/// - The errors of the writer are returned as they are (``WouldBlock`` included).
+pub fn write_vectored_from(writer: &mut dyn Write, parts: [&[u8]; 2], written: usize) -> io::Result<usize> {
+    let mut skip = written;
+    let mut slices = [IoSlice::new(&[]); 2];
+    let mut count = 0;
+
+    for part in parts {
+        if skip >= part.len() { skip -= part.len(); continue }
+        slices[count] = IoSlice::new(&part[skip..]);
+        count += 1;
+        skip = 0;
+    }
+
+    writer.write_vectored(&slices[..count])
+}
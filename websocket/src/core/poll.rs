@@ -0,0 +1,168 @@
+use std::io;
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Wait until the socket is ready to read (or to write if ``write`` is true) or the timeout expires.
+/// - Returns ``Ok(true)`` if the socket is ready, also if the connection was closed or has an error (the next read or write will report it).
+/// - Returns ``Ok(false)`` if the timeout expired.
+#[cfg(unix)]
+pub fn wait_socket(stream: &TcpStream, write: bool, timeout: Duration) -> io::Result<bool> {
+    let mut events = libc::POLLIN;
+    if write { events |= libc::POLLOUT }
+    poll_socket(stream, events, timeout)
+}
+
+/// Wait until the socket is ready to write or the timeout expires, returns ``Ok(false)`` if the timeout expired.
+#[cfg(unix)]
+pub fn wait_writable(stream: &TcpStream, timeout: Duration) -> io::Result<bool> {
+    poll_socket(stream, libc::POLLOUT, timeout)
+}
+
+/// Same as ``wait_socket``, but it also returns ``Ok(true)`` as soon as ``wakeup.wake()`` is called from another thread.
+#[cfg(unix)]
+pub fn wait_socket_or_wakeup(stream: &TcpStream, write: bool, timeout: Duration, wakeup: &Wakeup) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut events = libc::POLLIN;
+    if write { events |= libc::POLLOUT }
+
+    let mut fds = [
+        libc::pollfd { fd: stream.as_raw_fd(), events, revents: 0 },
+        libc::pollfd { fd: wakeup.read, events: libc::POLLIN, revents: 0 }
+    ];
+    let ready = poll_fds(&mut fds, timeout)?;
+    if fds[1].revents != 0 { wakeup.clear() }
+    Ok(ready)
+}
+
+/// Poll the socket for the ``events`` (POLLIN, POLLOUT) until one is ready or the timeout expires, interrupted polls are repeated
+#[cfg(unix)]
+fn poll_socket(stream: &TcpStream, events: libc::c_short, timeout: Duration) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    poll_fds(&mut [libc::pollfd { fd: stream.as_raw_fd(), events, revents: 0 }], timeout)
+}
+
+#[cfg(unix)]
+fn poll_fds(fds: &mut [libc::pollfd], timeout: Duration) -> io::Result<bool> {
+    // Round up to not return before the timeout expires
+    let millis = ((timeout.as_nanos() + 999_999) / 1_000_000).min(i32::MAX as u128) as i32;
+
+    loop {
+        let res = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, millis) };
+        if res >= 0 { return Ok(res > 0) }
+
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted { return Err(error) }
+    }
+}
+
+/// Wakes up the thread waiting in ``wait_socket_or_wakeup`` from other threads.
+/// On unix it's a pipe (self-pipe): ``wake`` writes a byte and the poll of the socket also watches the read end.
+#[cfg(unix)]
+pub struct Wakeup {
+    read: libc::c_int,
+    write: libc::c_int
+}
+
+#[cfg(unix)]
+impl Wakeup {
+    pub fn new() -> io::Result<Self> {
+        let mut fds = [0 as libc::c_int; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 { return Err(io::Error::last_os_error()) }
+        let wakeup = Wakeup { read: fds[0], write: fds[1] };
+
+        // Both ends non blocking, a full pipe already wakes up the poll
+        for fd in fds {
+            let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+            if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 { return Err(io::Error::last_os_error()) }
+            if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 { return Err(io::Error::last_os_error()) }
+        }
+
+        Ok(wakeup)
+    }
+
+    pub fn wake(&self) {
+        let byte = 1u8;
+        unsafe { libc::write(self.write, &byte as *const u8 as *const libc::c_void, 1) };
+    }
+
+    // Empty the pipe, the next poll waits again
+    fn clear(&self) {
+        let mut buf = [0u8; 64];
+        while unsafe { libc::read(self.read, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {}
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Wakeup {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+/// Without poll there's no way to know when the socket is writable, wait 1ms and try again
+#[cfg(not(unix))]
+pub fn wait_writable(_stream: &TcpStream, timeout: Duration) -> io::Result<bool> {
+    if timeout.is_zero() { return Ok(false) }
+    std::thread::sleep(timeout.min(Duration::from_millis(1)));
+    Ok(true)
+}
+
+/// Wait until the socket is ready to read or the timeout expires.
+/// Without poll there's no way to wait until the socket is writable, so if ``write`` is true it waits 1ms at most.
+#[cfg(not(unix))]
+pub fn wait_socket(stream: &TcpStream, write: bool, timeout: Duration) -> io::Result<bool> {
+    let timeout = if write { timeout.min(Duration::from_millis(1)) } else { timeout };
+    if timeout.is_zero() { return Ok(false) }
+
+    // Peek in blocking mode waits until there's data, the connection is closed or the timeout expires
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(timeout))?;
+    let res = stream.peek(&mut [0u8; 1]);
+    stream.set_read_timeout(None)?;
+    stream.set_nonblocking(true)?;
+
+    match res {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(write),
+        Err(_) => Ok(true)
+    }
+}
+
+/// Wakes up the thread waiting in ``wait_socket_or_wakeup`` from other threads.
+/// Without poll the wait can't watch the socket and the wakeup at the same time, the flag is checked every 10ms.
+#[cfg(not(unix))]
+pub struct Wakeup {
+    woken: std::sync::atomic::AtomicBool
+}
+
+#[cfg(not(unix))]
+impl Wakeup {
+    pub fn new() -> io::Result<Self> {
+        Ok(Wakeup { woken: std::sync::atomic::AtomicBool::new(false) })
+    }
+
+    pub fn wake(&self) {
+        self.woken.store(true, std::sync::atomic::Ordering::Release);
+    }
+
+    fn take(&self) -> bool {
+        self.woken.swap(false, std::sync::atomic::Ordering::AcqRel)
+    }
+}
+
+/// Same as ``wait_socket``, but it also returns ``Ok(true)`` (10ms later at most) when ``wakeup.wake()`` is called from another thread.
+#[cfg(not(unix))]
+pub fn wait_socket_or_wakeup(stream: &TcpStream, write: bool, timeout: Duration, wakeup: &Wakeup) -> io::Result<bool> {
+    let start = std::time::Instant::now();
+    loop {
+        if wakeup.take() { return Ok(true) }
+        let remaining = timeout.saturating_sub(start.elapsed());
+        if remaining.is_zero() { return Ok(false) }
+        if wait_socket(stream, write, remaining.min(Duration::from_millis(10)))? { return Ok(true) }
+    }
+}
@@ -0,0 +1,68 @@
+// Defaults and checks of the frames received shared by the sync, split and async clients
+use std::time::Duration;
+use crate::result::{WebSocketResult, ProtocolError, CapacityError};
+use crate::extension::{ExtensionFrame, WebSocketExtension};
+use crate::sync::client::Message;
+use crate::ws_basic::header::{OPCODE, FLAG};
+use crate::ws_basic::frame::peek_payload_len;
+
+pub const DEFAULT_MESSAGE_SIZE: u64 = 1024;
+pub const DEFAULT_MAX_FRAME_SIZE: u64 = 16 * 1024 * 1024;
+pub const DEFAULT_MAX_MESSAGE_SIZE: u64 = 64 * 1024 * 1024;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_READ_CHUNK_SIZE: usize = 4 * 1024;
+
+// Check the limits with the header of the next frame, before buffering its payload.
+// ``message_len`` is the length of the message being received, the frame only counts in it if ``buffered`` is true
+// (the client doesn't buffer the fragments it streams). Nothing is checked until the header is complete
+pub fn check_recv_limits(bytes: &[u8], message_len: usize, buffered: bool, max_frame_size: u64, max_message_size: u64) -> WebSocketResult<()> {
+    let payload_len = match peek_payload_len(bytes) {
+        Some(payload_len) => payload_len,
+        None => return Ok(())
+    };
+
+    if payload_len > max_frame_size {
+        return Err(CapacityError::FrameTooBig { max: max_frame_size }.into());
+    }
+
+    let is_data = bytes[0] & 0b00001000 == 0;
+    if is_data && buffered && (message_len as u64).saturating_add(payload_len) > max_message_size {
+        return Err(CapacityError::MessageTooBig { max: max_message_size }.into());
+    }
+
+    Ok(())
+}
+
+// Reserved bits can only be set in the first frame of a data message if a negotiated extension claimed them
+pub fn rsv_allowed(flag: FLAG, opcode: &OPCODE, rsv_bits: u8) -> bool {
+    let rsv = flag.bits() & (FLAG::RSV1 | FLAG::RSV2 | FLAG::RSV3).bits();
+    if rsv == 0 { return true }
+
+    let first_frame = *opcode == OPCODE::TEXT || *opcode == OPCODE::BINARY;
+    first_frame && rsv & rsv_bits == rsv
+}
+
+// A data frame starts a message (TEXT, BINARY) if no message is being received and continues it (CONTINUATION) otherwise.
+// ``in_message`` is true from a data frame without FIN until the frame with FIN, the connection fails with 1002 if this is false
+pub fn fragment_allowed(opcode: &OPCODE, in_message: bool) -> bool {
+    (*opcode == OPCODE::CONTINUATION) == in_message
+}
+
+// Transform the message received with the negotiated extensions (reverse order),
+// the opcode and the reserved bits are the ones of the first frame of the message
+pub fn decode_message(extensions: &mut [Box<dyn WebSocketExtension>], opcode: u8, rsv: u8, data: Vec<u8>, max_message_size: u64) -> WebSocketResult<Vec<u8>> {
+    let mut frame = ExtensionFrame::new(opcode, rsv, data);
+    for extension in extensions.iter_mut().rev() {
+        extension.decode(&mut frame, max_message_size)?;
+    }
+    Ok(frame.payload)
+}
+
+// Message with the payload received, the opcode is the one of the first frame. Only the text messages must be UTF-8
+pub fn into_message(opcode: &OPCODE, data: Vec<u8>) -> Result<Message, ProtocolError> {
+    if *opcode == OPCODE::BINARY { return Ok(Message::BINARY(data)) }
+
+    let text = String::from_utf8(data);
+    if text.is_err() { return Err(ProtocolError::InvalidUTF8) }
+    Ok(Message::TEXT(text.unwrap()))
+}
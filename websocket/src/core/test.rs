@@ -1,9 +1,12 @@
-use super::net::read_into_buffer;
+use super::net::{read_into_buffer, write_vectored_from};
 use std::io::Write;
 use std::net::{TcpStream, TcpListener, Shutdown};
 use std::time::Duration;
 use std::thread::sleep;
 use crate::result::WebSocketError;
+use crate::codec::SeededRandom;
+use crate::core::traits::Serialize;
+use super::handshake::handshake_request;
 
 // -------------------------------------------------------------------------------------------------------- //
 //                                               net.rs
@@ -135,39 +138,126 @@ fn read_more_than_buffer_capacity () {
     before_each(server, client);
 }
 
+#[test]
+fn write_vectored_from_skips_the_bytes_written() {
+    let parts: [&[u8]; 2] = [b"head", b"payload"];
+    let mut out = Vec::new();
+    let mut written = 0;
+
+    // Start in the middle of the first buffer, then in the second one
+    for start in [2, 6] {
+        out.clear();
+        written = start;
+        while written < 11 { written += write_vectored_from(&mut out, parts, written).unwrap() }
+        assert_eq!(out, &b"headpayload"[start..]);
+    }
+
+    assert_eq!(write_vectored_from(&mut out, parts, written).unwrap(), 0);
+}
+
 // -------------------------------------------------------------------------------------------------------- //
-//                                               binary.rs
+//                                               buffer.rs
 // -------------------------------------------------------------------------------------------------------- //
-use super::binary::*;
+use super::buffer::{RecvBuffer, SendBuffer};
 
 #[test]
-fn valid_bytes_to_u16 () {
-    let bytes = [67,218];
-    let res = bytes_to_u16(bytes.as_slice());
-    assert!(res.is_ok());
-    let value: u16 = (256u16.pow(1) * 67) + (256u16.pow(0) * 218);
-    assert_eq!(res.unwrap(), value);
+fn recv_buffer_reads_in_the_free_space() {
+    let (mut server, mut client) = setup();
+    let mut buffer = RecvBuffer::new();
+
+    server.write_all(b"Hello world").unwrap();
+    sleep(Duration::from_millis(50));
+
+    assert_eq!(buffer.read_from(&mut client, 5).unwrap(), 5);
+    assert_eq!(buffer.read_from(&mut client, 1024).unwrap(), 6);
+    assert_eq!(buffer.as_slice(), b"Hello world");
+    assert_eq!(buffer.read_from(&mut client, 1024).unwrap(), 0);
+
+    before_each(server, client);
 }
 
 #[test]
-fn invalid_bytes_to_u16 () {
-    let bytes = [8,0,1];
-    let res = bytes_to_u16(bytes.as_slice());
-    assert!(res.is_err());
+fn recv_buffer_consume_keeps_the_rest() {
+    let mut buffer = RecvBuffer::new();
+    buffer.chunk_mut(6).copy_from_slice(b"abcdef");
+    buffer.advance(6);
+
+    buffer.consume(2);
+    assert_eq!(buffer.as_slice(), b"cdef");
+
+    // The bytes left are moved to the beginning to make room, the data doesn't change
+    let capacity = buffer.chunk_mut(0).len();
+    buffer.chunk_mut(capacity + 4)[..2].copy_from_slice(b"gh");
+    buffer.advance(2);
+    assert_eq!(buffer.as_slice(), b"cdefgh");
+
+    buffer.consume(100);
+    assert!(buffer.is_empty());
 }
 
 #[test]
-fn valid_bytes_to_u64 () {
-    let bytes = [1,0,0,0,0,0,5,255];
-    let res = bytes_to_u64(bytes.as_slice());
-    assert!(res.is_ok());
-    let value: u64 = (256u64.pow(7) * 1) + (256u64.pow(1) * 5) + (256u64.pow(0) * 255);
-    assert_eq!(res.unwrap(), value);
+fn fixed_recv_buffer_does_not_grow() {
+    let mut buffer = RecvBuffer::fixed(8);
+    assert_eq!(buffer.chunk_mut(1024).len(), 8);
+    buffer.chunk_mut(6).copy_from_slice(b"abcdef");
+    buffer.advance(6);
+    buffer.consume(4);
+
+    // The data is moved to the beginning, the free space is what's left of the 8 bytes
+    assert_eq!(buffer.chunk_mut(1024).len(), 6);
+    buffer.advance(6);
+    assert!(buffer.chunk_mut(1024).is_empty());
 }
 
 #[test]
-fn invalid_bytes_to_u64 () {
-    let bytes = [8,0,1];
-    let res = bytes_to_u64(bytes.as_slice());
-    assert!(res.is_err());
-}
\ No newline at end of file
+fn send_buffer_reuses_the_space_written() {
+    let mut buffer = SendBuffer::with_capacity(8);
+    buffer.append(b"abcdef").unwrap().copy_from_slice(b"ABCDEF");
+    assert_eq!(buffer.pending(), b"ABCDEF");
+    assert!(buffer.append(b"ghi").is_none());
+
+    buffer.advance(4);
+    assert!(buffer.is_writing());
+    assert_eq!(buffer.free(), 6);
+    buffer.append(b"ghijkl").unwrap();
+    assert_eq!(buffer.pending(), b"EFghijkl");
+    assert_eq!(buffer.capacity(), 8);
+
+    buffer.advance(8);
+    assert!(buffer.is_empty() && !buffer.is_writing());
+    assert_eq!(buffer.free(), 8);
+}
+
+// -------------------------------------------------------------------------------------------------------- //
+//                                               handshake.rs
+// -------------------------------------------------------------------------------------------------------- //
+
+#[test]
+fn handshake_request_is_byte_exact_with_a_seeded_random() {
+    let (request, key) = handshake_request("/chat", Some(&["chat"]), &[], &mut SeededRandom::new(7)).unwrap();
+
+    let expected = "GET /chat HTTP/1.1\r\n\
+                    Connection: Upgrade\r\n\
+                    Sec-WebSocket-Key: 1w0yWeThy2McZjz01zxMBA==\r\n\
+                    Sec-WebSocket-Protocol: chat\r\n\
+                    Sec-WebSocket-Version: 13\r\n\
+                    Upgrade: websocket\r\n\
+                    User-agent: rust-websocket-std\r\n\r\n";
+    assert_eq!(key, "1w0yWeThy2McZjz01zxMBA==");
+    assert_eq!(String::from_utf8(request.serialize()).unwrap(), expected);
+}
+
+// -------------------------------------------------------------------------------------------------------- //
+//                                               recv.rs
+// -------------------------------------------------------------------------------------------------------- //
+
+#[test]
+fn fragments_must_follow_the_message_being_received() {
+    use super::recv::fragment_allowed;
+    use crate::ws_basic::header::OPCODE;
+
+    assert!(fragment_allowed(&OPCODE::TEXT, false) && fragment_allowed(&OPCODE::BINARY, false));
+    assert!(fragment_allowed(&OPCODE::CONTINUATION, true));
+    assert!(!fragment_allowed(&OPCODE::CONTINUATION, false));
+    assert!(!fragment_allowed(&OPCODE::TEXT, true) && !fragment_allowed(&OPCODE::BINARY, true));
+}
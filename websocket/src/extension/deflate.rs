@@ -0,0 +1,221 @@
+// permessage-deflate extension (RFC 7692)
+//
+// The whole message is compressed with raw deflate and the RSV1 bit is set in the first frame of the message.
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::io;
+use crate::result::{WebSocketError, WebSocketResult, HandShakeError, ProtocolError, CapacityError};
+use super::{Extension, Parameter, ExtensionFrame, WebSocketExtension, RSV1, OPCODE_TEXT, OPCODE_BINARY};
+
+pub const PERMESSAGE_DEFLATE: &str = "permessage-deflate";
+
+const SERVER_NO_CONTEXT_TAKEOVER: &str = "server_no_context_takeover";
+const CLIENT_NO_CONTEXT_TAKEOVER: &str = "client_no_context_takeover";
+const SERVER_MAX_WINDOW_BITS: &str = "server_max_window_bits";
+const CLIENT_MAX_WINDOW_BITS: &str = "client_max_window_bits";
+
+// Bytes removed at the end of each compressed message and appended before decompress (RFC 7692 7.2.1)
+const TRAILER: [u8; 4] = [0x00, 0x00, 0xFF, 0xFF];
+const MIN_WINDOW_BITS: u8 = 8;
+const MAX_WINDOW_BITS: u8 = 15;
+
+// Parameters to offer to the server
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeflateConfig {
+    // Compression level (0-9)
+    pub level: u32,
+    // Ask the server to reset its compression context after each message
+    pub server_no_context_takeover: bool,
+    // Tell the server that the client will reset its compression context after each message
+    pub client_no_context_takeover: bool,
+    // Ask the server to use a LZ77 window of at most 2ˆbits bytes (8-15)
+    pub server_max_window_bits: Option<u8>,
+    // client_max_window_bits is never offered: the compressor only works with a window of 2ˆ15 bytes,
+    // so the server can't ask for a smaller one (RFC 7692 7.1.2.2)
+}
+
+impl Default for DeflateConfig {
+    fn default() -> Self {
+        DeflateConfig {
+            level: Compression::default().level(),
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
+            server_max_window_bits: None
+        }
+    }
+}
+
+// Compression and decompression state of the extension
+pub struct PerMessageDeflate {
+    config: DeflateConfig,
+    compressor: Compress,
+    decompressor: Decompress,
+    reset_compressor: bool,                                  // client_no_context_takeover
+    reset_decompressor: bool,                                // server_no_context_takeover
+}
+
+impl PerMessageDeflate {
+    pub fn new(config: DeflateConfig) -> Self {
+        let compressor = Compress::new(Compression::new(config.level), false);
+        let reset_compressor = config.client_no_context_takeover;
+
+        PerMessageDeflate { config, compressor, decompressor: Decompress::new(false), reset_compressor, reset_decompressor: false }
+    }
+
+    // Compress the payload of a whole message
+    pub fn compress(&mut self, data: &[u8]) -> WebSocketResult<Vec<u8>> {
+        let mut output = Vec::with_capacity(data.len() / 2 + 64);
+        let start = self.compressor.total_in();
+
+        loop {
+            let consumed = (self.compressor.total_in() - start) as usize;
+            if output.len() == output.capacity() { output.reserve(output.capacity().max(64)) }
+
+            let res = self.compressor.compress_vec(&data[consumed..], &mut output, FlushCompress::Sync);
+            if let Err(e) = res { return Err(WebSocketError::IOError(io::Error::new(io::ErrorKind::Other, e))) }
+
+            // All the input was consumed and the flush ended (there was still space left in the output)
+            let consumed = (self.compressor.total_in() - start) as usize;
+            if consumed == data.len() && output.len() < output.capacity() { break }
+        }
+
+        if output.ends_with(&TRAILER) { output.truncate(output.len() - TRAILER.len()) }
+        if self.reset_compressor { self.compressor.reset() }
+
+        Ok(output)
+    }
+
+    // Decompress the payload of a whole message, the decompressed data can't be greater than max_size
+    pub fn decompress(&mut self, data: &[u8], max_size: u64) -> WebSocketResult<Vec<u8>> {
+        let mut input = Vec::with_capacity(data.len() + TRAILER.len());
+        input.extend_from_slice(data);
+        input.extend_from_slice(&TRAILER);
+
+        let mut output = Vec::with_capacity(data.len() * 2 + 64);
+        let start = self.decompressor.total_in();
+
+        loop {
+            let consumed = (self.decompressor.total_in() - start) as usize;
+            if output.len() == output.capacity() { output.reserve(output.capacity()) }
+
+            let total_out = self.decompressor.total_out();
+            let res = self.decompressor.decompress_vec(&input[consumed..], &mut output, FlushDecompress::Sync);
+
+            let status = match res {
+                Ok(status) => status,
+                Err(_) => return Err(ProtocolError::InvalidExtensionData(PERMESSAGE_DEFLATE.to_string()).into())
+            };
+
+            if output.len() as u64 > max_size { return Err(CapacityError::MessageTooBig { max: max_size }.into()) }
+
+            let consumed_now = (self.decompressor.total_in() - start) as usize;
+            if consumed_now == input.len() && output.len() < output.capacity() { break }
+
+            // The stream was finished by the server or there's no progress (corrupted data)
+            let progress = consumed_now != consumed || self.decompressor.total_out() != total_out;
+            if status == Status::StreamEnd || !progress { break }
+        }
+
+        if self.reset_decompressor { self.decompressor.reset(false) }
+
+        Ok(output)
+    }
+}
+
+impl WebSocketExtension for PerMessageDeflate {
+    fn name(&self) -> &str {
+        PERMESSAGE_DEFLATE
+    }
+
+    fn offer(&self) -> Extension {
+        let mut params = Vec::new();
+
+        if self.config.server_no_context_takeover { params.push(Parameter::with_value(SERVER_NO_CONTEXT_TAKEOVER.to_string(), None)) }
+        if self.config.client_no_context_takeover { params.push(Parameter::with_value(CLIENT_NO_CONTEXT_TAKEOVER.to_string(), None)) }
+        if let Some(bits) = self.config.server_max_window_bits {
+            params.push(Parameter::with_value(SERVER_MAX_WINDOW_BITS.to_string(), Some(bits.to_string())));
+        }
+
+        Extension::new(PERMESSAGE_DEFLATE.to_string(), params)
+    }
+
+    fn box_clone(&self) -> Box<dyn WebSocketExtension> {
+        Box::new(PerMessageDeflate::new(self.config.clone()))
+    }
+
+    // Validate the parameters accepted by the server, returns an error if the client can't work with them
+    fn accept(&mut self, response: &Extension) -> WebSocketResult<()> {
+        if response.name() != PERMESSAGE_DEFLATE { return Err(rejected()) }
+
+        let mut reset_compressor = self.config.client_no_context_takeover;
+        let mut reset_decompressor = false;
+        let mut seen: Vec<&str> = Vec::new();
+
+        for param in response.params() {
+            // Each parameter must appear only once
+            if seen.contains(&param.name()) { return Err(rejected()) }
+            seen.push(param.name());
+
+            match param.name() {
+                SERVER_NO_CONTEXT_TAKEOVER => {
+                    if param.value().is_some() { return Err(rejected()) }
+                    reset_decompressor = true;
+                },
+                CLIENT_NO_CONTEXT_TAKEOVER => {
+                    if param.value().is_some() { return Err(rejected()) }
+                    reset_compressor = true;
+                },
+                SERVER_MAX_WINDOW_BITS => {
+                    // The decompressor works with any window, but the value must be valid and not greater than the offered one
+                    let bits = parse_window_bits(param.value())?;
+                    if let Some(offered) = self.config.server_max_window_bits {
+                        if bits > offered { return Err(rejected()) }
+                    }
+                },
+                // Not offered, the server can't limit the window of the compressor
+                CLIENT_MAX_WINDOW_BITS => return Err(rejected()),
+                _ => return Err(rejected())
+            }
+        }
+
+        // The server must accept server_no_context_takeover if it was offered
+        if self.config.server_no_context_takeover && !reset_decompressor { return Err(rejected()) }
+
+        self.reset_compressor = reset_compressor;
+        self.reset_decompressor = reset_decompressor;
+
+        Ok(())
+    }
+
+    fn rsv_bits(&self) -> u8 {
+        RSV1
+    }
+
+    fn encode(&mut self, frame: &mut ExtensionFrame) -> WebSocketResult<()> {
+        if frame.opcode != OPCODE_TEXT && frame.opcode != OPCODE_BINARY { return Ok(()) }
+
+        frame.payload = self.compress(&frame.payload)?;
+        frame.rsv |= RSV1;
+        Ok(())
+    }
+
+    fn decode(&mut self, frame: &mut ExtensionFrame, max_size: u64) -> WebSocketResult<()> {
+        if frame.rsv & RSV1 == 0 { return Ok(()) }
+
+        frame.payload = self.decompress(&frame.payload, max_size)?;
+        frame.rsv &= !RSV1;
+        Ok(())
+    }
+}
+
+fn rejected() -> WebSocketError {
+    WebSocketError::HandShake(HandShakeError::ExtensionRejected(PERMESSAGE_DEFLATE.to_string()))
+}
+
+fn parse_window_bits(value: Option<&str>) -> WebSocketResult<u8> {
+    let bits = value.and_then(|v| v.parse::<u8>().ok());
+    match bits {
+        Some(b) if (MIN_WINDOW_BITS..=MAX_WINDOW_BITS).contains(&b) => Ok(b),
+        _ => Err(rejected())
+    }
+}
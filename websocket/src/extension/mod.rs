@@ -0,0 +1,161 @@
+use std::fmt;
+use std::collections::HashMap;
+use crate::result::WebSocketResult;
+
+#[cfg(feature = "permessage-deflate")]
+pub mod deflate;
+#[cfg(test)]
+mod test;
+
+// Reserved bits of the first byte of a frame that an extension can claim
+pub const RSV1: u8 = 0x40;
+pub const RSV2: u8 = 0x20;
+pub const RSV3: u8 = 0x10;
+
+// Opcodes of the data frames
+pub const OPCODE_TEXT: u8 = 0x1;
+pub const OPCODE_BINARY: u8 = 0x2;
+
+// Data frame given to the extensions. The extensions work with whole messages, so a message sent or received
+// in multiple frames is given as a single frame: outgoing frames before fragmentation and incoming frames after reassembly.
+// The reserved bits are set only in the first frame of the message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtensionFrame {
+    pub opcode: u8,
+    pub rsv: u8,
+    pub payload: Vec<u8>
+}
+
+impl ExtensionFrame {
+    pub fn new(opcode: u8, rsv: u8, payload: Vec<u8>) -> Self {
+        ExtensionFrame { opcode, rsv, payload }
+    }
+}
+
+// Extension negotiated in the handshake that can transform the data frames.
+// The outgoing frames go through the negotiated extensions in the order accepted by the server
+// and the incoming frames in the reverse order.
+pub trait WebSocketExtension: Send {
+    // Name of the extension, used to match the extension accepted in the server response
+    fn name(&self) -> &str;
+
+    // Extension to offer in the Sec-WebSocket-Extensions header of the handshake request
+    fn offer(&self) -> Extension;
+
+    // New handler with the configuration of this one and without the state of a connection.
+    // The extensions added to the client are only offered, each handshake negotiates a new handler
+    fn box_clone(&self) -> Box<dyn WebSocketExtension>;
+
+    // Validate the extension accepted by the server, an error fails the handshake
+    fn accept(&mut self, response: &Extension) -> WebSocketResult<()>;
+
+    // Reserved bits used by the extension (RSV1 | RSV2 | RSV3), two extensions can't claim the same bit
+    fn rsv_bits(&self) -> u8 { 0 }
+
+    // Transform a frame before sending it
+    fn encode(&mut self, _frame: &mut ExtensionFrame) -> WebSocketResult<()> { Ok(()) }
+
+    // Transform a frame received, the payload can't be greater than max_size after the transformation
+    fn decode(&mut self, _frame: &mut ExtensionFrame, _max_size: u64) -> WebSocketResult<()> { Ok(()) }
+}
+
+// Extension sent or received in the Sec-WebSocket-Extensions header.
+// e.g: permessage-deflate; client_no_context_takeover; server_max_window_bits=10
+#[derive(Clone, Debug, PartialEq)]
+pub struct Extension {
+    name: String,
+    params: Vec<Parameter>
+}
+
+impl Extension {
+    pub fn new(name: String, params: Vec<Parameter>) -> Self {
+        Extension { name, params }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn params(&self) -> &[Parameter] {
+        self.params.as_slice()
+    }
+
+    // Return the first parameter with the given name
+    pub fn param(&self, name: &str) -> Option<&Parameter> {
+        self.params.iter().find(|p| p.name == name)
+    }
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name.as_str())?;
+        for param in &self.params {
+            write!(f, "; {}", param)?;
+        }
+        Ok(())
+    }
+}
+
+// Parameter of an extension, with or without value. e.g: client_no_context_takeover, server_max_window_bits=10
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameter {
+    name: String,
+    value: Option<String>,
+    args: Option<HashMap<String, Option<String>>>            // Arguments of the parameters created with new, not sent in the header
+}
+
+impl Parameter {
+    // Parameter with arguments (arg or arg=value), the form of the first versions of the library.
+    // The header has one value for each parameter, use with_value for the parameters to offer
+    pub fn new(name: String, args: Option<HashMap<String, Option<String>>>) -> Self {
+        Parameter { name, value: None, args }
+    }
+
+    // Parameter of the Sec-WebSocket-Extensions header, e.g: server_max_window_bits=10
+    pub fn with_value(name: String, value: Option<String>) -> Self {
+        Parameter { name, value, args: None }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    pub fn args(&self) -> Option<&HashMap<String, Option<String>>> {
+        self.args.as_ref()
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{}={}", self.name, value),
+            None => f.write_str(self.name.as_str())
+        }
+    }
+}
+
+// parameter!("client_no_context_takeover") or parameter!("server_max_window_bits", 10).
+// parameter!("name"; "arg", "arg=value") creates a parameter with arguments (Parameter::new)
+#[macro_export]
+macro_rules! parameter {
+    ($n:expr) => { $crate::extension::Parameter::with_value($n.to_string(), None) };
+    ($n:expr, $v:expr) => { $crate::extension::Parameter::with_value($n.to_string(), Some($v.to_string())) };
+    ($n:expr; $($arg:expr),*) => {
+        {
+            let mut args: ::std::collections::HashMap<String, Option<String>> = ::std::collections::HashMap::new();
+
+            $(
+                let arg_val = String::from($arg);
+                match arg_val.find('=') {
+                    Some(i) => { args.insert(arg_val[0..i].trim().to_string(), Some(arg_val[i+1..].trim().to_string())); },
+                    None => { args.insert(arg_val, None); }
+                }
+            )*
+            $crate::extension::Parameter::new($n.to_string(), Some(args))
+        }
+    };
+}
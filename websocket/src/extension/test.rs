@@ -0,0 +1,178 @@
+use super::{Extension, Parameter};
+#[cfg(feature = "permessage-deflate")]
+use super::{ExtensionFrame, WebSocketExtension, RSV1, OPCODE_TEXT};
+#[cfg(feature = "permessage-deflate")]
+use crate::result::{WebSocketError, CapacityError};
+use crate::parameter;
+use std::collections::HashMap;
+
+// -------------------------------------------------------------------------------------------------------- //
+//                                               mod.rs
+// -------------------------------------------------------------------------------------------------------- //
+
+#[test]
+fn parameter_macro_without_value() {
+    let param = parameter!("client_no_context_takeover");
+    assert_eq!(param.name(), "client_no_context_takeover");
+    assert_eq!(param.value(), None);
+}
+
+#[test]
+fn parameter_macro_with_value() {
+    let param = parameter!("server_max_window_bits", 10);
+    assert_eq!(param.name(), "server_max_window_bits");
+    assert_eq!(param.value(), Some("10"));
+
+    assert_eq!(param, Parameter::with_value("server_max_window_bits".to_string(), Some("10".to_string())));
+
+    // The name is never split
+    assert_eq!(parameter!("a=b").name(), "a=b");
+}
+
+#[test]
+fn parameter_macro_with_arguments() {
+    let param = parameter!("param"; "flag", "size = 10");
+    let args = param.args().unwrap();
+    assert_eq!(args.len(), 2);
+    assert_eq!(args.get("flag"), Some(&None));
+    assert_eq!(args.get("size"), Some(&Some("10".to_string())));
+    assert_eq!(param.value(), None);
+
+    let mut expected = HashMap::new();
+    expected.insert("flag".to_string(), None);
+    expected.insert("size".to_string(), Some("10".to_string()));
+    assert_eq!(param, Parameter::new("param".to_string(), Some(expected)));
+    assert!(parameter!("param").args().is_none());
+}
+
+#[test]
+fn extension_to_string() {
+    let extension = Extension::new("permessage-deflate".to_string(), vec![
+        parameter!("client_no_context_takeover"),
+        parameter!("server_max_window_bits", 10)
+    ]);
+
+    assert_eq!(extension.to_string(), "permessage-deflate; client_no_context_takeover; server_max_window_bits=10");
+    assert_eq!(extension.param("server_max_window_bits").unwrap().value(), Some("10"));
+    assert!(extension.param("client_max_window_bits").is_none());
+}
+
+// -------------------------------------------------------------------------------------------------------- //
+//                                               deflate.rs
+// -------------------------------------------------------------------------------------------------------- //
+#[cfg(feature = "permessage-deflate")]
+use super::deflate::{PerMessageDeflate, DeflateConfig};
+
+#[cfg(feature = "permessage-deflate")]
+fn deflate_extension(params: Vec<Parameter>) -> Extension {
+    Extension::new("permessage-deflate".to_string(), params)
+}
+
+#[cfg(feature = "permessage-deflate")]
+fn deflate_accept(config: &DeflateConfig, response: &Extension) -> Result<PerMessageDeflate, crate::result::WebSocketError> {
+    let mut deflate = PerMessageDeflate::new(config.clone());
+    deflate.accept(response)?;
+    Ok(deflate)
+}
+
+#[test]
+#[cfg(feature = "permessage-deflate")]
+fn deflate_offer() {
+    let config = DeflateConfig { server_no_context_takeover: true, server_max_window_bits: Some(10), ..DeflateConfig::default() };
+    assert_eq!(PerMessageDeflate::new(config).offer().to_string(), "permessage-deflate; server_no_context_takeover; server_max_window_bits=10");
+    assert_eq!(PerMessageDeflate::new(DeflateConfig::default()).offer().to_string(), "permessage-deflate");
+}
+
+#[test]
+#[cfg(feature = "permessage-deflate")]
+fn deflate_accept_valid_response() {
+    let config = DeflateConfig { server_max_window_bits: Some(12), ..DeflateConfig::default() };
+    assert!(deflate_accept(&config, &deflate_extension(vec![])).is_ok());
+    assert!(deflate_accept(&config, &deflate_extension(vec![parameter!("server_max_window_bits", 10)])).is_ok());
+    assert!(deflate_accept(&config, &deflate_extension(vec![parameter!("client_no_context_takeover")])).is_ok());
+}
+
+#[test]
+#[cfg(feature = "permessage-deflate")]
+fn deflate_reject_invalid_response() {
+    let config = DeflateConfig { server_max_window_bits: Some(12), ..DeflateConfig::default() };
+    assert!(deflate_accept(&config, &deflate_extension(vec![parameter!("server_max_window_bits", 13)])).is_err());
+    assert!(deflate_accept(&config, &deflate_extension(vec![parameter!("server_max_window_bits", 7)])).is_err());
+    assert!(deflate_accept(&config, &deflate_extension(vec![parameter!("client_max_window_bits")])).is_err());
+    assert!(deflate_accept(&config, &deflate_extension(vec![parameter!("client_max_window_bits", 16)])).is_err());
+    assert!(deflate_accept(&config, &deflate_extension(vec![parameter!("unknown")])).is_err());
+    assert!(deflate_accept(&config, &deflate_extension(vec![parameter!("client_no_context_takeover"), parameter!("client_no_context_takeover")])).is_err());
+
+    // The server must accept server_no_context_takeover when it's offered
+    let config = DeflateConfig { server_no_context_takeover: true, ..DeflateConfig::default() };
+    assert!(deflate_accept(&config, &deflate_extension(vec![])).is_err());
+}
+
+#[test]
+#[cfg(feature = "permessage-deflate")]
+fn deflate_reject_client_max_window_bits() {
+    // The parameter is not offered, the compressor can't work with a smaller window
+    for bits in [8, 12, 15] {
+        assert!(deflate_accept(&DeflateConfig::default(), &deflate_extension(vec![parameter!("client_max_window_bits", bits)])).is_err());
+    }
+}
+
+#[test]
+#[cfg(feature = "permessage-deflate")]
+fn deflate_decompress_rfc_example() {
+    // RFC 7692 7.2.3.1: "Hello" compressed
+    let mut deflate = deflate_accept(&DeflateConfig::default(), &deflate_extension(vec![])).unwrap();
+    let data = deflate.decompress(&[0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00], 1024).unwrap();
+    assert_eq!(data, "Hello".as_bytes());
+
+    // Same message using the context of the previous one
+    let data = deflate.decompress(&[0xf2, 0x00, 0x11, 0x00, 0x00], 1024).unwrap();
+    assert_eq!(data, "Hello".as_bytes());
+}
+
+#[test]
+#[cfg(feature = "permessage-deflate")]
+fn deflate_compress_and_decompress() {
+    let mut client = deflate_accept(&DeflateConfig::default(), &deflate_extension(vec![])).unwrap();
+    let mut server = deflate_accept(&DeflateConfig::default(), &deflate_extension(vec![])).unwrap();
+    let message = "{\"temperature\": 21.5, \"humidity\": 40}".repeat(100);
+
+    for _ in 0..3 {
+        let compressed = client.compress(message.as_bytes()).unwrap();
+        assert!(compressed.len() < message.len() / 10);
+        assert!(!compressed.ends_with(&[0x00, 0x00, 0xFF, 0xFF]));
+
+        let data = server.decompress(&compressed, u64::MAX).unwrap();
+        assert_eq!(data, message.as_bytes());
+    }
+}
+
+#[test]
+#[cfg(feature = "permessage-deflate")]
+fn deflate_decompress_bigger_than_max_size() {
+    let mut client = deflate_accept(&DeflateConfig::default(), &deflate_extension(vec![])).unwrap();
+    let mut server = deflate_accept(&DeflateConfig::default(), &deflate_extension(vec![])).unwrap();
+    let message = "a".repeat(10000);
+
+    let compressed = client.compress(message.as_bytes()).unwrap();
+    assert!(server.decompress(&compressed, 100).err().unwrap() == WebSocketError::Capacity(CapacityError::MessageTooBig { max: 100 }));
+}
+
+#[test]
+#[cfg(feature = "permessage-deflate")]
+fn deflate_encode_and_decode_frames() {
+    let mut client = deflate_accept(&DeflateConfig::default(), &deflate_extension(vec![])).unwrap();
+    let mut server = deflate_accept(&DeflateConfig::default(), &deflate_extension(vec![])).unwrap();
+    assert_eq!(client.rsv_bits(), RSV1);
+
+    let mut frame = ExtensionFrame::new(OPCODE_TEXT, 0, "Hello".as_bytes().to_vec());
+    client.encode(&mut frame).unwrap();
+    assert_eq!(frame.rsv, RSV1);
+
+    server.decode(&mut frame, 1024).unwrap();
+    assert_eq!(frame, ExtensionFrame::new(OPCODE_TEXT, 0, "Hello".as_bytes().to_vec()));
+
+    // Frames without RSV1 are not compressed
+    server.decode(&mut frame, 1024).unwrap();
+    assert_eq!(frame.payload, "Hello".as_bytes());
+}
@@ -1,6 +1,7 @@
-use super::super::sync::client::{WSEvent as RWSEvent, Reason};
-use std::ffi::{c_void, CString};
-use crate::result::WebSocketError;
+use super::super::sync::client::{WSEvent as RWSEvent, Reason, MessageKind};
+use super::super::sync::stats::Stats;
+use std::ffi::{c_char, c_void, CString};
+use crate::result::{WebSocketError, ProtocolError};
 use std::ptr;
 
 #[repr(C)]
@@ -8,7 +9,10 @@ use std::ptr;
 enum WSEvent {
     ON_CONNECT,
     ON_TEXT,
-    ON_CLOSE
+    ON_CLOSE,
+    ON_DRAINED,
+    ON_FRAGMENT,
+    ON_BINARY
 }
 
 #[repr(C)]
@@ -25,11 +29,85 @@ enum WSReason {
     CLIENT_CLOSED
 }
 
+// The struct and the message are freed with free_rust_event
 #[repr(C)]
 #[allow(non_camel_case_types)]
 struct WSReason_t {
     reason: WSReason,
-    status: u16
+    status: u16,
+    message: *const c_char
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+enum WSMessageKind {
+    TEXT_MESSAGE,
+    BINARY_MESSAGE
+}
+
+// The data points to the payload of the event, only valid during the callback. The struct is freed with free_rust_event
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct WSFragment_t {
+    kind: WSMessageKind,
+    data: *const u8,
+    len: usize,
+    is_final: bool
+}
+
+// The data points to the payload of the binary message, only valid during the callback. The struct is freed with free_rust_event
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct WSBinary_t {
+    data: *const u8,
+    len: usize
+}
+
+// Statistics of the connection, the durations are 0 until they are measured
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct WSStats_t {
+    bytes_sent: u64,
+    bytes_received: u64,
+    frames_sent: u64,
+    frames_received: u64,
+    text_messages_sent: u64,
+    binary_messages_sent: u64,
+    text_messages_received: u64,
+    binary_messages_received: u64,
+    pings_sent: u64,
+    pongs_sent: u64,
+    pings_received: u64,
+    pongs_received: u64,
+    last_rtt_us: u64,
+    queue_high_water_messages: u64,
+    queue_high_water_bytes: u64,
+    handshake_us: u64,
+    reconnects: u64
+}
+
+impl From<Stats> for WSStats_t {
+    fn from(stats: Stats) -> Self {
+        WSStats_t {
+            bytes_sent: stats.bytes_sent,
+            bytes_received: stats.bytes_received,
+            frames_sent: stats.frames_sent,
+            frames_received: stats.frames_received,
+            text_messages_sent: stats.text_messages_sent,
+            binary_messages_sent: stats.binary_messages_sent,
+            text_messages_received: stats.text_messages_received,
+            binary_messages_received: stats.binary_messages_received,
+            pings_sent: stats.pings_sent,
+            pongs_sent: stats.pongs_sent,
+            pings_received: stats.pings_received,
+            pongs_received: stats.pongs_received,
+            last_rtt_us: stats.last_rtt.map_or(0, |rtt| rtt.as_micros() as u64),
+            queue_high_water_messages: stats.queue_high_water_messages,
+            queue_high_water_bytes: stats.queue_high_water_bytes,
+            handshake_us: stats.handshake_duration.map_or(0, |duration| duration.as_micros() as u64),
+            reconnects: stats.reconnects
+        }
+    }
 }
 
 #[repr(C)]
@@ -42,16 +120,30 @@ pub enum WSStatus {
     ConnectionClose,
     DecodingFromUTF8,
     IOError,
+    MessageTooBig,
+    Timeout,
+    QueueFull,
+}
+
+// Copy the string to the buffer ending with \0, false if the buffer is NULL or the string doesn't fit
+pub(crate) unsafe fn copy_to_c_buffer(value: &str, buffer: *mut c_char, len: usize) -> bool {
+    if buffer.is_null() || value.len() >= len { return false }
+    ptr::copy_nonoverlapping(value.as_ptr() as *const c_char, buffer, value.len());
+    *buffer.add(value.len()) = 0;
+    true
 }
 
 pub fn rust_error_to_c_error(error: WebSocketError) -> WSStatus {
     match error {
-        WebSocketError::UnreachableHost => WSStatus::UnreachableHost,
-        WebSocketError::HandShake => WSStatus::HandShake,
-        WebSocketError::InvalidFrame => WSStatus::InvalidFrame,
+        WebSocketError::UnreachableHost(_) => WSStatus::UnreachableHost,
+        WebSocketError::HandShake(_) => WSStatus::HandShake,
+        WebSocketError::Protocol(ProtocolError::InvalidUTF8) => WSStatus::DecodingFromUTF8,
+        WebSocketError::Protocol(_) => WSStatus::InvalidFrame,
+        WebSocketError::Capacity(_) => WSStatus::MessageTooBig,
+        WebSocketError::Timeout => WSStatus::Timeout,
         WebSocketError::ConnectionClose => WSStatus::ConnectionClose,
-        WebSocketError::DecodingFromUTF8 => WSStatus::DecodingFromUTF8,
-        WebSocketError::IOError => WSStatus::IOError
+        WebSocketError::QueueFull => WSStatus::QueueFull,
+        WebSocketError::IOError(_) => WSStatus::IOError,
     }
 }
 
@@ -76,14 +168,46 @@ pub extern "C" fn from_rust_event(event: &RWSEvent) -> WSEvent_t {
             let c_str = CString::new(msg.clone()).unwrap();
             WSEvent_t { event: WSEvent::ON_TEXT, value: c_str.into_raw() as *const c_void }
         },
+        RWSEvent::ON_BINARY(data) => {
+            let binary = Box::into_raw(Box::new(WSBinary_t { data: data.as_ptr(), len: data.len() }));
+            WSEvent_t { event: WSEvent::ON_BINARY, value: binary as *const c_void }
+        },
         RWSEvent::ON_CLOSE(reason) => {
+            let message = CString::new(reason.reason().replace('\0', "")).unwrap().into_raw();
             let (reason, status) = match reason {
-                Reason::SERVER_CLOSE(status) => (WSReason::SERVER_CLOSED, status.clone()),   
-                Reason::CLIENT_CLOSE(status) => (WSReason::CLIENT_CLOSED, status.clone())
+                Reason::SERVER_CLOSE(status, _) => (WSReason::SERVER_CLOSED, u16::from(*status)),   
+                Reason::CLIENT_CLOSE(status, _) => (WSReason::CLIENT_CLOSED, u16::from(*status))
             };
-            let reason = WSReason_t { reason, status };
+            let reason = WSReason_t { reason, status, message };
             let reason = Box::into_raw(Box::new(reason));
             WSEvent_t { event: WSEvent::ON_CLOSE, value: reason as *const c_void } 
+        },
+        RWSEvent::ON_DRAINED => WSEvent_t { event: WSEvent::ON_DRAINED, value: ptr::null() },
+        RWSEvent::ON_FRAGMENT { kind, data, is_final } => {
+            let kind = match kind {
+                MessageKind::TEXT => WSMessageKind::TEXT_MESSAGE,
+                MessageKind::BINARY => WSMessageKind::BINARY_MESSAGE
+            };
+            let fragment = WSFragment_t { kind, data: data.as_ptr(), len: data.len(), is_final: *is_final };
+            let fragment = Box::into_raw(Box::new(fragment));
+            WSEvent_t { event: WSEvent::ON_FRAGMENT, value: fragment as *const c_void }
         }
     }
-}
\ No newline at end of file
+}
+
+// Free the values allocated by from_rust_event, the event can't be used after
+#[no_mangle]
+unsafe extern "C" fn free_rust_event(event: WSEvent_t) {
+    if event.value.is_null() { return }
+
+    match event.event {
+        WSEvent::ON_CONNECT | WSEvent::ON_TEXT => drop(CString::from_raw(event.value as *mut c_char)),
+        WSEvent::ON_FRAGMENT => drop(Box::from_raw(event.value as *mut WSFragment_t)),
+        WSEvent::ON_BINARY => drop(Box::from_raw(event.value as *mut WSBinary_t)),
+        WSEvent::ON_CLOSE => {
+            let reason = Box::from_raw(event.value as *mut WSReason_t);
+            drop(CString::from_raw(reason.message as *mut c_char));
+        },
+        WSEvent::ON_DRAINED => {}
+    }
+}
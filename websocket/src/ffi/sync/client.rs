@@ -1,9 +1,11 @@
-use super::super::super::sync::client::{Config, WSEvent as RWSEvent, WSClient};
+use super::super::super::sync::client::{Config, WSEvent as RWSEvent, WSClient, ConnectionState};
+use crate::http::extension::extensions_to_header;
 use std::ffi::{c_void, c_char, CStr};
 use std::alloc::{alloc, Layout};
 use std::mem;
 use std::ptr;
 use std::str;
+use std::time::{Duration, UNIX_EPOCH};
 use super::super::common;
 
 #[no_mangle]
@@ -40,7 +42,7 @@ unsafe extern "C" fn wssclient_init<'a> (
     let path = str::from_utf8(CStr::from_ptr(path).to_bytes()).unwrap();
 
     let callback: fn(&mut WSClient<'a, *mut c_void>, &RWSEvent, Option<*mut c_void>) = mem::transmute(callback);
-    let config = Config { callback: Some(callback), data: None, protocols: None };
+    let config = Config { callback: Some(callback), data: None, protocols: None, random: None };
     
     let client = &mut *client;
 
@@ -62,10 +64,145 @@ unsafe extern "C" fn wssclient_loop<'a>(client: *mut WSClient<'a, *mut c_void>)
 }
 
 #[no_mangle]
-unsafe extern "C" fn wssclient_send<'a>(client: *mut WSClient<'a, *mut c_void>, message: *const c_char) {
+unsafe extern "C" fn wssclient_loop_timeout<'a>(client: *mut WSClient<'a, *mut c_void>, timeout_ms: u64) -> common::WSStatus {
+    let client = &mut *client;
+
+    match client.event_loop_timeout(Duration::from_millis(timeout_ms)) {
+        Ok(_) => {}
+        Err(e) => {
+            return common::rust_error_to_c_error(e);
+        } 
+    }
+
+    common::WSStatus::OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_set_drain<'a>(client: *mut WSClient<'a, *mut c_void>, drain: bool) {
+    let client = &mut *client;
+    client.set_drain(drain);
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_send<'a>(client: *mut WSClient<'a, *mut c_void>, message: *const c_char) -> common::WSStatus {
     let msg = str::from_utf8(CStr::from_ptr(message).to_bytes()).unwrap();
     let client = &mut *client;
-    client.send(msg);
+
+    match client.send(msg) {
+        Ok(_) => common::WSStatus::OK,
+        Err(e) => common::rust_error_to_c_error(e)
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_set_max_queued_messages<'a>(client: *mut WSClient<'a, *mut c_void>, max: u64) {
+    let client = &mut *client;
+    client.set_max_queued_messages(usize::try_from(max).unwrap_or(usize::MAX));
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_set_max_queued_bytes<'a>(client: *mut WSClient<'a, *mut c_void>, max: u64) {
+    let client = &mut *client;
+    client.set_max_queued_bytes(usize::try_from(max).unwrap_or(usize::MAX));
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_queued_bytes<'a>(client: *const WSClient<'a, *mut c_void>) -> u64 {
+    let client = &*client;
+    client.queued_bytes() as u64
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_set_stream_fragments<'a>(client: *mut WSClient<'a, *mut c_void>, stream: bool) {
+    let client = &mut *client;
+    client.set_stream_fragments(stream);
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_set_fixed_buffers<'a>(client: *mut WSClient<'a, *mut c_void>, recv_size: usize, send_size: usize) {
+    let client = &mut *client;
+    client.set_fixed_buffers(recv_size, send_size);
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_set_max_frame_size<'a>(client: *mut WSClient<'a, *mut c_void>, size: u64) {
+    let client = &mut *client;
+    client.set_max_frame_size(size);
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_set_max_message_size<'a>(client: *mut WSClient<'a, *mut c_void>, size: u64) {
+    let client = &mut *client;
+    client.set_max_message_size(size);
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_state<'a>(client: *const WSClient<'a, *mut c_void>) -> ConnectionState {
+    let client = &*client;
+    client.state()
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_is_open<'a>(client: *const WSClient<'a, *mut c_void>) -> bool {
+    let client = &*client;
+    client.is_open()
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_peer_addr<'a>(client: *const WSClient<'a, *mut c_void>, buffer: *mut c_char, len: usize) -> bool {
+    let client = &*client;
+    match client.peer_addr() {
+        Some(addr) => common::copy_to_c_buffer(&addr.to_string(), buffer, len),
+        None => false
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_local_addr<'a>(client: *const WSClient<'a, *mut c_void>, buffer: *mut c_char, len: usize) -> bool {
+    let client = &*client;
+    match client.local_addr() {
+        Some(addr) => common::copy_to_c_buffer(&addr.to_string(), buffer, len),
+        None => false
+    }
+}
+
+// Milliseconds since the unix epoch, 0 if the connection was never open
+#[no_mangle]
+unsafe extern "C" fn wssclient_connect_time_ms<'a>(client: *const WSClient<'a, *mut c_void>) -> u64 {
+    let client = &*client;
+    let since_epoch = client.connect_time().and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+    since_epoch.map_or(0, |time| time.as_millis() as u64)
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_uptime_ms<'a>(client: *const WSClient<'a, *mut c_void>) -> u64 {
+    let client = &*client;
+    client.uptime().map_or(0, |uptime| uptime.as_millis() as u64)
+}
+
+#[no_mangle]
+unsafe extern "C" fn wssclient_protocol<'a>(client: *const WSClient<'a, *mut c_void>, buffer: *mut c_char, len: usize) -> bool {
+    let client = &*client;
+    match client.protocol() {
+        Some(protocol) => common::copy_to_c_buffer(protocol, buffer, len),
+        None => false
+    }
+}
+
+// The extensions accepted by the server as the value of the header Sec-WebSocket-Extensions
+#[no_mangle]
+unsafe extern "C" fn wssclient_extensions<'a>(client: *const WSClient<'a, *mut c_void>, buffer: *mut c_char, len: usize) -> bool {
+    let client = &*client;
+    common::copy_to_c_buffer(&extensions_to_header(client.extensions()), buffer, len)
+}
+
+// Copy the statistics of the connection, false if stats is NULL
+#[no_mangle]
+unsafe extern "C" fn wssclient_stats<'a>(client: *const WSClient<'a, *mut c_void>, stats: *mut common::WSStats_t) -> bool {
+    if stats.is_null() { return false }
+    let client = &*client;
+    ptr::write(stats, client.stats().into());
+    true
 }
 
 #[no_mangle]
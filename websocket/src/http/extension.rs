@@ -0,0 +1,42 @@
+// Parse and build the value of the Sec-WebSocket-Extensions header
+
+use crate::extension::{Extension, Parameter};
+use super::super::core::traits::ParseError;
+
+// Parse the value of the header, e.g: permessage-deflate; client_max_window_bits=10, x-custom
+pub fn parse_extensions(value: &str) -> Result<Vec<Extension>, ParseError> {
+    let mut extensions = Vec::new();
+
+    for item in value.split(',') {
+        let mut tokens = item.split(';');
+        let name = tokens.next().unwrap_or("").trim();
+        if name.is_empty() { return Err(ParseError) }
+
+        let mut params = Vec::new();
+        for token in tokens {
+            let token = token.trim();
+            if token.is_empty() { return Err(ParseError) }
+
+            let param = match token.find('=') {
+                Some(i) => {
+                    let param_name = token[0..i].trim();
+                    let param_value = token[i+1..token.len()].trim().trim_matches('"');
+                    if param_name.is_empty() || param_value.is_empty() { return Err(ParseError) }
+                    Parameter::with_value(param_name.to_string(), Some(param_value.to_string()))
+                },
+                None => Parameter::with_value(token.to_string(), None)
+            };
+            params.push(param);
+        }
+
+        extensions.push(Extension::new(name.to_string(), params));
+    }
+
+    Ok(extensions)
+}
+
+// Build the value of the header, the extensions are separated by commas
+pub fn extensions_to_header(extensions: &[Extension]) -> String {
+    let values: Vec<String> = extensions.iter().map(|e| e.to_string()).collect();
+    values.join(", ")
+}
@@ -1,3 +1,6 @@
 pub mod request;
 pub mod response;
-mod commons;
\ No newline at end of file
+pub mod extension;
+mod commons;
+#[cfg(test)]
+mod test;
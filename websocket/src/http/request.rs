@@ -47,7 +47,11 @@ impl Serialize for Request {
         data.extend(END_LINE.as_bytes());
 
 
-        for (key, value) in (&self.headers).into_iter() {
+        // Sorted by name, the same request is always serialized with the same bytes
+        let mut headers: Vec<(&String, &String)> = self.headers.iter().collect();
+        headers.sort();
+
+        for (key, value) in headers {
             data.extend(key.as_bytes());
             data.extend(": ".as_bytes());
             data.extend(value.as_bytes());
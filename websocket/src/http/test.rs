@@ -0,0 +1,33 @@
+use super::extension::{parse_extensions, extensions_to_header};
+
+// -------------------------------------------------------------------------------------------------------- //
+//                                               extension.rs
+// -------------------------------------------------------------------------------------------------------- //
+
+#[test]
+fn parse_extensions_header() {
+    let extensions = parse_extensions("permessage-deflate; client_no_context_takeover; server_max_window_bits=\"10\", x-custom").unwrap();
+
+    assert_eq!(extensions.len(), 2);
+    assert_eq!(extensions[0].name(), "permessage-deflate");
+    assert_eq!(extensions[0].params().len(), 2);
+    assert_eq!(extensions[0].params()[0].name(), "client_no_context_takeover");
+    assert_eq!(extensions[0].params()[0].value(), None);
+    assert_eq!(extensions[0].params()[1].value(), Some("10"));
+    assert_eq!(extensions[1].name(), "x-custom");
+    assert!(extensions[1].params().is_empty());
+}
+
+#[test]
+fn parse_invalid_extensions_header() {
+    assert!(parse_extensions("").is_err());
+    assert!(parse_extensions("permessage-deflate,").is_err());
+    assert!(parse_extensions("permessage-deflate; ;").is_err());
+    assert!(parse_extensions("permessage-deflate; server_max_window_bits=").is_err());
+}
+
+#[test]
+fn extensions_header_value() {
+    let extensions = parse_extensions("permessage-deflate;client_no_context_takeover,x-custom").unwrap();
+    assert_eq!(extensions_to_header(&extensions), "permessage-deflate; client_no_context_takeover, x-custom");
+}
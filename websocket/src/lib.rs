@@ -1,7 +1,27 @@
+// Without the std feature only the codec is built (no_std + alloc)
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+#[cfg(feature = "std")]
 pub mod sync;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+#[cfg(feature = "std")]
 pub mod result;
+#[cfg(feature = "std")]
 pub mod extension;
+pub mod codec;
 mod ws_basic;
+#[cfg(feature = "std")]
 mod http;
+#[cfg(feature = "std")]
 mod core;
-pub mod ffi;
\ No newline at end of file
+#[cfg(feature = "std")]
+pub mod ffi;
+// Counting allocator to check the allocations of the client (fixed buffers)
+#[cfg(feature = "std")]
+pub mod testing;
+
+pub use ws_basic::status_code::CloseCode;
+// Masking of the payloads (RFC 6455 5.3), public for the benchmarks and for servers built on top of the crate
+pub use ws_basic::mask;
@@ -1,46 +1,220 @@
 use std::fmt;
-use std;
+use std::error;
+use std::io;
+use crate::ws_basic::status_code::CloseCode;
+use crate::codec::CodecError;
 
 // Define type for WebSocketStdResult
 pub type WebSocketResult<T> = Result<T, WebSocketError>;
 
 // Represents a WebSocket error
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub enum WebSocketError {
-    UnreachableHost,
-    HandShake,
-    InvalidFrame,
+    UnreachableHost(io::Error),                              // The TCP connection with the server can't be established
+    HandShake(HandShakeError),
+    Protocol(ProtocolError),                                 // The server violated the protocol, the connection was failed with the close code of the error
+    Capacity(CapacityError),                                 // A limit of the client was exceeded
+    Timeout,                                                 // The operation didn't finish in the time set with set_timeout
     ConnectionClose,
-    DecodingFromUTF8, 
-    IOError,
+    QueueFull,                                               // The outbound queue reached the limit set with set_max_queued_messages or set_max_queued_bytes
+    IOError(io::Error),
+}
+
+// Protocol violations of the server, each one fails the connection with a close code
+#[derive(Debug, PartialEq)]
+pub enum ProtocolError {
+    InvalidFrame,                                            // The frame can't be parsed (1002)
+    ReservedBits,                                            // Reserved bits set without an extension that uses them (1002)
+    InvalidCloseFrame,                                       // Close frame with a body of 1 byte or a status code that can't be sent (1002)
+    InvalidUTF8,                                             // Text message or close reason that is not valid UTF-8 (1007)
+    InvalidExtensionData(String),                            // The extension can't decode the message received (1007)
+}
+
+impl ProtocolError {
+    // Close code sent to the server when the error is detected
+    pub fn close_code(&self) -> CloseCode {
+        match self {
+            ProtocolError::InvalidFrame => CloseCode::ProtocolError,
+            ProtocolError::ReservedBits => CloseCode::ProtocolError,
+            ProtocolError::InvalidCloseFrame => CloseCode::ProtocolError,
+            ProtocolError::InvalidUTF8 => CloseCode::InvalidPayload,
+            ProtocolError::InvalidExtensionData(_) => CloseCode::InvalidPayload,
+        }
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::InvalidFrame => fmt.write_str("invalid frame received"),
+            ProtocolError::ReservedBits => fmt.write_str("reserved bits not negotiated"),
+            ProtocolError::InvalidCloseFrame => fmt.write_str("invalid close frame received"),
+            ProtocolError::InvalidUTF8 => fmt.write_str("invalid utf8 received"),
+            ProtocolError::InvalidExtensionData(e) => write!(fmt, "extension {} can't decode the message", e),
+        }
+    }
+}
+
+// Limits exceeded, max is the limit configured
+#[derive(Debug, PartialEq)]
+pub enum CapacityError {
+    FrameTooBig { max: u64 },                                // A frame received is bigger than max_frame_size (1009)
+    MessageTooBig { max: u64 },                              // A message received is bigger than max_message_size (1009)
+    SendBufferTooSmall { max: u64 },                         // A message to send doesn't fit in the send buffer of set_fixed_buffers (nothing is sent)
+}
+
+impl CapacityError {
+    // Close code sent to the server when the limit is exceeded
+    pub fn close_code(&self) -> CloseCode {
+        match self {
+            CapacityError::FrameTooBig { .. } => CloseCode::MessageTooBig,
+            CapacityError::MessageTooBig { .. } => CloseCode::MessageTooBig,
+            CapacityError::SendBufferTooSmall { .. } => CloseCode::MessageTooBig,
+        }
+    }
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CapacityError::FrameTooBig { max } => write!(fmt, "frame received exceeds the maximum size allowed ({} bytes)", max),
+            CapacityError::MessageTooBig { max } => write!(fmt, "message received exceeds the maximum size allowed ({} bytes)", max),
+            CapacityError::SendBufferTooSmall { max } => write!(fmt, "message to send doesn't fit in the send buffer ({} bytes)", max),
+        }
+    }
+}
+
+// Reason of a failed handshake
+#[derive(Debug, PartialEq)]
+pub enum HandShakeError {
+    StatusCode(u16),                                         // The server didn't response with 101 Switching Protocols
+    MissingAcceptKey,                                        // Sec-WebSocket-Accept not received
+    InvalidAcceptKey,                                        // Sec-WebSocket-Accept doesn't match the Sec-WebSocket-Key sent
+    InvalidHeader(String),                                   // The value of the header can't be parsed
+    ProtocolNotOffered(String),                              // The server selected a protocol that the client didn't offer
+    ProtocolRequired,                                        // The server didn't select any protocol and the client requires one
+    ExtensionNotOffered(String),                             // The server accepted an extension that the client didn't offer
+    ExtensionRejected(String),                               // The client can't work with the parameters accepted by the server for the extension
+}
+
+impl fmt::Display for HandShakeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HandShakeError::StatusCode(code) => write!(fmt, "unexpected status code {}", code),
+            HandShakeError::MissingAcceptKey => fmt.write_str("Sec-WebSocket-Accept not received"),
+            HandShakeError::InvalidAcceptKey => fmt.write_str("invalid Sec-WebSocket-Accept"),
+            HandShakeError::InvalidHeader(h) => write!(fmt, "invalid header {}", h),
+            HandShakeError::ProtocolNotOffered(p) => write!(fmt, "protocol {} was not offered", p),
+            HandShakeError::ProtocolRequired => fmt.write_str("the server didn't select any protocol"),
+            HandShakeError::ExtensionNotOffered(e) => write!(fmt, "extension {} was not offered", e),
+            HandShakeError::ExtensionRejected(e) => write!(fmt, "invalid parameters for extension {}", e),
+        }
+    }
 }
 
-// New Errors:
-// --------------
-// - UnreachableHost
-// - Handshacke
-// - DataFrame
-// - ConnectionClose
-// - IOError (timeout, brokenpipe, addrInuse.....)
+impl WebSocketError {
+    // Close code sent to the server when the error fails the connection
+    pub fn close_code(&self) -> Option<CloseCode> {
+        match self {
+            WebSocketError::Protocol(e) => Some(e.close_code()),
+            WebSocketError::Capacity(e) => Some(e.close_code()),
+            _ => None
+        }
+    }
 
-// ProtocolError --> DataFrameError
+    // Returns if connecting again could succeed: network errors, timeouts and server errors in the handshake.
+    // Protocol and capacity errors will happen again with the same server and configuration
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            WebSocketError::UnreachableHost(_) => true,
+            WebSocketError::HandShake(HandShakeError::StatusCode(code)) => *code >= 500,
+            WebSocketError::HandShake(_) => false,
+            WebSocketError::Protocol(_) => false,
+            WebSocketError::Capacity(_) => false,
+            WebSocketError::Timeout => true,
+            WebSocketError::ConnectionClose => true,
+            WebSocketError::QueueFull => false,
+            WebSocketError::IOError(e) => matches!(e.kind(),
+                io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted |
+                io::ErrorKind::NotConnected | io::ErrorKind::BrokenPipe | io::ErrorKind::TimedOut |
+                io::ErrorKind::Interrupted | io::ErrorKind::UnexpectedEof
+            )
+        }
+    }
+}
 
 impl fmt::Display for WebSocketError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.write_str("WebSocketError: ")?;
         match self {
-            WebSocketError::UnreachableHost => fmt.write_str("Unreachable host"),
-            WebSocketError::HandShake => fmt.write_str("Error performing initial handshake"),
-            WebSocketError::InvalidFrame => fmt.write_str("Invalid frame received"),
+            WebSocketError::UnreachableHost(e) => write!(fmt, "Unreachable host, {}", e),
+            WebSocketError::HandShake(e) => write!(fmt, "Error performing initial handshake, {}", e),
+            WebSocketError::Protocol(e) => write!(fmt, "Protocol error, {}", e),
+            WebSocketError::Capacity(e) => write!(fmt, "Capacity error, {}", e),
+            WebSocketError::Timeout => fmt.write_str("The operation timed out"),
             WebSocketError::ConnectionClose => fmt.write_str("The connection was closed"),
-            WebSocketError::DecodingFromUTF8 => fmt.write_str("Error decoding from utf8"),
-            WebSocketError::IOError => fmt.write_str("IOError")
+            WebSocketError::QueueFull => fmt.write_str("The outbound queue is full"),
+            WebSocketError::IOError(e) => write!(fmt, "IOError, {}", e),
         }
     }
 }
 
-impl From<std::io::Error> for WebSocketError {
-    fn from(_: std::io::Error) -> Self {
-        WebSocketError::IOError
+impl error::Error for WebSocketError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            WebSocketError::UnreachableHost(e) => Some(e),
+            WebSocketError::IOError(e) => Some(e),
+            _ => None
+        }
     }
-}
\ No newline at end of file
+}
+
+impl error::Error for HandShakeError {}
+impl error::Error for ProtocolError {}
+impl error::Error for CapacityError {}
+
+// io::Error can't be compared, the errors are equal if they have the same kind
+impl PartialEq for WebSocketError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (WebSocketError::UnreachableHost(a), WebSocketError::UnreachableHost(b)) => a.kind() == b.kind(),
+            (WebSocketError::HandShake(a), WebSocketError::HandShake(b)) => a == b,
+            (WebSocketError::Protocol(a), WebSocketError::Protocol(b)) => a == b,
+            (WebSocketError::Capacity(a), WebSocketError::Capacity(b)) => a == b,
+            (WebSocketError::Timeout, WebSocketError::Timeout) => true,
+            (WebSocketError::ConnectionClose, WebSocketError::ConnectionClose) => true,
+            (WebSocketError::QueueFull, WebSocketError::QueueFull) => true,
+            (WebSocketError::IOError(a), WebSocketError::IOError(b)) => a.kind() == b.kind(),
+            _ => false
+        }
+    }
+}
+
+impl From<io::Error> for WebSocketError {
+    fn from(error: io::Error) -> Self {
+        WebSocketError::IOError(error)
+    }
+}
+
+impl From<ProtocolError> for WebSocketError {
+    fn from(error: ProtocolError) -> Self {
+        WebSocketError::Protocol(error)
+    }
+}
+
+impl From<CapacityError> for WebSocketError {
+    fn from(error: CapacityError) -> Self {
+        WebSocketError::Capacity(error)
+    }
+}
+
+// The frames that the codec can't parse are protocol errors, the errors to send a frame are errors of the client
+impl From<CodecError> for WebSocketError {
+    fn from(error: CodecError) -> Self {
+        match error {
+            CodecError::InvalidFrame => WebSocketError::Protocol(ProtocolError::InvalidFrame),
+            CodecError::FrameTooBig { max } => WebSocketError::Capacity(CapacityError::FrameTooBig { max }),
+            e => WebSocketError::IOError(io::Error::new(io::ErrorKind::Other, e))
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+use crate::result::{WebSocketError, WebSocketResult};
+use super::client::{WSClient, Config, Reason, Message};
+
+// Blocking client on top of WSClient, for scripts and tests that don't need callbacks.
+// The calls wait on the socket until they can continue, instead of running the event loop in a busy loop.
+//
+// let mut ws = WSBlockingClient::connect("localhost", 3000, "/", None)?;
+// ws.send("Hello")?;
+// let msg = ws.recv()?;
+pub struct WSBlockingClient<'a> {
+    client: WSClient<'a, ()>
+}
+
+impl<'a> WSBlockingClient<'a> {
+    // Connect with the default options, returns when the connection is open or the handshake failed
+    pub fn connect(host: &'a str, port: u16, path: &'a str, protocols: Option<&'a [&'a str]>) -> WebSocketResult<Self> {
+        WSBlockingClient::connect_with(WSClient::new(), host, port, path, protocols)
+    }
+
+    // Connect with a client configured before (timeout, limits, extensions...), returns when the connection is open or the handshake failed
+    pub fn connect_with(client: WSClient<'a, ()>, host: &'a str, port: u16, path: &'a str, protocols: Option<&'a [&'a str]>) -> WebSocketResult<Self> {
+        let mut client = client;
+        let config = Config { callback: None, data: None, protocols, random: None };
+        client.init(host, port, path, Some(config));
+
+        // The handshake timeout is checked by the event loop
+        while !client.is_open() {
+            let timeout = client.timeout();
+            client.event_loop_timeout(timeout)?;
+        }
+
+        Ok(WSBlockingClient { client })
+    }
+
+    // Send the message and wait until is written in the socket
+    pub fn send(&mut self, payload: &str) -> WebSocketResult<()> {
+        if self.client.is_closed() { return Err(WebSocketError::ConnectionClose) }
+        self.client.send(payload)?;
+
+        let deadline = Instant::now() + self.client.timeout();
+        while self.client.has_output() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() { return Err(WebSocketError::Timeout) }
+            self.client.event_loop_timeout(remaining)?;
+        }
+
+        Ok(())
+    }
+
+    // Wait until a message is received, returns ConnectionClose once the connection is closed
+    pub fn recv(&mut self) -> WebSocketResult<Message> {
+        loop {
+            if let Some(message) = self.recv_timeout(self.client.timeout())? { return Ok(message) }
+        }
+    }
+
+    // Wait until a message is received or the timeout expires (None), returns ConnectionClose once the connection is closed
+    pub fn recv_timeout(&mut self, timeout: Duration) -> WebSocketResult<Option<Message>> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(message) = self.client.pop_message() { return Ok(Some(message)) }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let ready = self.client.wait_for_events(remaining)?;
+            if !ready { return Ok(None) }
+
+            self.client.event_loop()?;
+        }
+    }
+
+    // Returns the protocol accepted by the server
+    pub fn protocol(&self) -> Option<&str> {
+        self.client.protocol()
+    }
+
+    // Returns who closed the connection, the status code and the reason once the connection is closed
+    pub fn close_reason(&self) -> Option<&Reason> {
+        self.client.close_reason()
+    }
+
+    // Access the client to use the event loop API
+    pub fn client(&mut self) -> &mut WSClient<'a, ()> {
+        &mut self.client
+    }
+}
@@ -1,25 +1,33 @@
-use std::net::{TcpStream, Shutdown};
-use std::io::{Write, ErrorKind};
-use std::collections::{HashMap, VecDeque};
-use std::time::{Instant, Duration};
+use std::net::{TcpStream, Shutdown, SocketAddr};
+use std::io::ErrorKind;
+use std::collections::VecDeque;
+use std::time::{Instant, Duration, SystemTime};
 use std::format;
+use std::convert::TryFrom;
 use core::marker::Send;
-use crate::core::net::read_into_buffer;
-use crate::result::WebSocketError;
-use crate::ws_basic::header::{OPCODE, FLAG};
-use crate::ws_basic::frame::{DataFrame, ControlFrame, Frame, FrameKind, bytes_to_frame};
-use crate::ws_basic::status_code::{WSStatus, evaulate_status_code};
+use crate::core::buffer::{RecvBuffer, SendBuffer};
+use crate::core::poll::{wait_socket, wait_socket_or_wakeup};
+use crate::core::net::write_vectored_from;
+use crate::core::handshake::{handshake_request, verify_response, negotiate_protocol, negotiate_extensions};
+use crate::core::recv::{DEFAULT_MESSAGE_SIZE, DEFAULT_MAX_FRAME_SIZE, DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_TIMEOUT, DEFAULT_READ_CHUNK_SIZE, check_recv_limits, rsv_allowed, fragment_allowed, decode_message, into_message};
+use crate::result::{WebSocketError, ProtocolError, CapacityError};
+use crate::ws_basic::header::{Header, OPCODE, FLAG, MAX_HEADER_LEN, header_len};
+use crate::ws_basic::mask::{Mask, apply_mask, gen_mask};
+use crate::codec::{FrameEncoder, OsRandom, SharedRandom, shared_random};
+use crate::ws_basic::frame::{DataFrame, ControlFrame, Frame, FrameKind, parse_frame, is_frame_complete};
+use crate::ws_basic::status_code::CloseCode;
 use crate::core::traits::{Serialize, Parse};
-use crate::core::binary::bytes_to_u16;
 use super::super::result::WebSocketResult;
-use crate::http::request::{Request, Method};
+use crate::http::request::Request;
 use crate::http::response::Response;
-use crate::ws_basic::key::{gen_key, verify_key};
-use crate::extension::Extension;
+use crate::extension::{Extension, ExtensionFrame, WebSocketExtension};
+use super::sender::{WSSender, Commands, Command};
+use super::split::{split, SplitParts, WSReader, WSWriter};
+use super::writer::MessageWriter;
+use super::stats::{Stats, Counters, MetricsHook};
 
-const DEFAULT_MESSAGE_SIZE: u64 = 1024;
-const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
-const SWITCHING_PROTOCOLS: u16 = 101;
+const MAX_SERVER_HEADER_LEN: usize = 10;                     // The frames of the server are not masked
+const CONTROL_FRAME_LEN: usize = MAX_HEADER_LEN + 125;      // Room kept in the fixed send buffer to answer a ping
 
 #[allow(non_camel_case_types)]
 #[derive(PartialEq)]
@@ -34,15 +42,39 @@ enum ConnectionStatus {
     CLOSE
 }
 
+// State of the connection returned by state(), the values don't change (also used by the FFI)
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub enum ConnectionState {
+    NOT_INIT = 0,                                            // init wasn't called
+    CONNECTING = 1,                                          // Waiting for the event loop to connect the socket
+    HANDSHAKE = 2,                                           // Handshake request sent, waiting for the response
+    OPEN = 3,
+    CLOSING = 4,                                             // Close handshake started by the client or the server
+    CLOSED = 5
+}
+
 #[allow(non_camel_case_types)]
 #[repr(C)]
 enum Event {
     WEBSOCKET_DATA(Box<dyn Frame>),
+    WEBSOCKET_MESSAGE(Vec<u8>),                              // Payload of all the frames of a message received
+    WEBSOCKET_FRAGMENT(Vec<u8>, bool),                       // Payload of a frame of a message delivered in fragments and if it's the last one
     HTTP_RESPONSE(Response),
     HTTP_REQUEST(Request),
     NO_DATA,
 }
 
+// Serialized event that the socket didn't accept completely, the next write starts at written.
+// The header and the payload are kept in separate buffers to write them with write_vectored
+struct PartialWrite {
+    frame: Option<Header>,                                  // Header of the frame, None for the http request
+    head: Vec<u8>,
+    payload: Vec<u8>,
+    written: usize
+}
+
 fn is_websocket_data(event: &Event) -> bool {
     match event {
         Event::WEBSOCKET_DATA(_) => true,
@@ -61,22 +93,61 @@ pub struct Config<'a, T: Clone> {
     pub callback: Option<fn(&mut WSClient<'a, T>, &WSEvent, Option<T>)>,
     pub data: Option<T>,
     pub protocols: Option<&'a[&'a str]>,
+    pub random: Option<SharedRandom>,                        // Masking keys and Sec-WebSocket-Key, the entropy of the OS (OsRandom) if None
 }
 
+// Who started the close of the connection, the status code and the reason (UTF-8 text, can be empty).
+// The status code is 1005 if the close frame didn't have a status code and 1006 if the connection was lost without a close frame
 #[allow(non_camel_case_types)]
-#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Reason {
-    SERVER_CLOSE(u16),
-    CLIENT_CLOSE(u16)
+    SERVER_CLOSE(CloseCode, String),
+    CLIENT_CLOSE(CloseCode, String)
+}
+
+impl Reason {
+    pub fn code(&self) -> CloseCode {
+        match self {
+            Reason::SERVER_CLOSE(code, _) => *code,
+            Reason::CLIENT_CLOSE(code, _) => *code
+        }
+    }
+
+    pub fn reason(&self) -> &str {
+        match self {
+            Reason::SERVER_CLOSE(_, reason) => reason.as_str(),
+            Reason::CLIENT_CLOSE(_, reason) => reason.as_str()
+        }
+    }
 }
 
 #[allow(non_camel_case_types)]
 pub enum WSEvent { 
     ON_CONNECT(Option<String>),
     ON_TEXT(String),
+    ON_BINARY(Vec<u8>),
     ON_CLOSE(Reason),
+    ON_DRAINED,                                              // All the messages queued were sent
+    ON_FRAGMENT { kind: MessageKind, data: Vec<u8>, is_final: bool }, // Payload of a frame of a message, only with set_stream_fragments
+}
+
+// Type of a data message, sent with begin_message or received in fragments
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MessageKind {
+    TEXT,                                                    // The bytes of the whole message are UTF-8
+    BINARY
 }
 
+// Message received, returned by the blocking client and the split reader
+#[derive(Clone, PartialEq, Debug)]
+pub enum Message {
+    TEXT(String),                                            // Valid UTF-8, the connection fails (1007) otherwise
+    BINARY(Vec<u8>)
+}
+
+// Identifies a message queued with send, the ids are consecutive starting at 0
+pub type MessageId = u64;
+
 #[allow(dead_code)]
 #[repr(C)]
 pub struct WSClient<'a, T: Clone> {
@@ -85,20 +156,57 @@ pub struct WSClient<'a, T: Clone> {
     path: &'a str,
     connection_status: ConnectionStatus,
     message_size: u64,
+    max_frame_size: u64,                                     // Max payload length accepted in a single frame received
+    max_message_size: u64,                                   // Max length accepted for a message received (sum of all the frames of the message)
     timeout: Duration,
+    handshake_start: Instant,                                // Time when the handshake request was sent, used to check the timeout
+    drain: bool,                                             // Process all the frames ready in a single call to event_loop
+    write_blocked: bool,                                     // The last write couldn't be done because the socket buffer is full
+    partial_write: Option<PartialWrite>,                     // Event being written, it goes before the rest of the output events
     stream: Option<TcpStream>,
-    recv_storage: Vec<u8>,                                   // Storage to keep the bytes received from the socket (bytes that didn't use to create a frame)
+    recv_storage: RecvBuffer,                                // Storage to keep the bytes received from the socket (bytes that didn't use to create a frame)
+    read_chunk_size: usize,                                  // Max bytes read from the socket in each read
     recv_data: Vec<u8>,                                      // Store the data received from the Frames until the data is completelly received
+    fixed_buffers: Option<(usize, usize)>,                   // Size of the receive and send buffers, they are allocated in init and never grow
+    send_buffer: Option<SendBuffer>,                         // Frames serialized to send with fixed buffers
+    random: SharedRandom,                                    // Masking keys of the frames and the Sec-WebSocket-Key
+    encoder: FrameEncoder<SharedRandom>,                     // Serialize the frames of the send buffer
     cb_data: Option<T>,
     callback: Option<fn(&mut Self, &WSEvent, Option<T>)>,
     protocol: Option<String>,
     acceptable_protocols: Option<&'a [&'a str]>,
-    extensions: Vec<Extension>,
+    require_protocol: bool,                                  // Fail the handshake if the server doesn't select a protocol
+    extensions: Vec<Extension>,                              // Extensions accepted by the server
+    extension_offers: Vec<Box<dyn WebSocketExtension>>,     // Extensions to offer in the handshake
+    negotiated_extensions: Vec<Box<dyn WebSocketExtension>>, // Extensions accepted by the server (in the order of the response)
+    rsv_bits: u8,                                            // Reserved bits claimed by the negotiated extensions
+    recv_opcode: OPCODE,                                     // Opcode of the first frame of the message being received
+    recv_rsv: u8,                                            // Reserved bits of the first frame of the message being received
+    recv_in_message: bool,                                   // A data frame without FIN was received, the next data frames must be CONTINUATION
+    stream_fragments: bool,                                  // Deliver the frames of the messages received with ON_FRAGMENT instead of the whole message
+    recv_streaming: bool,                                    // The message being received is delivered in fragments
+    recv_utf8_tail: Vec<u8>,                                 // Bytes of a character split between two fragments of a text message
+    close_reason: Option<Reason>,                            // Close received from the server (reported once the close frame of the client is sent) or close of the connection
+    messages: VecDeque<Message>,                             // Messages received without callback, returned by the blocking client
+    commands: Option<Commands>,                              // Frames sent from other threads through the handles returned by sender()
+    max_queued_messages: usize,                              // Max messages waiting to be sent, send fails with QueueFull once reached
+    max_queued_bytes: usize,                                 // Max payload bytes waiting to be sent
+    queued_messages: usize,                                  // Messages with frames not sent yet
+    queued_bytes: usize,                                     // Payload bytes of the data frames not sent yet
+    next_message_id: MessageId,
+    streaming: bool,                                         // A message is being written with begin_message
+    held_output: VecDeque<Event>,                            // Messages queued while a message is streamed, they go after its FIN frame
+    held_bytes: usize,                                       // Payload bytes of the messages held
     input_events: VecDeque<Event>,
     output_events: VecDeque<Event>,
     websocket_key: String,
     close_iters: usize,                                      // Count the number of times send_message tries to execute after the close. If <= 1 don't raise error, otherwise raise ConnectionClose error 
-}                                                            // The close connection depends on the order of the functions event_loop and is_connected
+    connected_at: Option<(SystemTime, Instant)>,             // When the handshake was done
+    closed_at: Option<Instant>,                              // When the connection was closed, the uptime stops
+    counters: Counters,                                      // Statistics of the connection
+    metrics_hook: Option<(MetricsHook<T>, Duration)>,       // Export the statistics every interval
+    last_report: Instant,
+}                                                            // The close connection depends on the order of the functions event_loop and is_open
                         
 
 impl<'a, T> WSClient<'a, T> where T: Clone {
@@ -109,16 +217,53 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
             path: "", 
             connection_status: ConnectionStatus::NOT_INIT, 
             message_size: DEFAULT_MESSAGE_SIZE, 
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
             stream: None, 
-            recv_storage: Vec::new(), 
+            recv_storage: RecvBuffer::new(), 
+            read_chunk_size: DEFAULT_READ_CHUNK_SIZE,
             recv_data: Vec::new(), 
+            fixed_buffers: None,
+            send_buffer: None,
+            random: shared_random(OsRandom),
+            encoder: FrameEncoder::new(shared_random(OsRandom)),
             timeout: DEFAULT_TIMEOUT, 
+            handshake_start: Instant::now(),
+            drain: false,
+            write_blocked: false,
+            partial_write: None,
             cb_data: None,
             callback: None,
             protocol: None,
             acceptable_protocols: None,
+            require_protocol: false,
             extensions: Vec::new(),
+            extension_offers: Vec::new(),
+            negotiated_extensions: Vec::new(),
+            rsv_bits: 0,
+            recv_opcode: OPCODE::TEXT,
+            recv_in_message: false,
+            recv_rsv: 0,
+            stream_fragments: false,
+            recv_streaming: false,
+            recv_utf8_tail: Vec::new(),
+            close_reason: None,
+            messages: VecDeque::new(),
+            commands: None,
+            max_queued_messages: usize::MAX,
+            max_queued_bytes: usize::MAX,
+            queued_messages: 0,
+            queued_bytes: 0,
+            next_message_id: 0,
+            streaming: false,
+            held_output: VecDeque::new(),
+            held_bytes: 0,
             close_iters: 0,
+            connected_at: None,
+            closed_at: None,
+            counters: Counters::default(),
+            metrics_hook: None,
+            last_report: Instant::now(),
             input_events: VecDeque::new(),
             output_events: VecDeque::new(),
             websocket_key: String::new(),
@@ -126,6 +271,7 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
     }
 
     pub fn init(&mut self, host: &'a str, port: u16, path: &'a str, config: Option<Config<'a, T>>) {
+        if self.connection_status != ConnectionStatus::NOT_INIT { self.counters.stats.reconnects += 1 }
         self.host = host;
         self.port = port;
         self.path = path; 
@@ -134,6 +280,22 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
             self.cb_data = conf.data;
             self.callback = conf.callback;
             self.acceptable_protocols = conf.protocols;
+            if let Some(random) = conf.random {
+                self.encoder = FrameEncoder::new(random.clone());
+                self.random = random;
+            }
+        }
+
+        // The results of the last handshake don't apply to the new connection
+        self.protocol = None;
+        self.extensions.clear();
+        self.negotiated_extensions.clear();
+        self.rsv_bits = 0;
+
+        if let Some((recv_size, send_size)) = self.fixed_buffers {
+            self.recv_storage = RecvBuffer::fixed(recv_size);
+            self.recv_data = Vec::with_capacity(recv_size);
+            self.send_buffer = Some(SendBuffer::with_capacity(send_size.max(2 * CONTROL_FRAME_LEN)));
         }
 
         self.connection_status = ConnectionStatus::START_INIT;
@@ -141,28 +303,8 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
 
     fn start_init(&mut self) -> WebSocketResult<()> {
         let socket = TcpStream::connect(format!("{}:{}", self.host, self.port.to_string()));
-        if socket.is_err() { return Err(WebSocketError::UnreachableHost)} 
-        let sec_websocket_key = gen_key();
-        
-        let mut headers: HashMap<String, String> = HashMap::from([
-            (String::from("Upgrade"), String::from("websocket")),
-            (String::from("Connection"), String::from("Upgrade")),
-            (String::from("Sec-WebSocket-Key"), sec_websocket_key.clone()),
-            (String::from("Sec-WebSocket-Version"), String::from("13")),
-            (String::from("User-agent"), String::from("rust-websocket-std")),
-        ]);
-
-        // Add protocols to request
-        let mut protocols_value = String::new();
-        if let Some(protocols) = self.acceptable_protocols {
-            for p in protocols {
-                protocols_value.push_str(p);
-                protocols_value.push_str(", ");
-            }
-            headers.insert(String::from("Sec-WebSocket-Protocol"), (&(protocols_value)[0..protocols_value.len()-2]).to_string());
-        }
-        
-        let request = Request::new(Method::GET, self.path, "HTTP/1.1", Some(headers));
+        if socket.is_err() { return Err(WebSocketError::UnreachableHost(socket.err().unwrap())) }
+        let (request, sec_websocket_key) = handshake_request(self.path, self.acceptable_protocols, &self.extension_offers, &mut self.random)?;
         
         self.output_events.push_front(Event::HTTP_REQUEST(request)); // Push front, because the client could execute send before init (store the frames to send to do it later)
         self.websocket_key = sec_websocket_key;
@@ -170,59 +312,642 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
         socket.set_nonblocking(true)?;
         self.stream = Some(socket);
         self.connection_status = ConnectionStatus::HANDSHAKE;
+        self.handshake_start = Instant::now();
             
         Ok(())
     }
 
+    // Returns the state of the connection
+    pub fn state(&self) -> ConnectionState {
+        match self.connection_status {
+            ConnectionStatus::NOT_INIT => ConnectionState::NOT_INIT,
+            ConnectionStatus::START_INIT => ConnectionState::CONNECTING,
+            ConnectionStatus::HANDSHAKE => ConnectionState::HANDSHAKE,
+            ConnectionStatus::OPEN => ConnectionState::OPEN,
+            ConnectionStatus::CLIENT_WANTS_TO_CLOSE | ConnectionStatus::SERVER_WANTS_TO_CLOSE => ConnectionState::CLOSING,
+            ConnectionStatus::CLOSE => ConnectionState::CLOSED
+        }
+    }
+
+    // Returns true once the handshake is done until the close handshake starts, the messages can be sent
+    pub fn is_open(&self) -> bool {
+        self.connection_status == ConnectionStatus::OPEN
+    }
+
+    // Returns the address of the server, None if the socket is not connected (the connection is closed)
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.stream.as_ref().and_then(|stream| stream.peer_addr().ok())
+    }
+
+    // Returns the address of the socket of the client, None if the socket is not connected
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.stream.as_ref().and_then(|stream| stream.local_addr().ok())
+    }
+
+    // Socket of the connection to register in an external reactor (mio, epoll...). It's created in the first call to
+    // event_loop (or on_readable/on_writable) after init and closed with the connection, None before and after.
+    // Keep the value registered to deregister it
+    #[cfg(unix)]
+    pub fn raw_fd(&self) -> Option<std::os::unix::io::RawFd> {
+        use std::os::unix::io::AsRawFd;
+        self.stream.as_ref().map(|stream| stream.as_raw_fd())
+    }
+
+    // Same as raw_fd for the reactors of windows
+    #[cfg(windows)]
+    pub fn raw_socket(&self) -> Option<std::os::windows::io::RawSocket> {
+        use std::os::windows::io::AsRawSocket;
+        self.stream.as_ref().map(|stream| stream.as_raw_socket())
+    }
+
+    // Returns when the handshake was done, None if the connection was never open
+    pub fn connect_time(&self) -> Option<SystemTime> {
+        self.connected_at.map(|(time, _)| time)
+    }
+
+    // Returns the time since the handshake was done, once the connection is closed the time it was open
+    pub fn uptime(&self) -> Option<Duration> {
+        let (_, start) = self.connected_at?;
+        Some(self.closed_at.unwrap_or_else(Instant::now).duration_since(start))
+    }
+
+    // Returns a copy of the statistics of the connection
+    pub fn stats(&self) -> Stats {
+        self.counters.stats
+    }
+
+    // Call the hook with the statistics every interval from the event loop and once the connection is closed,
+    // to export them to a metrics backend
+    pub fn set_metrics_hook(&mut self, hook: MetricsHook<T>, interval: Duration) {
+        self.metrics_hook = Some((hook, interval));
+        self.last_report = Instant::now();
+    }
+
     // Returns the protocol accepted by the server
     pub fn protocol(&self) -> Option<&str> {
         if self.protocol.is_none() { return None };
         return Some(self.protocol.as_ref().unwrap().as_str());
     }
 
+    // Returns the extensions accepted by the server
+    pub fn extensions(&self) -> &[Extension] {
+        self.extensions.as_slice()
+    }
+
+    // Returns who closed the connection, the status code and the reason once the connection is closed
+    pub fn close_reason(&self) -> Option<&Reason> {
+        if self.connection_status != ConnectionStatus::CLOSE { return None }
+        self.close_reason.as_ref()
+    }
+
+    // Fail the handshake if the server doesn't select any of the protocols offered
+    pub fn set_require_protocol(&mut self, require: bool) {
+        self.require_protocol = require;
+    }
+
+    // Max payload length of the frames sent, the messages are split in frames of this size (at least 1 byte)
     pub fn set_message_size(&mut self, size: u64) {
-        self.message_size = size;
+        self.message_size = size.max(1);
+    }
+
+    // Max payload length of a frame received, if the server announces a bigger frame the connection will be closed (1009)
+    pub fn set_max_frame_size(&mut self, size: u64) {
+        self.max_frame_size = size;
+    }
+
+    // Max length of a message received, if the server sends a bigger message the connection will be closed (1009)
+    pub fn set_max_message_size(&mut self, size: u64) {
+        self.max_message_size = size;
+    }
+
+    // Offer an extension to the server, must be called before init. The extensions are offered in the order they are added.
+    // Messages sent before the handshake is completed don't go through the extensions
+    pub fn add_extension(&mut self, extension: Box<dyn WebSocketExtension>) {
+        self.extension_offers.push(extension);
+    }
+
+    // Max bytes read from the socket in each read (4KB by default). Bigger chunks need less reads for big messages,
+    // the receive buffer always keeps room for a chunk
+    pub fn set_read_chunk_size(&mut self, size: usize) {
+        self.read_chunk_size = size.max(1);
+    }
+
+    // Deliver each frame of the messages received with ON_FRAGMENT as it arrives, the messages are not kept in memory
+    // and max_message_size doesn't apply (max_frame_size does). Needs a callback. The messages transformed by an extension
+    // (compressed) are received whole and delivered in a single fragment
+    pub fn set_stream_fragments(&mut self, stream: bool) {
+        self.stream_fragments = stream;
+    }
+
+    // Process all the frames ready in each call to event_loop instead of one input and one output event
+    pub fn set_drain(&mut self, drain: bool) {
+        self.drain = drain;
+    }
+
+    // Buffers of a fixed size for devices with little memory, must be called before init. The buffers are allocated in init
+    // and nothing is allocated to send and receive messages once the connection is open (with a callback and without extensions).
+    // The receive buffer holds the handshake response and a whole frame, the frames and messages received are limited to its size
+    // (FrameTooBig/MessageTooBig, 1009). The messages sent are serialized in the send buffer: send fails with QueueFull if the message
+    // doesn't fit in the free space and with SendBufferTooSmall if it never fits. Part of the send buffer is kept to answer the pings,
+    // a ping is not answered if there's no room (only the last ping needs an answer). The send buffer has 278 bytes at least
+    pub fn set_fixed_buffers(&mut self, recv_size: usize, send_size: usize) {
+        self.fixed_buffers = Some((recv_size, send_size));
     }
 
     pub fn set_timeout(&mut self, timeout: Duration) {
         self.timeout = timeout;
     }
 
-    pub fn send(&mut self, payload: &str) {
-        // If connection is close do nothing
-        if self.connection_status == ConnectionStatus::CLOSE { return }
+    // Max messages waiting to be sent, there's no limit by default
+    pub fn set_max_queued_messages(&mut self, max: usize) {
+        self.max_queued_messages = max;
+    }
+
+    // Max payload bytes waiting to be sent, there's no limit by default. A message bigger than the limit
+    // can only be queued when the queue is empty
+    pub fn set_max_queued_bytes(&mut self, max: usize) {
+        self.max_queued_bytes = max;
+    }
+
+    // Returns the payload bytes of the messages waiting to be sent (after the transformation of the extensions)
+    pub fn queued_bytes(&self) -> usize {
+        self.queued_bytes
+    }
+
+    // Returns the number of messages waiting to be sent
+    pub fn queued_messages(&self) -> usize {
+        self.queued_messages
+    }
+
+    // Queue a text message and return its id, ON_DRAINED is notified when all the messages queued are sent.
+    // Fails with QueueFull if the message doesn't fit in the queue (nothing is queued) and with ConnectionClose if the connection is closing or closed
+    pub fn send(&mut self, payload: &str) -> WebSocketResult<MessageId> {
+        if !self.accepts_messages() { return Err(WebSocketError::ConnectionClose) }
+        if !self.queue_has_room(payload.len()) { return Err(WebSocketError::QueueFull) }
+        // The text is serialized in the send buffer without a copy
+        if self.send_buffer.is_some() { return self.buffer_message(OPCODE::TEXT, payload.as_bytes()) }
+
+        self.queue_message(OPCODE::TEXT, payload.as_bytes().to_vec())
+    }
+
+    // Queue a binary message, same results as send. The client takes the buffer: if the message fits in a frame (message_size)
+    // it's masked in place and written with the header, the payload is never copied
+    pub fn send_binary(&mut self, payload: Vec<u8>) -> WebSocketResult<MessageId> {
+        if !self.accepts_messages() { return Err(WebSocketError::ConnectionClose) }
+        if !self.queue_has_room(payload.len()) { return Err(WebSocketError::QueueFull) }
+
+        self.queue_message(OPCODE::BINARY, payload)
+    }
+
+    // Start a message that is written in several frames with the writer returned, for messages that don't fit in memory.
+    // The message counts in the queue limits from now, fails like send and with WouldBlock if another message is being streamed
+    // (from the callback while a write of the writer runs the event loop)
+    pub fn begin_message(&mut self, kind: MessageKind) -> WebSocketResult<MessageWriter<'_, 'a, T>> {
+        if !self.accepts_messages() { return Err(WebSocketError::ConnectionClose) }
+        if self.streaming { return Err(WebSocketError::IOError(ErrorKind::WouldBlock.into())) }
+        if !self.queue_has_room(0) { return Err(WebSocketError::QueueFull) }
+
+        self.streaming = true;
+        let id = self.message_queued(0);
+        Ok(MessageWriter::new(self, kind, id))
+    }
+
+    // Data messages can be queued until the close handshake starts
+    fn accepts_messages(&self) -> bool {
+        self.connection_status != ConnectionStatus::CLIENT_WANTS_TO_CLOSE &&
+            self.connection_status != ConnectionStatus::SERVER_WANTS_TO_CLOSE &&
+            self.connection_status != ConnectionStatus::CLOSE
+    }
+
+    // Queue a frame of the message streamed, the FIN frame ends the message and releases the messages held
+    pub(crate) fn queue_fragment(&mut self, opcode: OPCODE, payload: &[u8], fin: bool) -> WebSocketResult<()> {
+        if !self.accepts_messages() || !self.streaming {
+            self.streaming = false;
+            self.held_output.clear();
+            self.held_bytes = 0;
+            return Err(WebSocketError::ConnectionClose);
+        }
+
+        let flag = if fin { FLAG::FIN } else { FLAG::NOFLAG };
+        match self.send_buffer.as_mut() {
+            Some(buffer) => match buffer_frame(&mut self.encoder, buffer, &mut self.counters, flag, opcode, payload) {
+                Ok(true) => {},
+                Ok(false) => return Err(WebSocketError::QueueFull),
+                Err(e) => return self.fail_connection(e)
+            },
+            None => {
+                let frame = DataFrame::new(flag, opcode, payload.to_vec(), Some(self.new_mask()?));
+                self.output_events.push_back(Event::WEBSOCKET_DATA(Box::new(frame)));
+            }
+        }
+        self.queued_bytes += payload.len();
+        self.counters.queued(self.queued_messages, self.queued_bytes);
+
+        if fin {
+            self.streaming = false;
+            self.held_bytes = 0;
+            self.output_events.extend(self.held_output.drain(..));
+        }
+        Ok(())
+    }
+
+    // The writer was dropped without finishing the message: queue the FIN frame, if it doesn't fit (fixed buffers)
+    // the message can't be ended and the connection fails, otherwise the client would stay streaming
+    pub(crate) fn end_fragments(&mut self, opcode: OPCODE) {
+        if let Err(WebSocketError::QueueFull) = self.queue_fragment(opcode, &[], true) {
+            let _ = self.fail_connection::<()>(WebSocketError::QueueFull);
+        }
+    }
+
+    // The bytes limit for the frames of a message streamed, the message is already counted.
+    // The messages held can't be sent before the message ends, they don't take room from its frames
+    fn fragment_has_room(&self, len: usize) -> bool {
+        if let Some(buffer) = &self.send_buffer { return buffer.free() >= MAX_HEADER_LEN + len + CONTROL_FRAME_LEN }
+        let queued = self.queued_bytes.saturating_sub(self.held_bytes);
+        queued == 0 || queued.saturating_add(len) <= self.max_queued_bytes
+    }
+
+    // Run the event loop until a frame of len bytes fits in the queue, fails with Timeout after the timeout of the client
+    pub(crate) fn wait_for_room(&mut self, len: usize) -> WebSocketResult<()> {
+        let deadline = Instant::now() + self.timeout;
+        while !self.fragment_has_room(len) {
+            if !self.accepts_messages() { return Err(WebSocketError::ConnectionClose) }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() { return Err(WebSocketError::Timeout) }
+            self.event_loop_timeout(remaining)?;
+        }
+        Ok(())
+    }
+
+    // Run the event loop until the frames queued are written, fails with Timeout after the timeout of the client
+    pub(crate) fn flush_output(&mut self) -> WebSocketResult<()> {
+        let deadline = Instant::now() + self.timeout;
+        while self.has_output() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() { return Err(WebSocketError::Timeout) }
+            self.event_loop_timeout(remaining)?;
+        }
+        Ok(())
+    }
+
+    // The bytes limit is checked with the payload before the extensions, a message always fits in an empty queue
+    fn queue_has_room(&self, len: usize) -> bool {
+        if self.queued_messages == 0 { return self.max_queued_messages > 0 }
+        self.queued_messages < self.max_queued_messages && self.queued_bytes.saturating_add(len) <= self.max_queued_bytes
+    }
+
+    // Returns a handle to send messages from other threads, the handles can be cloned
+    pub fn sender(&mut self) -> WSSender {
+        let commands = self.commands.get_or_insert_with(Commands::new);
+        if self.connection_status == ConnectionStatus::CLOSE { commands.close() }
+        commands.sender()
+    }
+
+    // Split an open connection in a reader and a writer that can be used from different threads.
+    // The frames queued are sent before returning. The halves don't call the callback and the handles returned by sender() are closed
+    pub fn split(mut self) -> WebSocketResult<(WSReader, WSWriter)> {
+        if self.connection_status != ConnectionStatus::OPEN { return Err(WebSocketError::ConnectionClose) }
+
+        let mut pending = Vec::new();
+        if let Some(partial) = self.partial_write.take() {
+            let (head, payload) = (partial.head.as_slice(), partial.payload.as_slice());
+            let written = partial.written;
+            if written < head.len() { pending.extend_from_slice(&head[written..]) }
+            pending.extend_from_slice(&payload[written.saturating_sub(head.len())..]);
+        }
+        if let Some(buffer) = &self.send_buffer { pending.extend_from_slice(buffer.pending()) }
+        for event in self.output_events.drain(..) {
+            if let Event::WEBSOCKET_DATA(frame) = event {
+                let (_, head, payload) = frame.into_serialized().into_parts();
+                pending.extend_from_slice(&head);
+                pending.extend_from_slice(&payload);
+            }
+        }
+
+        let parts = SplitParts {
+            stream: self.stream.take().unwrap(),
+            pending,
+            timeout: self.timeout,
+            message_size: self.message_size,
+            max_frame_size: self.max_frame_size,
+            max_message_size: self.max_message_size,
+            read_chunk_size: self.read_chunk_size,
+            recv_storage: std::mem::take(&mut self.recv_storage),
+            recv_data: std::mem::take(&mut self.recv_data),
+            recv_opcode: self.recv_opcode.clone(),
+            recv_rsv: self.recv_rsv,
+            recv_in_message: self.recv_in_message,
+            rsv_bits: self.rsv_bits,
+            extensions: std::mem::take(&mut self.negotiated_extensions),
+            messages: std::mem::take(&mut self.messages),
+            random: self.random.clone()
+        };
+
+        // The connection belongs to the halves now, drop doesn't close it
+        self.connection_status = ConnectionStatus::CLOSE;
+        split(parts)
+    }
+
+    // Queue the frames sent by the handles, stop after a close or when the queue is full (the rest wait in the channel).
+    // With fixed buffers a command waits until the send buffer is empty and the message streamed ends.
+    // A message that can't be queued is dropped and the error is returned to the event loop
+    fn receive_commands(&mut self) -> WebSocketResult<()> {
+        while self.connection_status == ConnectionStatus::OPEN && self.queue_has_room(0) && !self.buffer_pending() {
+            if self.streaming && self.send_buffer.is_some() { return Ok(()) }
+            let command = match self.commands.as_ref().and_then(|c| c.try_recv()) {
+                Some(command) => command,
+                None => return Ok(())
+            };
+
+            match command {
+                Command::Text(payload) => { self.queue_message(OPCODE::TEXT, payload.into_bytes())?; },
+                Command::Binary(payload) => { self.queue_message(OPCODE::BINARY, payload)?; },
+                Command::Ping(payload) => {
+                    let ping_frame = ControlFrame::new(FLAG::FIN, OPCODE::PING, None, payload, Some(self.new_mask()?));
+                    self.output_events.push_back(Event::WEBSOCKET_DATA(Box::new(ping_frame)));
+                },
+                Command::Close(code, reason) => {
+                    let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, Some(code.into()), reason.into_bytes(), Some(self.new_mask()?));
+                    self.output_events.push_back(Event::WEBSOCKET_DATA(Box::new(close_frame)));
+                    self.connection_status = ConnectionStatus::CLIENT_WANTS_TO_CLOSE;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Split the message in frames of message_size after the transformation of the extensions
+    fn queue_message(&mut self, opcode: OPCODE, payload: Vec<u8>) -> WebSocketResult<MessageId> {
+        if self.connection_status == ConnectionStatus::CLOSE { return Err(WebSocketError::ConnectionClose) }
+        if self.send_buffer.is_some() { return self.buffer_message(opcode, &payload) }
+        let ExtensionFrame { rsv, payload, .. } = self.encode(ExtensionFrame::new(opcode.bits(), 0, payload))?;
+        let len = payload.len();
+
+        // A message that fits in a frame moves the buffer into the frame
+        if len as u64 <= self.message_size {
+            let frame = DataFrame::new(FLAG::FIN | FLAG::from_bits_truncate(rsv), opcode, payload, Some(self.new_mask()?));
+            self.message_output().push_back(Event::WEBSOCKET_DATA(Box::new(frame)));
+            if self.streaming { self.held_bytes += len }
+            return Ok(self.message_queued(len));
+        }
+
         let mut data_sent = 0;
         let mut _i: usize = 0;
 
         while data_sent < payload.len() {
             _i = data_sent + self.message_size as usize; 
             if _i >= payload.len() { _i = payload.len() };
-            let payload_chunk = payload[data_sent.._i].as_bytes();
-            let flag = if data_sent + self.message_size as usize >= payload.len() { FLAG::FIN } else { FLAG::NOFLAG };
-            let code = if data_sent == 0 { OPCODE::TEXT } else { OPCODE::CONTINUATION };
-            let frame = DataFrame::new(flag, code, payload_chunk.to_vec(), true, None);
-            self.output_events.push_back(Event::WEBSOCKET_DATA(Box::new(frame)));
+            let payload_chunk = &payload[data_sent.._i];
+            let mut flag = if data_sent + self.message_size as usize >= payload.len() { FLAG::FIN } else { FLAG::NOFLAG };
+            // The reserved bits set by the extensions go in the first frame of the message
+            if data_sent == 0 { flag |= FLAG::from_bits_truncate(rsv) }
+            let code = if data_sent == 0 { opcode.clone() } else { OPCODE::CONTINUATION };
+            let frame = DataFrame::new(flag, code, payload_chunk.to_vec(), Some(self.new_mask()?));
+            self.message_output().push_back(Event::WEBSOCKET_DATA(Box::new(frame)));
             data_sent += self.message_size as usize;
         }
+
+        if self.streaming { self.held_bytes += len }
+        Ok(self.message_queued(len))
+    }
+
+    // Serialize the frames of the message (message_size bytes each) in the send buffer, the buffer is checked before writing anything.
+    // The messages can't go between the frames of a message streamed, there's no room to hold them
+    fn buffer_message(&mut self, opcode: OPCODE, payload: &[u8]) -> WebSocketResult<MessageId> {
+        if self.connection_status == ConnectionStatus::CLOSE { return Err(WebSocketError::ConnectionClose) }
+        if self.streaming { return Err(WebSocketError::QueueFull) }
+
+        // The extensions transform the message in a new buffer
+        let encoded;
+        let (rsv, payload) = match self.negotiated_extensions.is_empty() {
+            true => (0, payload),
+            false => {
+                let frame = self.encode(ExtensionFrame::new(opcode.bits(), 0, payload.to_vec()))?;
+                encoded = frame.payload;
+                (frame.rsv, encoded.as_slice())
+            }
+        };
+
+        let frame_size = (self.message_size as usize).max(1);
+        let needed = frames_len(payload.len(), frame_size) + CONTROL_FRAME_LEN;
+        let buffer = self.send_buffer.as_mut().unwrap();
+        if needed > buffer.capacity() {
+            return Err(CapacityError::SendBufferTooSmall { max: (buffer.capacity() - CONTROL_FRAME_LEN) as u64 }.into());
+        }
+        if needed > buffer.free() { return Err(WebSocketError::QueueFull) }
+
+        let mut data_sent = 0;
+        let mut result = Ok(true);
+        while result.is_ok() {
+            let end = (data_sent + frame_size).min(payload.len());
+            let mut flag = if end == payload.len() { FLAG::FIN } else { FLAG::NOFLAG };
+            // The reserved bits set by the extensions go in the first frame of the message
+            if data_sent == 0 { flag |= FLAG::from_bits_truncate(rsv) }
+            let code = if data_sent == 0 { opcode.clone() } else { OPCODE::CONTINUATION };
+            result = buffer_frame(&mut self.encoder, buffer, &mut self.counters, flag, code, &payload[data_sent..end]);
+            data_sent = end;
+            if data_sent == payload.len() { break }
+        }
+        // Part of the message is in the buffer, the connection can't continue
+        if let Err(e) = result { return self.fail_connection(e) }
+
+        Ok(self.message_queued(payload.len()))
+    }
+
+    // Count the message in the queue and return its id
+    fn message_queued(&mut self, len: usize) -> MessageId {
+        self.queued_messages += 1;
+        self.queued_bytes += len;
+        self.counters.queued(self.queued_messages, self.queued_bytes);
+        let id = self.next_message_id;
+        self.next_message_id += 1;
+        id
+    }
+
+    // Drop the frames waiting to be sent, the messages queued won't be notified as drained
+    fn clear_output(&mut self) {
+        self.output_events.clear();
+        if let Some(buffer) = self.send_buffer.as_mut() {
+            // The frame partly written is completed before the next one (the rest of the buffer goes with it)
+            if buffer.is_writing() && self.partial_write.is_none() {
+                self.partial_write = Some(PartialWrite { frame: None, head: buffer.pending().to_vec(), payload: Vec::new(), written: 0 });
+            }
+            buffer.clear();
+        }
+        self.held_output.clear();
+        self.held_bytes = 0;
+        self.streaming = false;
+        self.queued_messages = 0;
+        self.queued_bytes = 0;
+    }
+
+    // Transform the message to send with the negotiated extensions
+    fn encode(&mut self, frame: ExtensionFrame) -> WebSocketResult<ExtensionFrame> {
+        let mut frame = frame;
+        for extension in self.negotiated_extensions.iter_mut() {
+            extension.encode(&mut frame)?;
+        }
+        Ok(frame)
+    }
+
+    // Transform the message received with the negotiated extensions (reverse order)
+    fn decode(&mut self, data: Vec<u8>) -> WebSocketResult<Vec<u8>> {
+        match decode_message(&mut self.negotiated_extensions, self.recv_opcode.bits(), self.recv_rsv, data, self.max_message_size) {
+            Ok(data) => Ok(data),
+            Err(e) => self.fail_connection(e)
+        }
     }
 
     pub fn event_loop(&mut self) -> WebSocketResult<()> {
+        self.advance(|ws| if ws.drain { ws.drain_events() } else { ws.handle_events().map(|_| ()) })
+    }
+
+    // Entry point for external reactors (mio, epoll...) when the socket is readable.
+    // Read all the data available and handle the frames received, the frames to send are only queued (wants_write)
+    pub fn on_readable(&mut self) -> WebSocketResult<()> {
+        self.advance(WSClient::handle_readable)
+    }
+
+    // Entry point for external reactors (mio, epoll...) when the socket is writable.
+    // Send the queued frames until the queue is empty or the socket can't accept more data
+    pub fn on_writable(&mut self) -> WebSocketResult<()> {
+        self.advance(WSClient::handle_writable)
+    }
+
+    // Returns if the client needs to know when the socket is readable
+    pub fn wants_read(&self) -> bool {
+        self.stream.is_some() && self.connection_status != ConnectionStatus::CLOSE
+    }
+
+    // Returns if the client has data to send and needs to know when the socket is writable
+    pub fn wants_write(&self) -> bool {
+        self.stream.is_some() && self.has_output()
+    }
+
+    // Check the state and timers of the connection, run the handler and detect if the connection was lost
+    fn advance(&mut self, handler: fn(&mut Self) -> WebSocketResult<()>) -> WebSocketResult<()> {
         if self.connection_status == ConnectionStatus::NOT_INIT { return Ok(()) }
         if self.connection_status == ConnectionStatus::START_INIT { return self.start_init()}
         if self.connection_status == ConnectionStatus::CLOSE { return Err(WebSocketError::ConnectionClose) }
-    
-        let event = self.read_bytes_from_socket()?;
+
+        // The server didn't answer the handshake in time
+        if self.connection_status == ConnectionStatus::HANDSHAKE && self.handshake_start.elapsed() >= self.timeout {
+            self.connection_status = ConnectionStatus::CLOSE;
+            if let Some(stream) = self.stream.take() {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            return Err(WebSocketError::Timeout);
+        }
+
+        if self.connection_status == ConnectionStatus::OPEN { self.receive_commands()? }
+        let result = handler(self);
+
+        // The connection was lost without the close handshake
+        if let Err(WebSocketError::ConnectionClose | WebSocketError::IOError(_)) = result {
+            if self.connection_status != ConnectionStatus::HANDSHAKE {
+                let reason = match self.connection_status {
+                    ConnectionStatus::CLIENT_WANTS_TO_CLOSE => Reason::CLIENT_CLOSE(CloseCode::AbnormalClosure, String::new()),
+                    _ => Reason::SERVER_CLOSE(CloseCode::AbnormalClosure, String::new())
+                };
+                self.close_connection(reason);
+            }
+        }
+
+        self.report_metrics(false);
+        return result;
+    }
+
+    // Same as event_loop, but if there's nothing to do, it waits until the socket is readable (or writable if there are frames to send),
+    // the timeout expires or a timer of the connection expires (e.g: handshake timeout)
+    pub fn event_loop_timeout(&mut self, timeout: Duration) -> WebSocketResult<()> {
+        if self.connection_status == ConnectionStatus::NOT_INIT || self.connection_status == ConnectionStatus::START_INIT {
+            return self.event_loop();
+        }
+
+        let mut timeout = timeout;
+        if self.connection_status == ConnectionStatus::HANDSHAKE {
+            let handshake_deadline = self.timeout.saturating_sub(self.handshake_start.elapsed());
+            timeout = timeout.min(handshake_deadline);
+        }
+
+        self.wait_for_events(timeout)?;
+        self.event_loop()
+    }
+
+    // Handle one input and one output event, returns the bytes read from the socket
+    fn handle_events(&mut self) -> WebSocketResult<usize> {
+        let bytes_read = self.read_socket()?;
+        let event = self.next_input_event()?;
         self.insert_input_event(event);
         
         let in_event = self.input_events.pop_front();     
         // Check that the message taken from the queue is not a websocket event and the state of the websocket is different
         // - if the state is HANDSHAKE dont pop an event if is a websocket event
-        let out_event = self.pop_output_event();
+        // - if a write is in progress, finish it before taking the next event
+        let out_event = if self.partial_write.is_some() || self.buffer_pending() { None } else { self.pop_output_event() };
 
         if in_event.is_some() { self.handle_event(in_event.unwrap(), EventIO::INPUT)? };
+
+        // The input closed the connection
+        if self.connection_status == ConnectionStatus::CLOSE { return Ok(bytes_read) }
+
+        if self.resume_write()? {
+            // The input added frames to the send buffer (pong), they go before the event
+            if let Some(event) = out_event { self.output_events.push_front(event) }
+            return Ok(bytes_read)
+        }
         if out_event.is_some() { self.handle_event(out_event.unwrap(), EventIO::OUTPUT)? };
 
-        return Ok(())
+        return Ok(bytes_read)
+    }
+
+    // Read until the socket doesn't have more data and handle all the input events.
+    // The data already received is handled before reporting an error of the socket
+    fn handle_readable(&mut self) -> WebSocketResult<()> {
+        let mut read_result = Ok(());
+        loop {
+            match self.read_socket() {
+                Ok(0) => break,
+                Ok(_) => continue,
+                Err(e) => { read_result = Err(e); break }
+            }
+        }
+
+        while self.connection_status != ConnectionStatus::CLOSE {
+            let event = self.next_input_event()?;
+            self.insert_input_event(event);
+
+            match self.input_events.pop_front() {
+                Some(event) => self.handle_event(event, EventIO::INPUT)?,
+                None => break
+            }
+        }
+
+        read_result
+    }
+
+    // Send the queued events until the queue is empty or the socket is full
+    fn handle_writable(&mut self) -> WebSocketResult<()> {
+        while self.connection_status != ConnectionStatus::CLOSE {
+            if !self.resume_write()? {
+                match self.pop_output_event() {
+                    Some(event) => self.handle_event(event, EventIO::OUTPUT)?,
+                    None => break
+                }
+            }
+            if self.write_blocked { break }
+        }
+
+        Ok(())
+    }
+
+    // Handle events until there's no more data to read and no frames to send (or the socket can't accept more data)
+    fn drain_events(&mut self) -> WebSocketResult<()> {
+        loop {
+            let bytes_read = self.handle_events()?;
+            if self.connection_status == ConnectionStatus::CLOSE { return Ok(()) }
+            if bytes_read == 0 && !self.has_pending_events() { return Ok(()) }
+        }
     }
 
     fn pop_output_event(&mut self) -> Option<Event> {
@@ -238,67 +963,173 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
     }
 
     fn handle_recv_bytes_frame(&mut self) -> WebSocketResult<Event> {
-        let frame = bytes_to_frame(&self.recv_storage)?;
-        if frame.is_none() { return Ok(Event::NO_DATA) };
+        // Check the limits with the header before buffering the payload
+        let buffered = self.recv_storage.as_slice().first().map_or(true, |first_byte| !self.streams_frame(*first_byte));
+        let (max_frame_size, max_message_size) = self.recv_limits();
+        if let Err(e) = check_recv_limits(self.recv_storage.as_slice(), self.recv_data.len(), buffered, max_frame_size, max_message_size) {
+            return self.fail_connection(e);
+        }
 
-        let (frame, offset) = frame.unwrap();
+        let frame = match parse_frame(self.recv_storage.as_slice()) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(Event::NO_DATA),
+            Err(e) => return self.fail_connection(e)
+        };
 
-        let event = Event::WEBSOCKET_DATA(frame);
-        self.recv_storage.drain(0..offset);
+        let len = frame.frame_len();
+        let flag = frame.get_flag();
+        let opcode = frame.get_opcode();
+        self.counters.frame_received(&opcode, flag.contains(FLAG::FIN));
 
-        Ok(event)
-    }
+        if !rsv_allowed(flag, &opcode, self.rsv_bits) {
+            return self.fail_connection(ProtocolError::ReservedBits.into());
+        }
 
-    fn handle_recv_frame(&mut self, frame: Box<dyn Frame>) -> WebSocketResult<()> {
-        match frame.kind()  {
-            FrameKind::Data => { 
-                if frame.get_header().get_flag() != FLAG::FIN {
-                    self.recv_data.extend_from_slice(frame.get_data());
+        match frame.kind() {
+            FrameKind::Data => {
+                if !fragment_allowed(&opcode, self.recv_in_message) {
+                    return self.fail_connection(ProtocolError::InvalidFrame.into());
                 }
+                self.recv_in_message = !flag.contains(FLAG::FIN);
 
-                if self.callback.is_some() {
-                    let callback = self.callback.unwrap();
+                // The extensions work with the opcode and reserved bits of the first frame of the message
+                if opcode != OPCODE::CONTINUATION {
+                    self.recv_opcode = opcode;
+                    self.recv_rsv = flag.bits() & self.rsv_bits;
+                    self.recv_streaming = self.fragments_enabled() && self.recv_rsv == 0;
+                }
 
-                    let res = String::from_utf8(frame.get_data().to_vec());
-                    if res.is_err() { return Err(WebSocketError::DecodingFromUTF8) }
-                    
-                    let msg = res.unwrap();
+                // Each frame is delivered as it arrives, only the payload of the frame is copied
+                if self.recv_streaming {
+                    let data = match self.fixed_buffers {
+                        // The buffer of the message is reused for the fragments
+                        Some(_) => {
+                            self.recv_data.clear();
+                            self.recv_data.extend_from_slice(frame.payload());
+                            std::mem::take(&mut self.recv_data)
+                        },
+                        None => frame.payload().to_vec()
+                    };
+                    self.recv_storage.consume(len);
+                    return Ok(Event::WEBSOCKET_FRAGMENT(data, flag.contains(FLAG::FIN)));
+                }
 
-                    // Message received in a single frame
-                    if self.recv_data.is_empty() {
-                        callback(self, &WSEvent::ON_TEXT(msg), self.cb_data.clone());
+                // The payload goes from the receive buffer to the message, the frame is not copied
+                self.recv_data.extend_from_slice(frame.payload());
+                self.recv_storage.consume(len);
 
-                    // Message from a multiples frames     
-                    } else {
-                        let previous_data = self.recv_data.clone();
-                        let res = String::from_utf8(previous_data);
-                        if res.is_err() { return Err(WebSocketError::DecodingFromUTF8); }
-                        
-                        let mut completed_msg = res.unwrap();
-                        completed_msg.push_str(msg.as_str());
+                // Wait for the rest of the frames of the message
+                if !flag.contains(FLAG::FIN) { return Ok(Event::NO_DATA) }
+                Ok(Event::WEBSOCKET_MESSAGE(std::mem::take(&mut self.recv_data)))
+            },
+            // With fixed buffers the pong is serialized in the send buffer from the ping received, nothing is copied
+            FrameKind::Control if self.send_buffer.is_some() && (opcode == OPCODE::PING || opcode == OPCODE::PONG) => {
+                // The ping is not answered if there's no room, only the answer to the last ping is required
+                let mut result = Ok(true);
+                if opcode == OPCODE::PING && frame.payload().len() <= 125 {
+                    let buffer = self.send_buffer.as_mut().unwrap();
+                    result = buffer_frame(&mut self.encoder, buffer, &mut self.counters, FLAG::FIN, OPCODE::PONG, frame.payload());
+                }
+                self.recv_storage.consume(len);
+                if let Err(e) = result { return self.fail_connection(e) }
+                Ok(Event::NO_DATA)
+            },
+            // Control frames are small, keep a copy to answer them
+            FrameKind::Control => {
+                let frame = frame.to_control_frame();
+                self.recv_storage.consume(len);
+
+                match frame {
+                    Ok(frame) => Ok(Event::WEBSOCKET_DATA(Box::new(frame))),
+                    Err(e) => self.fail_connection(e)
+                }
+            },
+            FrameKind::NotDefine => self.fail_connection(ProtocolError::InvalidFrame.into())
+        }
+    }
 
-                        // Send the message to the callback function
-                        callback(self, &WSEvent::ON_TEXT(completed_msg), self.cb_data.clone());
-                        
-                        // There is 2 ways to deal with the vector data:
-                        // 1 - Remove from memory (takes more time)
-                        //         Creating a new vector produces that the old vector will be dropped (deallocating the memory)
-                        self.recv_data = Vec::new();
-
-                        // // 2 - Use the clear method (takes more memory because we never drop it)
-                        // //         The vector does not remove memory that has already been allocated.
-                        // self.recv_data.clear();
-                    }
+    fn handle_recv_message(&mut self, data: Vec<u8>) -> WebSocketResult<()> {
+        let data = if self.negotiated_extensions.is_empty() { data } else { self.decode(data)? };
+
+        // Message transformed by an extension, received whole
+        if self.fragments_enabled() { return self.handle_recv_fragment(data, true) }
+
+        let message = match into_message(&self.recv_opcode, data) {
+            Ok(message) => message,
+            Err(e) => return self.fail_connection(e.into())
+        };
+
+        // Send the message to the callback function or keep it until is taken
+        match self.callback {
+            Some(callback) => {
+                let event = match message {
+                    Message::TEXT(text) => WSEvent::ON_TEXT(text),
+                    Message::BINARY(data) => WSEvent::ON_BINARY(data)
+                };
+                callback(self, &event, self.cb_data.clone());
+                match event {
+                    WSEvent::ON_TEXT(text) => self.reuse_recv_data(text.into_bytes()),
+                    WSEvent::ON_BINARY(data) => self.reuse_recv_data(data),
+                    _ => {}
                 }
-                return Ok(());
             },
-            FrameKind::Control => { return self.handle_control_frame(frame.as_any().downcast_ref::<ControlFrame>().unwrap()); },
-            FrameKind::NotDefine => return Err(WebSocketError::InvalidFrame)
-        }; 
+            None => {
+                self.messages.push_back(message);
+                self.reuse_recv_data(Vec::new());
+            }
+        }
+        Ok(())
+    }
+
+    // With fixed buffers the buffer of the message delivered receives the next one, the buffer is only allocated again
+    // if the message was kept (without callback)
+    fn reuse_recv_data(&mut self, data: Vec<u8>) {
+        if let Some((recv_size, _)) = self.fixed_buffers {
+            self.recv_data = data;
+            self.recv_data.clear();
+            self.recv_data.reserve_exact(recv_size);
+        }
+    }
+
+    // Max payload of a frame and max length of a message received, with fixed buffers the whole frame must fit in the receive buffer
+    fn recv_limits(&self) -> (u64, u64) {
+        match self.fixed_buffers {
+            Some((recv_size, _)) => {
+                let max_frame_size = recv_size.saturating_sub(MAX_SERVER_HEADER_LEN) as u64;
+                (self.max_frame_size.min(max_frame_size), self.max_message_size.min(recv_size as u64))
+            },
+            None => (self.max_frame_size, self.max_message_size)
+        }
+    }
+
+    fn handle_recv_fragment(&mut self, data: Vec<u8>, is_final: bool) -> WebSocketResult<()> {
+        let kind = if self.recv_opcode == OPCODE::BINARY { MessageKind::BINARY } else { MessageKind::TEXT };
+        if is_final { self.recv_streaming = false }
+
+        if kind == MessageKind::TEXT && !check_utf8_fragment(&mut self.recv_utf8_tail, &data, is_final) {
+            return self.fail_connection(ProtocolError::InvalidUTF8.into());
+        }
+
+        if let Some(callback) = self.callback {
+            let event = WSEvent::ON_FRAGMENT { kind, data, is_final };
+            callback(self, &event, self.cb_data.clone());
+            if let WSEvent::ON_FRAGMENT { data, .. } = event { self.reuse_recv_data(data) }
+        }
+        Ok(())
+    }
+
+    fn fragments_enabled(&self) -> bool {
+        self.stream_fragments && self.callback.is_some()
+    }
+
+    // Returns if the data frame that starts with the byte is delivered as a fragment (the message is not buffered)
+    fn streams_frame(&self, first_byte: u8) -> bool {
+        if first_byte & 0b00001111 == OPCODE::CONTINUATION.bits() { return self.recv_streaming }
+        self.fragments_enabled() && first_byte & 0b01110000 & self.rsv_bits == 0
     }
 
     fn handle_recv_bytes_http_response(&mut self) -> WebSocketResult<Event> {
-        let response = Response::parse(&self.recv_storage);
+        let response = Response::parse(self.recv_storage.as_slice());
         if response.is_err() { return Ok(Event::NO_DATA); } // TODO: Check for timeout to raise an error
 
         let response = response.unwrap();
@@ -312,23 +1143,13 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
     fn handle_recv_http_response(&mut self, response: Response) -> WebSocketResult<()> {
         match self.connection_status {
             ConnectionStatus::HANDSHAKE => {
-                let sec_websocket_accept = response.header("Sec-WebSocket-Accept");
-            
-                if sec_websocket_accept.is_none() { return Err(WebSocketError::HandShake) }
-                let sec_websocket_accept = sec_websocket_accept.unwrap();
-            
-                // Verify Sec-WebSocket-Accept
-                let accepted = verify_key(&self.websocket_key, &sec_websocket_accept);
-                if !accepted {
-                    return Err(WebSocketError::HandShake);
-                }
-            
-                if response.get_status_code() == 0 || 
-                   response.get_status_code() != SWITCHING_PROTOCOLS { 
-                    return Err(WebSocketError::HandShake) 
-                }
+                verify_response(&response, &self.websocket_key)?;
+                self.protocol = negotiate_protocol(&response, self.acceptable_protocols, self.require_protocol)?;
 
-                self.protocol = response.header("Sec-WebSocket-Protocol");
+                let negotiated = negotiate_extensions(&response, &self.extension_offers)?;
+                self.negotiated_extensions = negotiated.handlers;
+                self.extensions = negotiated.extensions;
+                self.rsv_bits = negotiated.rsv_bits;
 
                 let mut response_msg = None;
                 
@@ -337,6 +1158,9 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
                 }
 
                 self.connection_status = ConnectionStatus::OPEN;
+                self.connected_at = Some((SystemTime::now(), Instant::now()));
+                self.closed_at = None;
+                self.counters.stats.handshake_duration = Some(self.handshake_start.elapsed());
 
                 if let Some(callback) = self.callback { 
                     callback(self, &WSEvent::ON_CONNECT(response_msg), self.cb_data.clone());
@@ -348,36 +1172,95 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
         Ok(())
     }
 
+    // The header and the payload are written together without copying the payload in a new buffer
     fn handle_send_frame(&mut self, frame: Box<dyn Frame>) -> WebSocketResult<()> {
-        let sent = self.try_write(frame.serialize().as_slice())?;
-        let kind = frame.kind();
-        let mut status = None;
+        let (header, head, payload) = frame.into_serialized().into_parts();
+        self.write_event(PartialWrite { frame: Some(header), head, payload, written: 0 })
+    }
+
+    fn handle_send_http_request(&mut self, request: Request) -> WebSocketResult<()> {
+        self.write_event(PartialWrite { frame: None, head: request.serialize(), payload: Vec::new(), written: 0 })
+    }
 
-        if frame.kind() == FrameKind::Control {
-            status = frame.as_any().downcast_ref::<ControlFrame>().unwrap().get_status_code();
+    // Write the bytes of the event from written, if the socket doesn't accept all of them keep the event
+    // to continue in the next write (the frame can't be serialized again, the rest of the stream depends on the bytes sent)
+    fn write_event(&mut self, write: PartialWrite) -> WebSocketResult<()> {
+        let mut write = write;
+        write.written += self.try_write([&write.head, &write.payload], write.written)?;
+        if write.written < write.head.len() + write.payload.len() {
+            self.partial_write = Some(write);
+            return Ok(());
         }
 
-        if !sent { self.output_events.push_front(Event::WEBSOCKET_DATA(frame)) };
+        match write.frame {
+            Some(header) => self.frame_sent(&header),
+            None => Ok(())
+        }
+    }
 
-        if sent && kind == FrameKind::Control && self.connection_status == ConnectionStatus::SERVER_WANTS_TO_CLOSE {
-            self.connection_status = ConnectionStatus::CLOSE;
-            self.stream.as_mut().unwrap().shutdown(Shutdown::Both)?;
-            self.stream = None;
+    // Continue the write that the socket didn't accept completely or write the send buffer, returns false if there isn't any.
+    // The frames of the send buffer go before the output events
+    fn resume_write(&mut self) -> WebSocketResult<bool> {
+        let partial = match self.partial_write.take() {
+            Some(partial) => partial,
+            None => return self.write_send_buffer()
+        };
 
-            if let Some(callback) = self.callback {
-                let reason = Reason::SERVER_CLOSE(status.unwrap_or(0));
-                callback(self, &WSEvent::ON_CLOSE(reason), self.cb_data.clone());
+        self.write_event(partial)?;
+        Ok(true)
+    }
+
+    // Write the frames of the send buffer, once all of them are written the messages queued are sent
+    fn write_send_buffer(&mut self) -> WebSocketResult<bool> {
+        if !self.buffer_pending() { return Ok(false) }
+
+        // The buffer is taken to write from it, nothing is moved or allocated
+        let mut buffer = self.send_buffer.take().unwrap();
+        let result = self.try_write([buffer.pending(), &[]], 0);
+        if let Ok(written) = result { buffer.advance(written) }
+        let drained = buffer.is_empty();
+        self.send_buffer = Some(buffer);
+        result?;
+
+        if drained && self.queued_messages > 0 {
+            // The message streamed is still queued until its FIN frame
+            self.queued_messages = if self.streaming { 1 } else { 0 };
+            self.queued_bytes = 0;
+
+            if self.queued_messages == 0 && self.connection_status == ConnectionStatus::OPEN {
+                if let Some(callback) = self.callback { callback(self, &WSEvent::ON_DRAINED, self.cb_data.clone()) }
             }
         }
+        Ok(true)
+    }
 
-        Ok(())
+    // Returns if there are frames in the send buffer to write, the buffer is only written once the connection is open
+    fn buffer_pending(&self) -> bool {
+        if self.connection_status == ConnectionStatus::HANDSHAKE { return false }
+        self.send_buffer.as_ref().map_or(false, |buffer| !buffer.is_empty())
     }
 
-    fn handle_send_http_request(&mut self, request: Request) -> WebSocketResult<()> {
-        let sent = self.try_write(request.serialize().as_slice())?;
-        if !sent { 
-            self.output_events.push_front(Event::HTTP_REQUEST(request)) 
+    fn frame_sent(&mut self, header: &Header) -> WebSocketResult<()> {
+        let opcode = header.get_opcode();
+        self.counters.frame_sent(&opcode, header.get_flag().contains(FLAG::FIN));
+        let is_close = opcode == OPCODE::CLOSE;
+
+        if opcode == OPCODE::TEXT || opcode == OPCODE::BINARY || opcode == OPCODE::CONTINUATION {
+            self.queued_bytes = self.queued_bytes.saturating_sub(header.get_payload_len() as usize);
+            if header.get_flag().contains(FLAG::FIN) { self.queued_messages = self.queued_messages.saturating_sub(1) }
+
+            if self.queued_messages == 0 && self.connection_status == ConnectionStatus::OPEN {
+                if let Some(callback) = self.callback { callback(self, &WSEvent::ON_DRAINED, self.cb_data.clone()) }
+            }
+            return Ok(());
+        }
+
+        // Response to the close of the server sent, the close handshake is done
+        if is_close && self.connection_status == ConnectionStatus::SERVER_WANTS_TO_CLOSE {
+            let reason = self.close_reason.take().unwrap_or(Reason::SERVER_CLOSE(CloseCode::NoStatusReceived, String::new()));
+            self.close_connection(reason);
         }
+
         Ok(())
     }
 
@@ -386,7 +1269,9 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
         match kind {
             EventIO::INPUT => {
                 match event {
-                    Event::WEBSOCKET_DATA(frame) => self.handle_recv_frame(frame)?,
+                    Event::WEBSOCKET_DATA(frame) => self.handle_control_frame(frame.as_any().downcast_ref::<ControlFrame>().unwrap())?,
+                    Event::WEBSOCKET_MESSAGE(data) => self.handle_recv_message(data)?,
+                    Event::WEBSOCKET_FRAGMENT(data, is_final) => self.handle_recv_fragment(data, is_final)?,
                     Event::HTTP_RESPONSE(response) => self.handle_recv_http_response(response)?,
                     Event::HTTP_REQUEST(_) => {} // Unreachable
                     Event::NO_DATA => {} // Unreachable
@@ -397,6 +1282,8 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
                 match event { 
                     Event::WEBSOCKET_DATA(frame) => self.handle_send_frame(frame)?,
                     Event::HTTP_REQUEST(request) => self.handle_send_http_request(request)?,
+                    Event::WEBSOCKET_MESSAGE(_) => {} // Unreachable
+                    Event::WEBSOCKET_FRAGMENT(..) => {} // Unreachable
                     Event::HTTP_RESPONSE(_) => {} // Unreachable
                     Event::NO_DATA => {} // Unreachable
                 }
@@ -406,19 +1293,18 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
         return Ok(());
     }
 
-    fn read_bytes_from_socket(&mut self) -> WebSocketResult<Event> {
-        // TODO: Add timeout attribute to self in order to raise an error if any op overflow the time required to finish
-        let mut buffer = [0u8; 1024];
+    // Read the bytes available in the socket, returns the amount of bytes read
+    fn read_socket(&mut self) -> WebSocketResult<usize> {
         let reader = self.stream.as_mut().unwrap();
-        let bytes_readed = read_into_buffer(reader, &mut buffer)?;
-
-        if bytes_readed > 0 {
-            self.recv_storage.extend_from_slice(&buffer[0..bytes_readed]);
-        }
+        let bytes_read = self.recv_storage.read_from(reader, self.read_chunk_size)?;
+        self.counters.bytes_received(bytes_read);
+        Ok(bytes_read)
+    }
 
-        // Input data
+    // Parse the next response or frame from the bytes received
+    fn next_input_event(&mut self) -> WebSocketResult<Event> {
         let mut event = Event::NO_DATA;
-        if self.recv_storage.len() > 0 {
+        if !self.recv_storage.is_empty() {
             match self.connection_status {
                 ConnectionStatus::HANDSHAKE => event = self.handle_recv_bytes_http_response()?,
                 ConnectionStatus::OPEN | ConnectionStatus::CLIENT_WANTS_TO_CLOSE | ConnectionStatus::SERVER_WANTS_TO_CLOSE => {
@@ -443,90 +1329,270 @@ impl<'a, T> WSClient<'a, T> where T: Clone {
                 }
             },
 
-            Event::HTTP_RESPONSE(_) => self.input_events.push_back(event),
+            Event::WEBSOCKET_MESSAGE(_) | Event::WEBSOCKET_FRAGMENT(..) | Event::HTTP_RESPONSE(_) => self.input_events.push_back(event),
             Event::HTTP_REQUEST(_) => {} // Unreachable
             Event::NO_DATA => {}
         }
     }
 
-    fn try_write(&mut self, bytes: &[u8]) -> WebSocketResult<bool> {
-        let res = self.stream.as_mut().unwrap().write_all(bytes);
-        if res.is_err(){
-            let error = res.err().unwrap();
+    // Write the buffers (in order) from the byte written until all the bytes are written or the socket is full,
+    // returns the bytes written in this call
+    fn try_write(&mut self, parts: [&[u8]; 2], written: usize) -> WebSocketResult<usize> {
+        let total: usize = parts.iter().map(|part| part.len()).sum();
+        let start = written;
+        let mut written = written;
+        while written < total {
+            match write_vectored_from(self.stream.as_mut().unwrap(), parts, written) {
+                Ok(0) => return Err(WebSocketError::IOError(ErrorKind::WriteZero.into())),
+                Ok(n) => {
+                    written += n;
+                    self.counters.bytes_sent(n);
+                },
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                // Try to send the rest next iteration
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    self.write_blocked = true;
+                    return Ok(written - start);
+                },
+                Err(e) => return Err(WebSocketError::IOError(e))
+            }
+        }
+        self.write_blocked = false;
+        Ok(written - start)
+    }
 
-            // Try to send next iteration
-            if error.kind() == ErrorKind::WouldBlock { 
-                return Ok(false);
+    pub(crate) fn is_closed(&self) -> bool {
+        self.connection_status == ConnectionStatus::CLOSE
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // Max payload of a frame of a message streamed, with fixed buffers the frame must fit in the send buffer
+    pub(crate) fn max_fragment_len(&self) -> usize {
+        let len = self.message_size as usize;
+        match &self.send_buffer {
+            Some(buffer) => len.min(buffer.capacity() - CONTROL_FRAME_LEN - MAX_HEADER_LEN),
+            None => len
+        }
+    }
 
-            } else {
-                return Err(WebSocketError::IOError);
+    // Take the oldest message received without callback
+    pub(crate) fn pop_message(&mut self) -> Option<Message> {
+        self.messages.pop_front()
+    }
+
+    // Returns if there are frames waiting to be sent
+    pub(crate) fn has_output(&self) -> bool {
+        if self.partial_write.is_some() || self.buffer_pending() { return true }
+        match self.output_events.front() {
+            Some(event) => self.connection_status != ConnectionStatus::HANDSHAKE || !is_websocket_data(event),
+            None => false
+        }
+    }
+
+    // Returns if the next call to event_loop has work to do without waiting for the socket
+    fn has_pending_events(&self) -> bool {
+        if self.connection_status == ConnectionStatus::NOT_INIT || self.connection_status == ConnectionStatus::START_INIT { return true }
+        if !self.input_events.is_empty() || (self.has_output() && !self.write_blocked) { return true }
+        self.connection_status != ConnectionStatus::HANDSHAKE && is_frame_complete(self.recv_storage.as_slice())
+    }
+
+    // Block until the socket is readable (or writable if there are frames to send), or there's pending work, or a handle pushes a message, or the timeout expires.
+    // Returns false if the timeout expired
+    pub(crate) fn wait_for_events(&mut self, timeout: Duration) -> WebSocketResult<bool> {
+        if self.connection_status == ConnectionStatus::OPEN { self.receive_commands()? }
+        if self.has_pending_events() { return Ok(true) }
+        if self.stream.is_none() { return Ok(true) }             // event_loop will report the state of the connection
+
+        // The handles of sender() wake up the wait when they push a message
+        let stream = self.stream.as_ref().unwrap();
+        let ready = match self.commands.as_ref().and_then(|c| c.wakeup()) {
+            Some(wakeup) => wait_socket_or_wakeup(stream, self.has_output(), timeout, wakeup)?,
+            None => wait_socket(stream, self.has_output(), timeout)?
+        };
+        Ok(ready)
+    }
+
+    // Masking key of a frame of the client, the connection fails if the random source can't generate it
+    fn new_mask(&mut self) -> WebSocketResult<Mask> {
+        match gen_mask(&mut self.random) {
+            Ok(mask) => Ok(mask),
+            Err(e) => self.fail_connection(e.into())
+        }
+    }
+
+    // The frames of a message can't go between the frames of the message streamed
+    fn message_output(&mut self) -> &mut VecDeque<Event> {
+        if self.streaming { &mut self.held_output } else { &mut self.output_events }
+    }
+
+    // Send a close frame with the close code of the error without waiting for the response of the server, close the connection and return the error
+    fn fail_connection<R>(&mut self, error: WebSocketError) -> WebSocketResult<R> {
+        let status = error.close_code().unwrap_or(CloseCode::InternalError);
+        // Ignore result, the connection will be closed anyway. The frame can't be written in the middle of another one,
+        // and it isn't sent if the random source can't mask it
+        let writing = self.send_buffer.as_ref().map_or(false, |buffer| buffer.is_writing());
+        if let (true, Ok(mask)) = (self.partial_write.is_none() && !writing, gen_mask(&mut self.random)) {
+            let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, Some(status.into()), Vec::new(), Some(mask));
+            let serialized = close_frame.serialize();
+            if let Ok(written) = self.try_write([&serialized, &[]], 0) {
+                if written == serialized.len() { self.counters.frame_sent(&OPCODE::CLOSE, true) }
             }
         }
-        Ok(true)
+
+        self.clear_output();
+        self.input_events.clear();
+        self.recv_storage.clear();
+        self.recv_data.clear();
+        self.close_connection(Reason::CLIENT_CLOSE(status, String::new()));
+
+        Err(error)
+    }
+
+    // Close the socket and notify the close to the callback, only the first close of the connection is notified
+    fn close_connection(&mut self, reason: Reason) {
+        if self.connection_status == ConnectionStatus::CLOSE { return }
+        self.connection_status = ConnectionStatus::CLOSE;
+        self.closed_at = Some(Instant::now());
+        self.partial_write = None;
+        self.recv_streaming = false;
+        self.recv_in_message = false;
+        self.recv_utf8_tail.clear();
+        if let Some(commands) = &self.commands { commands.close() }
+
+        if let Some(stream) = self.stream.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+
+        if let Some(callback) = self.callback {
+            callback(self, &WSEvent::ON_CLOSE(reason.clone()), self.cb_data.clone());
+        }
+
+        self.close_reason = Some(reason);
+        self.report_metrics(true);
+    }
+
+    // Call the metrics hook once the interval passed since the last report (or now with force)
+    fn report_metrics(&mut self, force: bool) {
+        if let Some((hook, interval)) = self.metrics_hook {
+            if !force && self.last_report.elapsed() < interval { return }
+            self.last_report = Instant::now();
+            hook(&self.counters.stats, self.cb_data.clone());
+        }
     }
 
     fn handle_control_frame(&mut self, frame: &ControlFrame) -> WebSocketResult<()> {
         match frame.get_header().get_opcode() {
             OPCODE::PING=> { 
                 let data = frame.get_data();
-                let pong_frame = ControlFrame::new(FLAG::FIN, OPCODE::PONG, None, data.to_vec(), true, None);
+                let pong_frame = ControlFrame::new(FLAG::FIN, OPCODE::PONG, None, data.to_vec(), Some(self.new_mask()?));
                 self.output_events.push_front(Event::WEBSOCKET_DATA(Box::new(pong_frame)));
             },
-            OPCODE::PONG => { todo!("Not implemented handle PONG") },
+            OPCODE::PONG => {},                             // Response to a ping of the client, nothing to do
             OPCODE::CLOSE => {
-                let data = frame.get_data();
-                let status_code = &data[0..2];
-                let res = bytes_to_u16(status_code);
-
-                let status_code = if res.is_ok() { res.unwrap() } else { WSStatus::EXPECTED_STATUS_CODE.bits() };
+                let (status_code, reason) = match parse_close_frame(frame) {
+                    Ok(close) => close,
+                    Err(e) => return self.fail_connection(e.into())
+                };
 
                 match self.connection_status {
                     // Server wants to close the connection
                     ConnectionStatus::OPEN => {
-                        let status_code = WSStatus::from_bits(status_code);
-
-                        let reason = &data[2..data.len()];
-                        let mut status_code = if status_code.is_some() { status_code.unwrap() } else { WSStatus::PROTOCOL_ERROR };
-                        
-                        let (error, _) = evaulate_status_code(status_code);
-                        if error { status_code = WSStatus::PROTOCOL_ERROR }
-
-                        // Enqueue close frame to response to the server
-                        self.output_events.clear();
+                        // Enqueue close frame to response to the server, echo the status code received
+                        self.clear_output();
                         self.input_events.clear();
-                        let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, Some(status_code.bits()), reason.to_vec(), true, None);
+                        let status = frame.get_status_code();
+                        let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, status, Vec::new(), Some(self.new_mask()?));
                         self.output_events.push_front(Event::WEBSOCKET_DATA(Box::new(close_frame)));
 
+                        self.close_reason = Some(Reason::SERVER_CLOSE(status_code, reason));
                         self.connection_status = ConnectionStatus::SERVER_WANTS_TO_CLOSE;
-                        
-                        // TODO: Create and on close cb to handle this situation, send the status code an the reason
                     },
+                    // Received a response to the client close handshake
                     ConnectionStatus::CLIENT_WANTS_TO_CLOSE => {
-                        // TODO: ?
-                        // Received a response to the client close handshake
-                        // Verify the status of close handshake
-                        self.connection_status = ConnectionStatus::CLOSE;
-                        self.stream.as_mut().unwrap().shutdown(Shutdown::Both)?;
-                        
-                        if let Some(callback) = self.callback {
-                            let reason = Reason::CLIENT_CLOSE(frame.get_status_code().unwrap());
-                            callback(self, &WSEvent::ON_CLOSE(reason), self.cb_data.clone());
-                        }
+                        self.close_connection(Reason::CLIENT_CLOSE(status_code, reason));
                     },
-                    ConnectionStatus::SERVER_WANTS_TO_CLOSE => {}  // Unreachable  
+                    ConnectionStatus::SERVER_WANTS_TO_CLOSE => {}  // Already closing, ignore
                     ConnectionStatus::CLOSE => {}                  // Unreachable
                     ConnectionStatus::HANDSHAKE => {}              // Unreachable
                     ConnectionStatus::NOT_INIT => {}               // Unreachable
                     ConnectionStatus::START_INIT => {}             // Unreachable
                 }
             },
-            _ => return Err(WebSocketError::InvalidFrame)
+            _ => return self.fail_connection(ProtocolError::InvalidFrame.into())
         }
 
         Ok(())
     }
 }
 
+// Bytes of the frames of a message of len bytes sent in frames of frame_size bytes (masked)
+fn frames_len(len: usize, frame_size: usize) -> usize {
+    if len == 0 { return header_len(0, true) }
+    let (frames, rest) = (len / frame_size, len % frame_size);
+    let last = if rest > 0 { header_len(rest as u64, true) + rest } else { 0 };
+    frames * (header_len(frame_size as u64, true) + frame_size) + last
+}
+
+// Serialize a masked frame at the end of the send buffer without allocating, returns false if it doesn't fit (nothing is added)
+fn buffer_frame(encoder: &mut FrameEncoder<SharedRandom>, buffer: &mut SendBuffer, counters: &mut Counters, flag: FLAG, opcode: OPCODE, payload: &[u8]) -> WebSocketResult<bool> {
+    if buffer.free() < encoder.frame_len(payload.len()) { return Ok(false) }
+
+    let mut head = [0u8; MAX_HEADER_LEN];
+    let (len, mask) = encoder.encode_header(flag, opcode.clone(), payload.len() as u64, &mut head)?;
+    buffer.append(&head[..len]);
+    if let (Some(bytes), Some(mask)) = (buffer.append(payload), mask) { apply_mask(bytes, mask, 0) }
+    counters.frame_sent(&opcode, flag.contains(FLAG::FIN));
+    Ok(true)
+}
+
+// Check the UTF-8 of a fragment of a text message, a character can be split between two fragments:
+// the bytes of the incomplete character at the end are kept in the tail and checked with the next fragment
+fn check_utf8_fragment(tail: &mut Vec<u8>, data: &[u8], is_final: bool) -> bool {
+    let mut data = data;
+
+    // Complete the character of the previous fragment
+    while !tail.is_empty() {
+        if data.is_empty() { return !is_final }
+        tail.push(data[0]);
+        data = &data[1..];
+
+        match std::str::from_utf8(tail) {
+            Ok(_) => tail.clear(),
+            Err(e) if e.error_len().is_none() => {},
+            Err(_) => return false
+        }
+    }
+
+    match std::str::from_utf8(data) {
+        Ok(_) => true,
+        Err(e) if e.error_len().is_none() && !is_final => {
+            tail.extend_from_slice(&data[e.valid_up_to()..]);
+            true
+        },
+        Err(_) => false
+    }
+}
+
+// Status code and reason of a close frame received, the status code is 1005 if the frame doesn't have one.
+// Returns the protocol error to fail the connection with if the frame is not valid
+pub(crate) fn parse_close_frame(frame: &ControlFrame) -> Result<(CloseCode, String), ProtocolError> {
+    let status_code = frame.get_status_code();
+    if status_code.is_none() { return Ok((CloseCode::NoStatusReceived, String::new())) }
+
+    // Codes out of range or reserved can't be received
+    let status_code = CloseCode::try_from(status_code.unwrap());
+    if status_code.is_err() || !status_code.unwrap().may_send() { return Err(ProtocolError::InvalidCloseFrame) }
+    let status_code = status_code.unwrap();
+
+    let reason = String::from_utf8(frame.get_data()[2..].to_vec());
+    if reason.is_err() { return Err(ProtocolError::InvalidUTF8) }
+
+    Ok((status_code, reason.unwrap()))
+}
+
 impl<'a, T> Drop for WSClient<'a, T> where T: Clone {
     fn drop(&mut self) {
         if self.connection_status != ConnectionStatus::NOT_INIT &&
@@ -535,12 +1601,17 @@ impl<'a, T> Drop for WSClient<'a, T> where T: Clone {
             self.stream.is_some() {
 
                 let msg = "Done";
-                let status_code: u16 = 1000;
-                let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, Some(status_code), msg.as_bytes().to_vec(), true, None);
+                let status_code = CloseCode::NormalClosure;
+                // Without a masking key the close handshake can't be done, the connection is just closed
+                let mask = match gen_mask(&mut self.random) {
+                    Ok(mask) => mask,
+                    Err(_) => return self.close_connection(Reason::CLIENT_CLOSE(CloseCode::AbnormalClosure, String::new()))
+                };
+                let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, Some(status_code.into()), msg.as_bytes().to_vec(), Some(mask));
         
                 // Add close frame at the end of the queue.
                 // Clear both queues
-                self.output_events.clear();
+                self.clear_output();
                 self.input_events.clear();
                 self.output_events.push_back(Event::WEBSOCKET_DATA(Box::new(close_frame)));
                 self.connection_status = ConnectionStatus::CLIENT_WANTS_TO_CLOSE;
@@ -560,9 +1631,10 @@ impl<'a, T> Drop for WSClient<'a, T> where T: Clone {
                     }
         
                     }
-                let _ = self.stream.as_mut().unwrap().shutdown(Shutdown::Both); // Ignore result from shutdown method.
+                // The server didn't answer the close handshake in time
+                self.close_connection(Reason::CLIENT_CLOSE(CloseCode::AbnormalClosure, String::new()));
             }
         }
 }
 
-unsafe impl<'a, T> Send for WSClient<'a, T> where T: Clone {}
\ No newline at end of file
+unsafe impl<'a, T> Send for WSClient<'a, T> where T: Clone {}
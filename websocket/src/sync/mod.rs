@@ -1 +1,6 @@
-pub mod client;
\ No newline at end of file
+pub mod client;
+pub mod blocking;
+pub mod sender;
+pub mod split;
+pub mod writer;
+pub mod stats;
@@ -0,0 +1,110 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender, Receiver};
+use crate::core::poll::Wakeup;
+use crate::result::{WebSocketError, WebSocketResult, ProtocolError};
+use crate::ws_basic::status_code::CloseCode;
+
+// Max payload of a control frame (RFC 6455 5.5)
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+// Frames sent by the handles, the client takes them in the event loop
+pub(crate) enum Command {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Close(CloseCode, String)
+}
+
+// Handle to send messages through a client from other threads, returned by client.sender().
+// The messages are queued in the client in the next call to the event loop, a client waiting in event_loop_timeout
+// wakes up when a message is pushed.
+// All the calls return ConnectionClose once the connection is closed or the client is dropped.
+// A message that the client can't queue (bigger than the fixed send buffer, the extensions or the random source failed)
+// is dropped and the error is returned by the event loop of the client
+#[derive(Clone)]
+pub struct WSSender {
+    channel: Arc<Mutex<Sender<Command>>>,
+    closed: Arc<AtomicBool>,
+    wakeup: Option<Arc<Wakeup>>                              // None if the pipe couldn't be created, the client sees the messages when its wait ends
+}
+
+impl WSSender {
+    pub fn send_text(&self, payload: &str) -> WebSocketResult<()> {
+        self.push(Command::Text(payload.to_string()))
+    }
+
+    pub fn send_binary(&self, payload: &[u8]) -> WebSocketResult<()> {
+        self.push(Command::Binary(payload.to_vec()))
+    }
+
+    // The payload can't be longer than 125 bytes
+    pub fn ping(&self, payload: &[u8]) -> WebSocketResult<()> {
+        if payload.len() > MAX_CONTROL_PAYLOAD { return Err(WebSocketError::Protocol(ProtocolError::InvalidFrame)) }
+        self.push(Command::Ping(payload.to_vec()))
+    }
+
+    // Start the close handshake after the messages already queued. The code must be allowed in a close frame
+    // and the reason can't be longer than 123 bytes
+    pub fn close(&self, code: CloseCode, reason: &str) -> WebSocketResult<()> {
+        if !code.may_send() || reason.len() > MAX_CONTROL_PAYLOAD - 2 { return Err(WebSocketError::Protocol(ProtocolError::InvalidCloseFrame)) }
+        self.push(Command::Close(code, reason.to_string()))
+    }
+
+    // Returns if the connection is closed or the client was dropped
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn push(&self, command: Command) -> WebSocketResult<()> {
+        if self.is_closed() { return Err(WebSocketError::ConnectionClose) }
+
+        let channel = self.channel.lock().unwrap_or_else(|e| e.into_inner());
+        if channel.send(command).is_err() {
+            self.closed.store(true, Ordering::Release);
+            return Err(WebSocketError::ConnectionClose);
+        }
+
+        if let Some(wakeup) = &self.wakeup { wakeup.wake() }
+        Ok(())
+    }
+}
+
+// Receiver side kept by the client
+pub(crate) struct Commands {
+    channel: Receiver<Command>,
+    sender: WSSender
+}
+
+impl Commands {
+    pub(crate) fn new() -> Self {
+        let (tx, rx) = channel();
+        let wakeup = Wakeup::new().ok().map(Arc::new);
+        let sender = WSSender { channel: Arc::new(Mutex::new(tx)), closed: Arc::new(AtomicBool::new(false)), wakeup };
+        Commands { channel: rx, sender }
+    }
+
+    pub(crate) fn sender(&self) -> WSSender {
+        self.sender.clone()
+    }
+
+    // Woken up when a handle pushes a command
+    pub(crate) fn wakeup(&self) -> Option<&Wakeup> {
+        self.sender.wakeup.as_deref()
+    }
+
+    pub(crate) fn try_recv(&self) -> Option<Command> {
+        self.channel.try_recv().ok()
+    }
+
+    // The handles will fail from now on
+    pub(crate) fn close(&self) {
+        self.sender.closed.store(true, Ordering::Release);
+    }
+}
+
+impl Drop for Commands {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -0,0 +1,445 @@
+use std::net::{TcpStream, Shutdown};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use crate::core::buffer::RecvBuffer;
+use crate::core::net::write_all_wait;
+use crate::core::poll::wait_socket;
+use crate::extension::{ExtensionFrame, WebSocketExtension};
+use crate::core::recv::{check_recv_limits, rsv_allowed, fragment_allowed, decode_message, into_message};
+use crate::result::{WebSocketError, WebSocketResult, ProtocolError};
+use crate::ws_basic::header::{OPCODE, FLAG};
+use crate::ws_basic::mask::{Mask, gen_mask};
+use crate::codec::SharedRandom;
+use crate::ws_basic::frame::{DataFrame, ControlFrame, Frame, FrameKind, parse_frame};
+use crate::ws_basic::status_code::CloseCode;
+use super::client::{Reason, Message, parse_close_frame};
+
+// Max payload of a control frame (RFC 6455 5.5)
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+#[derive(PartialEq, Clone, Copy)]
+enum ConnectionStatus {
+    OPEN,
+    CLIENT_WANTS_TO_CLOSE,
+    SERVER_WANTS_TO_CLOSE,
+    CLOSE
+}
+
+struct State {
+    connection_status: ConnectionStatus,
+    close_reason: Option<Reason>                         // Close received from the server (reported once the response is sent) or close of the connection
+}
+
+// Control frame sent by the reader through the socket of the writer
+struct ControlOutput {
+    bytes: Vec<u8>,
+    is_close: bool
+}
+
+// State shared by the reader and the writer.
+// The frames are written with the lock of the socket, the control frames of the reader are queued and written
+// by the half that holds the lock, so the reader never waits for a write of the writer
+struct Shared {
+    state: Mutex<State>,
+    output: Mutex<TcpStream>,
+    control: Mutex<VecDeque<ControlOutput>>,
+    extensions: Mutex<Vec<Box<dyn WebSocketExtension>>>,
+    random: SharedRandom,                                // Masking keys of the frames of both halves
+    timeout: Duration
+}
+
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Shared {
+    // Masking key of a frame, fails if the random source can't generate it
+    fn new_mask(&self) -> WebSocketResult<Mask> {
+        Ok(gen_mask(&mut *lock(&self.random))?)
+    }
+
+    fn status(&self) -> ConnectionStatus {
+        lock(&self.state).connection_status
+    }
+
+    fn close_reason(&self) -> Option<Reason> {
+        let state = lock(&self.state);
+        if state.connection_status != ConnectionStatus::CLOSE { return None }
+        state.close_reason.clone()
+    }
+
+    // Write the frame and the control frames queued by the reader
+    fn write(&self, bytes: &[u8]) -> WebSocketResult<()> {
+        let mut bytes = bytes;
+
+        loop {
+            {
+                let mut stream = lock(&self.output);
+                if !bytes.is_empty() {
+                    if let Err(e) = write_all_wait(&mut stream, bytes, self.timeout) { return Err(self.connection_lost(&stream, e)) }
+                    bytes = &[];
+                }
+                self.write_control(&mut stream)?;
+            }
+
+            // The reader could queue a frame after the last check and before releasing the socket
+            if lock(&self.control).is_empty() { return Ok(()) }
+        }
+    }
+
+    // Queue a control frame, it's written now if the writer is not using the socket
+    fn route_control(&self, frame: ControlFrame) -> WebSocketResult<()> {
+        let is_close = frame.get_header().get_opcode() == OPCODE::CLOSE;
+        lock(&self.control).push_back(ControlOutput { bytes: frame.serialize(), is_close });
+
+        let stream = self.output.try_lock();
+        if let Ok(mut stream) = stream { return self.write_control(&mut stream) }
+        Ok(())
+    }
+
+    fn write_control(&self, stream: &mut TcpStream) -> WebSocketResult<()> {
+        loop {
+            let frame = lock(&self.control).pop_front();
+            if frame.is_none() { return Ok(()) }
+            let frame = frame.unwrap();
+
+            if let Err(e) = write_all_wait(stream, &frame.bytes, self.timeout) { return Err(self.connection_lost(stream, e)) }
+
+            // Response to the close of the server sent, the close handshake is done
+            if frame.is_close && self.status() == ConnectionStatus::SERVER_WANTS_TO_CLOSE {
+                let reason = lock(&self.state).close_reason.take();
+                self.close_connection(stream, reason.unwrap_or(Reason::SERVER_CLOSE(CloseCode::NoStatusReceived, String::new())));
+            }
+        }
+    }
+
+    // Send a close frame, the reader receives the response of the server
+    fn close(&self, code: CloseCode, reason: &str) -> WebSocketResult<()> {
+        if !code.may_send() || reason.len() > MAX_CONTROL_PAYLOAD - 2 { return Err(WebSocketError::Protocol(ProtocolError::InvalidCloseFrame)) }
+
+        {
+            let mut state = lock(&self.state);
+            if state.connection_status != ConnectionStatus::OPEN { return Err(WebSocketError::ConnectionClose) }
+            state.connection_status = ConnectionStatus::CLIENT_WANTS_TO_CLOSE;
+        }
+
+        let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, Some(code.into()), reason.as_bytes().to_vec(), Some(self.new_mask()?));
+        self.route_control(close_frame)
+    }
+
+    // Close the socket, the other half will stop. Only the first close of the connection is kept
+    fn close_connection(&self, stream: &TcpStream, reason: Reason) {
+        let mut state = lock(&self.state);
+        if state.connection_status == ConnectionStatus::CLOSE { return }
+        state.connection_status = ConnectionStatus::CLOSE;
+        state.close_reason = Some(reason);
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+
+    // The socket failed or was closed without the close handshake (1006)
+    fn connection_lost(&self, stream: &TcpStream, error: WebSocketError) -> WebSocketError {
+        let reason = match self.status() {
+            ConnectionStatus::CLOSE => return WebSocketError::ConnectionClose,
+            ConnectionStatus::CLIENT_WANTS_TO_CLOSE => Reason::CLIENT_CLOSE(CloseCode::AbnormalClosure, String::new()),
+            _ => Reason::SERVER_CLOSE(CloseCode::AbnormalClosure, String::new())
+        };
+        self.close_connection(stream, reason);
+        error
+    }
+}
+
+// Parts of an open client moved to the halves
+pub(crate) struct SplitParts {
+    pub stream: TcpStream,
+    pub pending: Vec<u8>,                                // Frames queued before the split
+    pub timeout: Duration,
+    pub message_size: u64,
+    pub max_frame_size: u64,
+    pub max_message_size: u64,
+    pub read_chunk_size: usize,
+    pub recv_storage: RecvBuffer,
+    pub recv_data: Vec<u8>,
+    pub recv_opcode: OPCODE,
+    pub recv_rsv: u8,
+    pub recv_in_message: bool,
+    pub rsv_bits: u8,
+    pub extensions: Vec<Box<dyn WebSocketExtension>>,
+    pub messages: VecDeque<Message>,
+    pub random: SharedRandom
+}
+
+pub(crate) fn split(parts: SplitParts) -> WebSocketResult<(WSReader, WSWriter)> {
+    let output = parts.stream.try_clone()?;
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State { connection_status: ConnectionStatus::OPEN, close_reason: None }),
+        output: Mutex::new(output),
+        control: Mutex::new(VecDeque::new()),
+        extensions: Mutex::new(parts.extensions),
+        random: parts.random,
+        timeout: parts.timeout
+    });
+
+    if !parts.pending.is_empty() { shared.write(&parts.pending)? }
+
+    let reader = WSReader {
+        shared: shared.clone(),
+        stream: parts.stream,
+        max_frame_size: parts.max_frame_size,
+        max_message_size: parts.max_message_size,
+        read_chunk_size: parts.read_chunk_size,
+        recv_storage: parts.recv_storage,
+        recv_data: parts.recv_data,
+        recv_opcode: parts.recv_opcode,
+        recv_rsv: parts.recv_rsv,
+        recv_in_message: parts.recv_in_message,
+        rsv_bits: parts.rsv_bits,
+        messages: parts.messages
+    };
+
+    let writer = WSWriter { shared, message_size: parts.message_size };
+
+    Ok((reader, writer))
+}
+
+// Read half of a client returned by split. Answers the pings and the close of the server through the writer
+pub struct WSReader {
+    shared: Arc<Shared>,
+    stream: TcpStream,
+    max_frame_size: u64,
+    max_message_size: u64,
+    read_chunk_size: usize,
+    recv_storage: RecvBuffer,
+    recv_data: Vec<u8>,
+    recv_opcode: OPCODE,                                 // Opcode of the first frame of the message being received
+    recv_rsv: u8,                                        // Reserved bits of the first frame of the message being received
+    recv_in_message: bool,                               // A data frame without FIN was received, the next data frames must be CONTINUATION
+    rsv_bits: u8,                                        // Reserved bits claimed by the negotiated extensions
+    messages: VecDeque<Message>                          // Messages received before the split
+}
+
+impl WSReader {
+    // Wait until a message is received, returns ConnectionClose once the connection is closed
+    pub fn recv(&mut self) -> WebSocketResult<Message> {
+        loop {
+            if let Some(message) = self.recv_timeout(self.shared.timeout)? { return Ok(message) }
+        }
+    }
+
+    // Wait until a message is received or the timeout expires (None), returns ConnectionClose once the connection is closed
+    pub fn recv_timeout(&mut self, timeout: Duration) -> WebSocketResult<Option<Message>> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(message) = self.messages.pop_front() { return Ok(Some(message)) }
+            if self.shared.status() == ConnectionStatus::CLOSE { return Err(WebSocketError::ConnectionClose) }
+            if let Some(message) = self.next_message()? { return Ok(Some(message)) }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !wait_socket(&self.stream, false, remaining)? { return Ok(None) }
+
+            if let Err(e) = self.recv_storage.read_from(&mut self.stream, self.read_chunk_size) {
+                return Err(self.shared.connection_lost(&self.stream, e));
+            }
+        }
+    }
+
+    // Start the close handshake, the writer can't send more messages
+    pub fn close(&self, code: CloseCode, reason: &str) -> WebSocketResult<()> {
+        self.shared.close(code, reason)
+    }
+
+    // Returns who closed the connection, the status code and the reason once the connection is closed
+    pub fn close_reason(&self) -> Option<Reason> {
+        self.shared.close_reason()
+    }
+
+    // Parse the frames received until a whole message is ready
+    fn next_message(&mut self) -> WebSocketResult<Option<Message>> {
+        loop {
+            // Check the limits with the header before buffering the payload
+            if let Err(e) = check_recv_limits(self.recv_storage.as_slice(), self.recv_data.len(), true, self.max_frame_size, self.max_message_size) {
+                return self.fail_connection(e);
+            }
+
+            let frame = match parse_frame(self.recv_storage.as_slice()) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return Ok(None),
+                Err(e) => return self.fail_connection(e)
+            };
+
+            let len = frame.frame_len();
+            let flag = frame.get_flag();
+            let opcode = frame.get_opcode();
+
+            if !rsv_allowed(flag, &opcode, self.rsv_bits) {
+                return self.fail_connection(ProtocolError::ReservedBits.into());
+            }
+
+            match frame.kind() {
+                FrameKind::Data => {
+                    if !fragment_allowed(&opcode, self.recv_in_message) {
+                        return self.fail_connection(ProtocolError::InvalidFrame.into());
+                    }
+                    self.recv_in_message = !flag.contains(FLAG::FIN);
+
+                    // The extensions work with the opcode and reserved bits of the first frame of the message
+                    if opcode != OPCODE::CONTINUATION {
+                        self.recv_opcode = opcode.clone();
+                        self.recv_rsv = flag.bits() & (FLAG::RSV1 | FLAG::RSV2 | FLAG::RSV3).bits();
+                    }
+
+                    // The payload goes from the receive buffer to the message, the frame is not copied
+                    self.recv_data.extend_from_slice(frame.payload());
+                    self.recv_storage.consume(len);
+
+                    // Wait for the rest of the frames of the message
+                    if !flag.contains(FLAG::FIN) { continue }
+
+                    let data = std::mem::take(&mut self.recv_data);
+                    let result = decode_message(&mut lock(&self.shared.extensions), self.recv_opcode.bits(), self.recv_rsv, data, self.max_message_size);
+                    let message = result.and_then(|data| Ok(into_message(&self.recv_opcode, data)?));
+                    return match message {
+                        Ok(message) => Ok(Some(message)),
+                        Err(e) => self.fail_connection(e)
+                    };
+                },
+                FrameKind::Control => {
+                    let frame = frame.to_control_frame();
+                    self.recv_storage.consume(len);
+
+                    let frame = match frame {
+                        Ok(frame) => frame,
+                        Err(e) => return self.fail_connection(e)
+                    };
+
+                    self.handle_control_frame(&frame)?;
+                    if self.shared.status() == ConnectionStatus::CLOSE { return Err(WebSocketError::ConnectionClose) }
+                },
+                FrameKind::NotDefine => return self.fail_connection(ProtocolError::InvalidFrame.into())
+            }
+        }
+    }
+
+    fn handle_control_frame(&mut self, frame: &ControlFrame) -> WebSocketResult<()> {
+        match frame.get_header().get_opcode() {
+            OPCODE::PING => {
+                let pong_frame = ControlFrame::new(FLAG::FIN, OPCODE::PONG, None, frame.get_data().to_vec(), Some(self.shared.new_mask()?));
+                self.shared.route_control(pong_frame)?;
+            },
+            OPCODE::PONG => {},
+            OPCODE::CLOSE => {
+                let (status_code, reason) = match parse_close_frame(frame) {
+                    Ok(close) => close,
+                    Err(e) => return self.fail_connection(e.into())
+                };
+
+                let connection_status = self.shared.status();
+                match connection_status {
+                    // Server wants to close the connection, echo the status code received
+                    ConnectionStatus::OPEN => {
+                        {
+                            let mut state = lock(&self.shared.state);
+                            state.connection_status = ConnectionStatus::SERVER_WANTS_TO_CLOSE;
+                            state.close_reason = Some(Reason::SERVER_CLOSE(status_code, reason));
+                        }
+                        let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, frame.get_status_code(), Vec::new(), Some(self.shared.new_mask()?));
+                        self.shared.route_control(close_frame)?;
+                    },
+                    // Received a response to the client close handshake
+                    ConnectionStatus::CLIENT_WANTS_TO_CLOSE => {
+                        self.shared.close_connection(&self.stream, Reason::CLIENT_CLOSE(status_code, reason));
+                    },
+                    ConnectionStatus::SERVER_WANTS_TO_CLOSE => {}  // Already closing, ignore
+                    ConnectionStatus::CLOSE => {}                  // Unreachable
+                }
+            },
+            _ => return self.fail_connection(ProtocolError::InvalidFrame.into())
+        }
+
+        Ok(())
+    }
+
+    // Send a close frame with the close code of the error if the socket is free, close the connection and return the error
+    fn fail_connection<R>(&mut self, error: WebSocketError) -> WebSocketResult<R> {
+        let status = error.close_code().unwrap_or(CloseCode::InternalError);
+
+        // Not sent if the random source can't mask it
+        if let (Ok(mut stream), Ok(mask)) = (self.shared.output.try_lock(), self.shared.new_mask()) {
+            let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, Some(status.into()), Vec::new(), Some(mask));
+            let _ = write_all_wait(&mut stream, &close_frame.serialize(), self.shared.timeout); // Ignore result, the connection will be closed anyway
+        }
+
+        self.recv_storage.clear();
+        self.recv_data.clear();
+        self.shared.close_connection(&self.stream, Reason::CLIENT_CLOSE(status, String::new()));
+
+        Err(error)
+    }
+}
+
+// Write half of a client returned by split, can be used from another thread than the reader
+pub struct WSWriter {
+    shared: Arc<Shared>,
+    message_size: u64
+}
+
+impl WSWriter {
+    // Send the message and wait until is written in the socket, returns ConnectionClose once the close handshake started
+    pub fn send(&mut self, payload: &str) -> WebSocketResult<()> {
+        self.send_message(OPCODE::TEXT, payload.as_bytes().to_vec())
+    }
+
+    pub fn send_binary(&mut self, payload: &[u8]) -> WebSocketResult<()> {
+        self.send_message(OPCODE::BINARY, payload.to_vec())
+    }
+
+    // The payload can't be longer than 125 bytes
+    pub fn ping(&mut self, payload: &[u8]) -> WebSocketResult<()> {
+        if payload.len() > MAX_CONTROL_PAYLOAD { return Err(WebSocketError::Protocol(ProtocolError::InvalidFrame)) }
+        if self.shared.status() != ConnectionStatus::OPEN { return Err(WebSocketError::ConnectionClose) }
+
+        let ping_frame = ControlFrame::new(FLAG::FIN, OPCODE::PING, None, payload.to_vec(), Some(self.shared.new_mask()?));
+        self.shared.write(&ping_frame.serialize())
+    }
+
+    // Start the close handshake, the reader returns ConnectionClose once the server answers
+    pub fn close(&mut self, code: CloseCode, reason: &str) -> WebSocketResult<()> {
+        self.shared.close(code, reason)
+    }
+
+    // Returns who closed the connection, the status code and the reason once the connection is closed
+    pub fn close_reason(&self) -> Option<Reason> {
+        self.shared.close_reason()
+    }
+
+    fn send_message(&mut self, opcode: OPCODE, payload: Vec<u8>) -> WebSocketResult<()> {
+        if self.shared.status() != ConnectionStatus::OPEN { return Err(WebSocketError::ConnectionClose) }
+
+        // Transform the message to send with the negotiated extensions
+        let mut frame = ExtensionFrame::new(opcode.bits(), 0, payload);
+        for extension in lock(&self.shared.extensions).iter_mut() {
+            extension.encode(&mut frame)?;
+        }
+
+        let ExtensionFrame { rsv, payload, .. } = frame;
+        let mut bytes = Vec::new();
+        let mut data_sent = 0;
+
+        loop {
+            let end = (data_sent + self.message_size as usize).min(payload.len());
+            let mut flag = if end == payload.len() { FLAG::FIN } else { FLAG::NOFLAG };
+            // The reserved bits set by the extensions go in the first frame of the message
+            if data_sent == 0 { flag |= FLAG::from_bits_truncate(rsv) }
+            let code = if data_sent == 0 { opcode.clone() } else { OPCODE::CONTINUATION };
+            bytes.extend_from_slice(&DataFrame::new(flag, code, payload[data_sent..end].to_vec(), Some(self.shared.new_mask()?)).serialize());
+
+            data_sent = end;
+            if data_sent >= payload.len() { break }
+        }
+
+        self.shared.write(&bytes)
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+use crate::ws_basic::header::OPCODE;
+
+// Counters of the connection returned by WSClient::stats(). They are kept after a reconnection (init again)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub bytes_sent: u64,                                     // Bytes written to the socket, handshake included
+    pub bytes_received: u64,                                 // Bytes read from the socket, handshake included
+    pub frames_sent: u64,                                    // With fixed buffers the frames are counted once serialized in the send buffer
+    pub frames_received: u64,
+    pub text_messages_sent: u64,
+    pub binary_messages_sent: u64,
+    pub text_messages_received: u64,
+    pub binary_messages_received: u64,
+    pub pings_sent: u64,
+    pub pongs_sent: u64,
+    pub pings_received: u64,
+    pub pongs_received: u64,
+    pub last_rtt: Option<Duration>,                          // Time from the last ping sent to the pong received
+    pub queue_high_water_messages: u64,                      // Max messages waiting to be sent at the same time
+    pub queue_high_water_bytes: u64,                         // Max payload bytes waiting to be sent at the same time
+    pub handshake_duration: Option<Duration>,                // From the handshake request to the response of the last connection
+    pub reconnects: u64                                      // Times init was called again to connect with the same client
+}
+
+// Hook of WSClient::set_metrics_hook, receives the data of the config like the callback
+pub type MetricsHook<T> = fn(&Stats, Option<T>);
+
+// Counters updated by the client in the event loop, plain integers because the client isn't shared between threads
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub stats: Stats,
+    send_binary: bool,                                       // Kind of the message being sent, the continuation frames don't have it
+    recv_binary: bool,                                       // Kind of the message being received
+    ping_sent_at: Option<Instant>
+}
+
+impl Counters {
+    pub fn bytes_sent(&mut self, bytes: usize) {
+        self.stats.bytes_sent += bytes as u64;
+    }
+
+    pub fn bytes_received(&mut self, bytes: usize) {
+        self.stats.bytes_received += bytes as u64;
+    }
+
+    // A message is counted with its FIN frame
+    pub fn frame_sent(&mut self, opcode: &OPCODE, fin: bool) {
+        self.stats.frames_sent += 1;
+
+        if *opcode == OPCODE::TEXT || *opcode == OPCODE::BINARY { self.send_binary = *opcode == OPCODE::BINARY }
+
+        if *opcode == OPCODE::PING {
+            self.stats.pings_sent += 1;
+            self.ping_sent_at = Some(Instant::now());
+        } else if *opcode == OPCODE::PONG {
+            self.stats.pongs_sent += 1;
+        } else if fin && *opcode != OPCODE::CLOSE {
+            if self.send_binary { self.stats.binary_messages_sent += 1 } else { self.stats.text_messages_sent += 1 }
+        }
+    }
+
+    // The pong of the last ping sent gives the round trip time, a pong not requested doesn't change it
+    pub fn frame_received(&mut self, opcode: &OPCODE, fin: bool) {
+        self.stats.frames_received += 1;
+
+        if *opcode == OPCODE::TEXT || *opcode == OPCODE::BINARY { self.recv_binary = *opcode == OPCODE::BINARY }
+
+        if *opcode == OPCODE::PING {
+            self.stats.pings_received += 1;
+        } else if *opcode == OPCODE::PONG {
+            self.stats.pongs_received += 1;
+            if let Some(sent_at) = self.ping_sent_at.take() { self.stats.last_rtt = Some(sent_at.elapsed()) }
+        } else if fin && *opcode != OPCODE::CLOSE {
+            if self.recv_binary { self.stats.binary_messages_received += 1 } else { self.stats.text_messages_received += 1 }
+        }
+    }
+
+    pub fn queued(&mut self, messages: usize, bytes: usize) {
+        self.stats.queue_high_water_messages = self.stats.queue_high_water_messages.max(messages as u64);
+        self.stats.queue_high_water_bytes = self.stats.queue_high_water_bytes.max(bytes as u64);
+    }
+}
@@ -0,0 +1,78 @@
+use std::io::{self, Write, ErrorKind};
+use crate::result::{WebSocketError, WebSocketResult};
+use crate::ws_basic::header::OPCODE;
+use super::client::{WSClient, MessageId, MessageKind};
+
+// Message sent in several frames, returned by client.begin_message. Each write queues a frame (at most message_size bytes)
+// and finish sends the FIN frame (with fixed buffers the frames are also limited by the send buffer). The control frames can go between the frames and the messages queued with send
+// while the message is written are sent after it.
+// A write waits (running the event loop of the client) until the frames queued fit in the outbound queue limits,
+// it fails with TimedOut after the timeout of the client. Dropping the writer finishes the message.
+// The messages streamed are not transformed by the extensions
+pub struct MessageWriter<'c, 'a, T: Clone> {
+    client: &'c mut WSClient<'a, T>,
+    opcode: OPCODE,
+    id: MessageId,
+    first: bool,                                             // No frame was queued yet, the next one has the opcode of the message
+    finished: bool
+}
+
+impl<'c, 'a, T: Clone> MessageWriter<'c, 'a, T> {
+    pub(crate) fn new(client: &'c mut WSClient<'a, T>, kind: MessageKind, id: MessageId) -> Self {
+        let opcode = match kind {
+            MessageKind::TEXT => OPCODE::TEXT,
+            MessageKind::BINARY => OPCODE::BINARY
+        };
+        MessageWriter { client, opcode, id, first: true, finished: false }
+    }
+
+    // Id of the message, ON_DRAINED is notified once all the frames are sent
+    pub fn id(&self) -> MessageId {
+        self.id
+    }
+
+    // Queue the FIN frame of the message and return its id. If it fails the writer is dropped and the drop ends the message
+    pub fn finish(mut self) -> WebSocketResult<MessageId> {
+        self.client.wait_for_room(0)?;
+        self.client.queue_fragment(self.next_opcode(), &[], true)?;
+        self.finished = true;
+        Ok(self.id)
+    }
+
+    fn next_opcode(&self) -> OPCODE {
+        if self.first { self.opcode.clone() } else { OPCODE::CONTINUATION }
+    }
+}
+
+impl<'c, 'a, T: Clone> Write for MessageWriter<'c, 'a, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() { return Ok(0) }
+
+        let len = buf.len().min(self.client.max_fragment_len()).max(1);
+        self.client.wait_for_room(len).map_err(to_io_error)?;
+        self.client.queue_fragment(self.next_opcode(), &buf[..len], false).map_err(to_io_error)?;
+        self.first = false;
+        Ok(len)
+    }
+
+    // Wait until the frames queued are written in the socket
+    fn flush(&mut self) -> io::Result<()> {
+        self.client.flush_output().map_err(to_io_error)
+    }
+}
+
+impl<'c, 'a, T: Clone> Drop for MessageWriter<'c, 'a, T> {
+    fn drop(&mut self) {
+        if self.finished { return }
+        self.client.end_fragments(self.next_opcode());
+    }
+}
+
+fn to_io_error(error: WebSocketError) -> io::Error {
+    match error {
+        WebSocketError::IOError(e) => e,
+        WebSocketError::Timeout => ErrorKind::TimedOut.into(),
+        WebSocketError::ConnectionClose => ErrorKind::NotConnected.into(),
+        e => io::Error::new(ErrorKind::Other, e)
+    }
+}
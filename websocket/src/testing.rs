@@ -0,0 +1,71 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+// Helpers to check that the client doesn't allocate (fixed buffers). Install the allocator in the test binary:
+//
+//     #[global_allocator]
+//     static ALLOCATOR: CountingAllocator = CountingAllocator;
+//
+// and measure the code with count_allocations. Only the allocations of the thread that calls count_allocations are counted
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static COUNT: Cell<usize> = const { Cell::new(0) };
+    static BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+// Allocations done while the function measured runs (reallocations included)
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Allocations {
+    pub count: usize,
+    pub bytes: usize
+}
+
+// Global allocator that counts the allocations of the system allocator while count_allocations runs
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    fn record(size: usize) {
+        // The thread locals can't be used while the thread is destroyed, the allocation isn't counted then
+        let _ = COUNTING.try_with(|counting| {
+            if !counting.get() { return }
+            COUNT.with(|count| count.set(count.get() + 1));
+            BYTES.with(|bytes| bytes.set(bytes.get() + size));
+        });
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        CountingAllocator::record(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        CountingAllocator::record(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        CountingAllocator::record(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+// Run the function and return its result with the allocations it did. Without CountingAllocator as the global allocator
+// the allocations are always 0
+pub fn count_allocations<R>(f: impl FnOnce() -> R) -> (R, Allocations) {
+    COUNT.with(|count| count.set(0));
+    BYTES.with(|bytes| bytes.set(0));
+    COUNTING.with(|counting| counting.set(true));
+
+    let result = f();
+
+    COUNTING.with(|counting| counting.set(false));
+    let allocations = Allocations { count: COUNT.with(|count| count.get()), bytes: BYTES.with(|bytes| bytes.get()) };
+    (result, allocations)
+}
@@ -1,4 +1,4 @@
-use std::convert::TryInto;
+use core::convert::TryInto;
 use core::array::TryFromSliceError;
 
 /// Return a unsigned 64 bits number from the given bytes asuming big endian representation.
@@ -1,15 +1,12 @@
 use std::any::Any;
-use crate::result::{WebSocketResult, WebSocketError};
-use super::{header::{Header, FLAG, OPCODE}, mask::{Mask, gen_mask}};
+use crate::result::{WebSocketResult, WebSocketError, ProtocolError};
+use crate::codec::{FrameDecoder, CodecError};
+use super::{header::{Header, FLAG, OPCODE}, mask::{Mask, apply_mask}};
 use super::super::core::traits::Serialize;
-use super::super::core::binary::{bytes_to_u16, bytes_to_u64};
+use super::binary::bytes_to_u16;
 
-#[derive(PartialEq)]
-pub enum FrameKind {
-    Data,
-    Control,
-    NotDefine
-}
+// The frames received are parsed in place by the codec
+pub use crate::codec::{FrameRef, FrameKind, peek_payload_len, is_frame_complete};
 
 pub trait Frame {
     // Return the data containing in the frame
@@ -34,35 +31,41 @@ pub trait Frame {
     // Return the byte representation of the frame, useful to send through a socket
     fn serialize(&self) -> Vec<u8> {
         let mut serialized_data = self.get_header().serialize();
+        let start = serialized_data.len();
 
-        match self.get_header().get_mask() {
-            // Apply mask to data
-            Some(mask) => {
-                let mut i = 0;
-                for &byte in self.get_data() {
-                    serialized_data.push(byte ^ mask[i]);
-                    i += 1;
-                    if i >= mask.len() { i = 0 };
-                }
-            },
-            // Just insert App data without mask
-            None => serialized_data.extend(self.get_data())
+        // Copy the payload once and mask it in place
+        serialized_data.reserve_exact(self.get_data().len());
+        serialized_data.extend_from_slice(self.get_data());
+        if let Some(mask) = self.get_header().get_mask() {
+            apply_mask(&mut serialized_data[start..], mask, 0);
         }
 
         return serialized_data;
     }
+
+    // Consume the frame to write it with write_vectored, the payload is masked in place and never copied
+    fn into_serialized(self: Box<Self>) -> SerializedFrame;
 }
 
-fn get_mask(mask_frame: bool, mask: Option<Mask>) -> Option<Mask> {
-    let mut _mask: Option<Mask> = None;
-        
-    if let Some(m) = mask {
-        _mask = Some(m);
-    } else if mask_frame {
-        _mask = Some(gen_mask());
+// Frame ready to be written: the bytes of the header and the payload (already masked) in separate buffers
+pub struct SerializedFrame {
+    header: Header,
+    head: Vec<u8>,
+    payload: Vec<u8>
+}
+
+impl SerializedFrame {
+    fn new(header: Header, payload: Vec<u8>) -> Self {
+        let head = header.serialize();
+        let mut payload = payload;
+        if let Some(mask) = header.get_mask() { apply_mask(&mut payload, mask, 0) }
+        SerializedFrame { header, head, payload }
     }
 
-    return _mask;
+    // Header, bytes of the header and payload masked
+    pub fn into_parts(self) -> (Header, Vec<u8>, Vec<u8>) {
+        (self.header, self.head, self.payload)
+    }
 }
 
 // Dataframe struct
@@ -72,8 +75,9 @@ pub struct DataFrame {
 }
 
 impl DataFrame {
-    pub fn new(flag: FLAG, opcode: OPCODE, data: Vec<u8>, mask_frame: bool, mask: Option<Mask>) -> Self {
-        let header: Header = Header::new(flag, opcode, get_mask(mask_frame, mask), data.len() as u64);
+    // The frames of a client are masked with a new key of its random source (gen_mask), the frames of a server have no mask
+    pub fn new(flag: FLAG, opcode: OPCODE, data: Vec<u8>, mask: Option<Mask>) -> Self {
+        let header: Header = Header::new(flag, opcode, mask, data.len() as u64);
         DataFrame { header, data }
     }
 }
@@ -90,6 +94,10 @@ impl Frame for DataFrame {
     fn get_header(&self) -> &Header {
         &self.header
     }
+
+    fn into_serialized(self: Box<Self>) -> SerializedFrame {
+        SerializedFrame::new(self.header, self.data)
+    }
 }
 
 // ControlFrame struct
@@ -101,7 +109,7 @@ pub struct ControlFrame {
 
 impl ControlFrame {
     // Payload should be <= 125 bytes
-    pub fn new(flag: FLAG, opcode: OPCODE, status_code: Option<u16>, data: Vec<u8>, mask_frame: bool, mask: Option<Mask>) -> Self {
+    pub fn new(flag: FLAG, opcode: OPCODE, status_code: Option<u16>, data: Vec<u8>, mask: Option<Mask>) -> Self {
         let status_len = if status_code.is_some() { 2 } else { 0 };
         let mut payload_len = data.len() + status_len;
 
@@ -112,7 +120,7 @@ impl ControlFrame {
             data = data[0..124-status_len].to_vec();
         }
 
-        let header = Header::new(flag, opcode, get_mask(mask_frame, mask), payload_len as u64);
+        let header = Header::new(flag, opcode, mask, payload_len as u64);
 
         let mut merge_data = Vec::new();
         if status_code.is_some() {
@@ -140,80 +148,35 @@ impl Frame for ControlFrame {
     fn get_header(&self) -> &Header {
         &self.header
     }
-}
-
-// try to convert the bytes into a frame
-// the offset means the bytes that are left over bytes[offset, end]
-pub fn bytes_to_frame(bytes: &[u8]) -> WebSocketResult<Option<(Box<dyn Frame>, usize)>>{
-    if bytes.len() < 2 {
-        let mut msg = String::from("Error parsing a frame, frame length must be >= 2, got: ");
-        msg.push_str(bytes.len().to_string().as_str());
-        return Ok(None);
-        // This is not an error, bytes could arrive later
-    }
 
-    // Flag
-    let flag = FLAG::from_bits(bytes[0] & 0b11110000);
-    if flag.is_none() { 
-        let mut msg = String::from("Invalid flag: ");
-        msg.push_str(bytes[0].to_string().as_str());
-        return Err(WebSocketError::InvalidFrame);
+    fn into_serialized(self: Box<Self>) -> SerializedFrame {
+        SerializedFrame::new(self.header, self.data)
     }
+}
 
-    
-    // code
-    let code = OPCODE::from_bits(bytes[0] & 0b000011111);
-    if  code.is_none() { 
-        let mut msg = String::from("Invalid opcode: ");
-        msg.push_str(bytes[1].to_string().as_str());
-        return Err(WebSocketError::InvalidFrame);
-    }
-    
-    let is_masked = (0b10000000 & bytes[1]) == 1;
-    
-    // Payload length
-    let mut payload_len: u64 = 0b01111111 as u64 & bytes[1] as u64;
-    let mut i = 2; // Index to know the start point of the mask if exists
-    
-    if payload_len == 126 {
-        i = 4;
-        payload_len = bytes_to_u16(&bytes[2..4]).unwrap() as u64;
-    } else if payload_len == 127 {
-        i = 10;
-        payload_len = bytes_to_u64(&bytes[2..10]).unwrap();
-    }
-    
-    // bytes not received completelly due to buffers from the OS
-    if payload_len + i as u64 > bytes.len() as u64 { return Ok(None) }
-    // if payload_len + i as u64 > bytes.len() as u64 { return Err(WebSocketError::Custom(String::from("Frame is not completelly readed"))); }
-    
-    // Mask Key
-    let mut mask: Option<Mask> = None;
-    if is_masked {
-        let mut buf: [u8; 4] = [0,0,0,0];
-        for j in 0..4 {
-            buf[j] = bytes[i+j];
+impl<'a> FrameRef<'a> {
+    // Copy of a control frame (at most 125 bytes), to handle it after the bytes received are consumed.
+    // Fails if the body of a close frame doesn't start with a status code
+    pub(crate) fn to_control_frame(&self) -> WebSocketResult<ControlFrame> {
+        let data = self.payload();
+        let (flag, code) = (self.get_flag(), self.get_opcode());
+        // Only the close frame has a status code, and it's optional
+        if code != OPCODE::CLOSE || data.is_empty() {
+            return Ok(ControlFrame::new(flag, code, None, data.to_vec(), None));
         }
-        mask = Some(buf);
-        i += 4;
-    }
-    
-    let flag = flag.unwrap();
-    let code = code.unwrap();
-
-    let offset = i + payload_len as usize;
 
-    // Dataframe
-    if code == OPCODE::TEXT || code == OPCODE::BINARY || code == OPCODE::CONTINUATION {
-        let data = &bytes[i..payload_len as usize +i];
-        return Ok(Some((Box::new(DataFrame::new(flag, code, data.to_vec(), false, mask)), offset)));
-
-    // ControlFrame
-    } else {
-        let status_code = bytes_to_u16(&bytes[i..i+2]).unwrap();
-        let data = &bytes[i+2..payload_len as usize + 2];
-        return Ok(Some((Box::new(ControlFrame::new(flag, code, Some(status_code), data.to_vec(), false, mask)), offset)));
+        // The body of a close frame must start with a status code of 2 bytes
+        if data.len() < 2 { return Err(WebSocketError::Protocol(ProtocolError::InvalidCloseFrame)) }
+        let status_code = bytes_to_u16(&data[0..2]).unwrap();
+        Ok(ControlFrame::new(flag, code, Some(status_code), data[2..].to_vec(), None))
     }
+}
 
-    // if bytes.len() == 0 { break } // Al frames readed
-}
\ No newline at end of file
+// Parse the frame at the start of the bytes without copying the payload.
+// None if the frame was not completelly received (bytes could arrive later)
+pub fn parse_frame(bytes: &[u8]) -> WebSocketResult<Option<FrameRef<'_>>> {
+    // The clients check their own limits with the header before parsing the frame
+    let mut decoder = FrameDecoder::new();
+    decoder.set_max_frame_size(u64::MAX);
+    decoder.decode(bytes).map_err(|e: CodecError| e.into())
+}
@@ -1,4 +1,5 @@
 use super::mask::Mask;
+#[cfg(feature = "std")]
 use super::super::core::traits::Serialize;
 
 use bitflags::bitflags;
@@ -47,7 +48,11 @@ impl Header {
     pub fn new(flag: FLAG, code: OPCODE, mask_key: Option<Mask>, payload_len: u64) -> Self {
         Header { flag, code, mask_key, payload_len }
     }
+}
 
+// The frames of the clients read the header back, the codec only writes it
+#[cfg(feature = "std")]
+impl Header {
     // Return the mask if the frame is masked
     pub fn get_mask(&self) -> Option<Mask> {
         self.mask_key
@@ -61,42 +66,65 @@ impl Header {
     pub fn get_flag(&self) -> FLAG {
         self.flag
     }
-}
 
-// Serialize header into bytes usefull for sending over sockets
-impl Serialize for Header {
-    fn serialize(&self) -> Vec<u8> {
-        let mut buffer: Vec<u8> = vec![];
+    // Return the length of the payload
+    pub fn get_payload_len(&self) -> u64 {
+        self.payload_len
+    }
+}
 
-        let mut header1: u8 = self.flag.bits();
+// Max bytes of a header: first byte, payload length (1 + 8 bytes) and mask
+pub const MAX_HEADER_LEN: usize = 14;
+
+// Bytes of the header of a frame with the payload length
+pub fn header_len(payload_len: u64, masked: bool) -> usize {
+    let mask_len = if masked { 4 } else { 0 };
+    if payload_len <= 125 {
+        2 + mask_len
+    } else if payload_len <= 65535 {
+        4 + mask_len
+    } else {
+        10 + mask_len
+    }
+}
 
+impl Header {
+    // Write the header in the array without allocating, returns the bytes used
+    pub fn write_to(&self, buffer: &mut [u8; MAX_HEADER_LEN]) -> usize {
         // OR with OPCODE to get the first part of the header
-        header1 |= self.code.bits();
-
-        buffer.push(header1);
+        buffer[0] = self.flag.bits() | self.code.bits();
 
         // Mask bit + Payload len
-        let mut header2 = if self.mask_key.is_some() { 0b10000000 } else { 0b00000000 };
+        let mask_bit = if self.mask_key.is_some() { 0b10000000 } else { 0b00000000 };
+        let mut len = 2;
 
-        if self.payload_len < 125 {
-            header2 |= self.payload_len as u8;
-            buffer.push(header2);
+        if self.payload_len <= 125 {
+            buffer[1] = mask_bit | self.payload_len as u8;
         } else if self.payload_len <=  65535 { // 65535 = 2ˆ16 - 1 (max unsigned integer that can be represented with 16 bits)
-            header2 |= 126;
-            buffer.push(header2);
-            buffer.extend((self.payload_len as u16).to_be_bytes());
+            buffer[1] = mask_bit | 126;
+            buffer[2..4].copy_from_slice(&(self.payload_len as u16).to_be_bytes());
+            len = 4;
         } else {
-            header2 |= 127; // Payload len represented by a 64 bits number
-            buffer.push(header2);
-            buffer.extend(self.payload_len.to_be_bytes());
+            buffer[1] = mask_bit | 127; // Payload len represented by a 64 bits number
+            buffer[2..10].copy_from_slice(&self.payload_len.to_be_bytes());
+            len = 10;
         }
 
-        if self.mask_key.is_some() {
-            // Add mask
-            let mask = self.mask_key.unwrap();
-            buffer.extend(mask);
+        if let Some(mask) = self.mask_key {
+            buffer[len..len + 4].copy_from_slice(&mask);
+            len += 4;
         }
 
-        return buffer;
+        return len;
     }
-}
\ No newline at end of file
+}
+
+// Serialize header into bytes usefull for sending over sockets
+#[cfg(feature = "std")]
+impl Serialize for Header {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = [0u8; MAX_HEADER_LEN];
+        let len = self.write_to(&mut buffer);
+        return buffer[..len].to_vec();
+    }
+}
@@ -1,14 +1,15 @@
-use getrandom as rand; 
 use base64;
+use crate::codec::{RandomSource, CodecError};
 use sha1_smol::Sha1;
 
 // Globally Unique Identifier
 const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
 
-pub fn gen_key() -> String {
+// Sec-WebSocket-Key of the handshake, 16 random bytes in base64
+pub fn gen_key<R: RandomSource + ?Sized>(random: &mut R) -> Result<String, CodecError> {
     let mut buf: [u8; 16] = [0u8; 16];
-    let _ = rand::getrandom(&mut buf); // ignore error
-    return base64::encode(&buf);
+    random.fill(&mut buf)?;
+    return Ok(base64::encode(&buf));
 }
 
 pub fn verify_key(sec_websocket_key: &str, sec_websocket_accept: &str) -> bool {
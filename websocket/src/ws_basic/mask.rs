@@ -1,9 +1,57 @@
-use getrandom as rand;
+use core::mem::size_of;
+use crate::codec::{RandomSource, CodecError};
 
 pub type Mask = [u8; 4];
 
-pub fn gen_mask() -> Mask {
+// New masking key from the random source, fails if the source can't generate it (a predictable mask is never used)
+pub fn gen_mask<R: RandomSource + ?Sized>(random: &mut R) -> Result<Mask, CodecError> {
     let mut buf: Mask = [0u8; 4];
-    let _ = rand::getrandom(&mut buf); // Ignore error
-    return buf
-}
\ No newline at end of file
+    random.fill(&mut buf)?;
+    return Ok(buf)
+}
+
+// Mask the payload in place. The offset is the position of the first byte in the payload, to mask a payload in several parts.
+// The bytes are masked a word at a time (4 bytes in 32 bit targets like the ESP32, 8 bytes in 64 bit targets),
+// only the unaligned bytes at the start and the end are masked one by one
+pub fn apply_mask(bytes: &mut [u8], mask: Mask, offset: usize) {
+    let mask = rotate(mask, offset);
+
+    // Safe: any bit pattern is a valid usize
+    let (head, words, tail) = unsafe { bytes.align_to_mut::<usize>() };
+    mask_bytes(head, mask);
+
+    // The words start after the head, a whole word keeps the position in the mask for the tail
+    let mask = rotate(mask, head.len());
+    let mut word = [0u8; size_of::<usize>()];
+    for (i, byte) in word.iter_mut().enumerate() { *byte = mask[i & 3] }
+    let word = usize::from_ne_bytes(word);
+
+    for w in words.iter_mut() { *w ^= word }
+    mask_bytes(tail, mask);
+}
+
+// Unmask a payload received in place (masking is an xor, the same operation), for the frames sent by the clients to a server
+pub fn unmask(bytes: &mut [u8], mask: Mask, offset: usize) {
+    apply_mask(bytes, mask, offset)
+}
+
+// Byte by byte implementation, the reference for the equivalence tests and the benchmarks
+#[doc(hidden)]
+pub fn apply_mask_reference(bytes: &mut [u8], mask: Mask, offset: usize) {
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte ^= mask[(offset + i) & 3];
+    }
+}
+
+fn mask_bytes(bytes: &mut [u8], mask: Mask) {
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte ^= mask[i & 3];
+    }
+}
+
+// Mask that starts at the position of the offset
+fn rotate(mask: Mask, offset: usize) -> Mask {
+    let mut rotated = mask;
+    rotated.rotate_left(offset & 3);
+    rotated
+}
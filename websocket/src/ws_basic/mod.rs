@@ -1,7 +1,10 @@
+#[cfg(feature = "std")]
 pub mod frame;
 pub mod header;
 pub mod mask;
 pub mod status_code;
+pub mod binary;
+#[cfg(feature = "std")]
 pub mod key;
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod test;
\ No newline at end of file
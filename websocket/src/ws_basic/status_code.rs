@@ -1,36 +1,131 @@
-use bitflags::bitflags;
-
-bitflags! {
-    #[allow(non_camel_case_types)]
-    #[derive(PartialEq, Eq, Clone, Copy)]
-    pub struct WSStatus: u16 {
-        const NORMAL_CLOSURE = 1000;                                // Close the connection, no error
-        const GOING_AWAY = 1001;                                    // Close the connection, no error
-        const PROTOCOL_ERROR = 1002;                                // Close the connection, error
-        const TYPE_DATA_NOT_ACCEPTABLE = 1003;                      // Close the connection, error
-        const RESERVED_1004 = 1004;                                 // RFC6455 | Reserved. The specific meaning might be defined in the future
-        const EXPECTED_STATUS_CODE = 1005;                          // MUST NOT be set as a status code in Close control frame by an endpoint. It is designated for use in applications expecting a status code to indicate that no status code was actually present. 
-        const CONNECTION_CLOSE_ABNORMALLY = 1006;                   // reserved value and MUST NOT be set as a status code in a Close control frame by an endpoint 
-        const INCONSISTENT_DATA_TYPE_INSIDE_MESSAGE = 1007;         // Close the connection, error  For example, not utf-8 data in a text message
-        const POLICY_VIOLATION = 1008;                              // Close, error
-        const MESSAGE_TO_BIG = 1009;                                // Close error
-        const EXPECTED_EXTENSION_NEGOTIATION_WITH_SERVER = 1010;    // Close, error (Only for clients) The list of extensions needed should appear in the reason part of the frame
-        const UNEXPECTED_CONDITION_ENCOUNTERED = 1011;              // CLOSE: indicates that a server is terminating the connection because it encountered an unexpected condition that prevented it from fulfilling the request.
-        const TLS_HANDSHAKE_ERROR = 1015;                           // (Only  for clients) the server certificate can't be verified
+use core::fmt;
+use core::convert::TryFrom;
+
+// Status code of a close frame (RFC 6455 7.4)
+// - 1000-2999: Defined by the protocol (RFC 6455 and IANA registry), the codes not assigned yet are kept in Protocol
+// - 3000-3999: Registered by libraries, frameworks and applications in the IANA registry
+// - 4000-4999: Private use, not registered
+// Codes out of the range 1000-4999 are not valid. The value of Protocol, Registered and Private is only checked by try_from,
+// a code built with a value out of its range is never sent (may_send)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CloseCode {
+    NormalClosure,                                           // 1000
+    GoingAway,                                               // 1001
+    ProtocolError,                                           // 1002
+    UnsupportedData,                                         // 1003
+    Reserved,                                                // 1004 The meaning might be defined in the future
+    NoStatusReceived,                                        // 1005 Reported when the close frame doesn't have a status code, never sent
+    AbnormalClosure,                                         // 1006 Reported when the connection is lost without a close frame, never sent
+    InvalidPayload,                                          // 1007 e.g: not UTF-8 data in a text message
+    PolicyViolation,                                         // 1008
+    MessageTooBig,                                           // 1009
+    MandatoryExtension,                                      // 1010 (Only for clients) The server didn't negotiate the extensions required
+    InternalError,                                           // 1011
+    ServiceRestart,                                          // 1012
+    TryAgainLater,                                           // 1013
+    BadGateway,                                              // 1014
+    TlsHandshake,                                            // 1015 The server certificate can't be verified, never sent
+    Protocol(u16),                                           // 1016-2999 Not assigned yet
+    Registered(u16),                                         // 3000-3999
+    Private(u16),                                            // 4000-4999
+}
+
+impl CloseCode {
+    // Returns if the code reports a failure of the connection
+    pub fn is_error(&self) -> bool {
+        matches!(self,
+            CloseCode::ProtocolError | CloseCode::UnsupportedData | CloseCode::AbnormalClosure |
+            CloseCode::InvalidPayload | CloseCode::PolicyViolation | CloseCode::MessageTooBig |
+            CloseCode::MandatoryExtension | CloseCode::InternalError | CloseCode::BadGateway |
+            CloseCode::TlsHandshake
+        )
+    }
+
+    // Returns if the code is reserved by the protocol and can't be used by an endpoint
+    pub fn is_reserved(&self) -> bool {
+        matches!(self,
+            CloseCode::Reserved | CloseCode::NoStatusReceived | CloseCode::AbnormalClosure |
+            CloseCode::TlsHandshake | CloseCode::Protocol(_)
+        )
+    }
+
+    // Returns if the value of Protocol, Registered and Private is in the range of the variant
+    pub fn is_valid(&self) -> bool {
+        match self {
+            CloseCode::Protocol(code) => (1016..=2999).contains(code),
+            CloseCode::Registered(code) => (3000..=3999).contains(code),
+            CloseCode::Private(code) => (4000..=4999).contains(code),
+            _ => true
+        }
+    }
+
+    // Returns if the code can be set in a close frame.
+    // 1005, 1006 and 1015 are only used to report the closure to the application and never go on the wire
+    pub fn may_send(&self) -> bool {
+        self.is_valid() && !self.is_reserved()
     }
 }
 
-// Returns if there was an error and if the connection should be closed
-pub fn evaulate_status_code(status: WSStatus) -> (bool, bool) {
-    let mut is_error = true;
-    let mut should_close = true;
+impl TryFrom<u16> for CloseCode {
+    type Error = u16;
+
+    // Returns the code as error if it's out of the range 1000-4999
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        let close_code = match code {
+            1000 => CloseCode::NormalClosure,
+            1001 => CloseCode::GoingAway,
+            1002 => CloseCode::ProtocolError,
+            1003 => CloseCode::UnsupportedData,
+            1004 => CloseCode::Reserved,
+            1005 => CloseCode::NoStatusReceived,
+            1006 => CloseCode::AbnormalClosure,
+            1007 => CloseCode::InvalidPayload,
+            1008 => CloseCode::PolicyViolation,
+            1009 => CloseCode::MessageTooBig,
+            1010 => CloseCode::MandatoryExtension,
+            1011 => CloseCode::InternalError,
+            1012 => CloseCode::ServiceRestart,
+            1013 => CloseCode::TryAgainLater,
+            1014 => CloseCode::BadGateway,
+            1015 => CloseCode::TlsHandshake,
+            1016..=2999 => CloseCode::Protocol(code),
+            3000..=3999 => CloseCode::Registered(code),
+            4000..=4999 => CloseCode::Private(code),
+            _ => return Err(code)
+        };
+
+        Ok(close_code)
+    }
+}
 
-    match status {
-        WSStatus::NORMAL_CLOSURE => is_error = false,
-        WSStatus::GOING_AWAY => is_error = false,
-        WSStatus::RESERVED_1004 => { is_error = false; should_close = false; } // TODO: should_close true or false?
-        _ => {}
+impl From<CloseCode> for u16 {
+    fn from(code: CloseCode) -> u16 {
+        match code {
+            CloseCode::NormalClosure => 1000,
+            CloseCode::GoingAway => 1001,
+            CloseCode::ProtocolError => 1002,
+            CloseCode::UnsupportedData => 1003,
+            CloseCode::Reserved => 1004,
+            CloseCode::NoStatusReceived => 1005,
+            CloseCode::AbnormalClosure => 1006,
+            CloseCode::InvalidPayload => 1007,
+            CloseCode::PolicyViolation => 1008,
+            CloseCode::MessageTooBig => 1009,
+            CloseCode::MandatoryExtension => 1010,
+            CloseCode::InternalError => 1011,
+            CloseCode::ServiceRestart => 1012,
+            CloseCode::TryAgainLater => 1013,
+            CloseCode::BadGateway => 1014,
+            CloseCode::TlsHandshake => 1015,
+            CloseCode::Protocol(code) => code,
+            CloseCode::Registered(code) => code,
+            CloseCode::Private(code) => code,
+        }
     }
+}
 
-    return (is_error, should_close);
-}
\ No newline at end of file
+impl fmt::Display for CloseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", u16::from(*self))
+    }
+}
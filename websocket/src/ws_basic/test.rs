@@ -1,6 +1,9 @@
 use crate::core::traits::Serialize;
 use super::header::*;
 use super::mask::gen_mask;
+use super::key::gen_key;
+use super::frame::peek_payload_len;
+use crate::codec::{SeededRandom, RandomSource, CodecError};
 // -------------------------------------------------------------------------------------------------------- //
 //                                               header.rs
 // -------------------------------------------------------------------------------------------------------- //
@@ -53,7 +56,7 @@ fn serialize_header_with_no_mask_data_less_than_126() {
 fn serialize_header_with_mask_data_0() {
     let flag = FLAG::FIN;
     let opcode = OPCODE::TEXT;
-    let mask = gen_mask();
+    let mask = gen_mask(&mut SeededRandom::new(1)).unwrap();
     let payload_len = 0;
     let header = Header::new(flag, opcode, Some(mask), payload_len);
 
@@ -67,7 +70,7 @@ fn serialize_header_with_mask_data_0() {
 fn serialize_header_with_mask_data_less_than_126() {
     let flag = FLAG::FIN;
     let opcode = OPCODE::TEXT;
-    let mask = gen_mask();
+    let mask = gen_mask(&mut SeededRandom::new(1)).unwrap();
     let payload_len = 10;
     let header = Header::new(flag, opcode, Some(mask), payload_len);
 
@@ -119,7 +122,7 @@ fn serialize_header_with_no_mask_data_equal_65535() {
 fn serialize_header_with_mask_data_equal_126() {
     let flag = FLAG::FIN;
     let opcode = OPCODE::TEXT;
-    let mask = gen_mask();
+    let mask = gen_mask(&mut SeededRandom::new(1)).unwrap();
     let payload_len = 126;
     let header = Header::new(flag, opcode, Some(mask), payload_len);
 
@@ -132,7 +135,7 @@ fn serialize_header_with_mask_data_equal_126() {
 fn serialize_header_with_mask_data_greather_than_126_less_than_65535() {
     let flag = FLAG::FIN;
     let opcode = OPCODE::TEXT;
-    let mask = gen_mask();
+    let mask = gen_mask(&mut SeededRandom::new(1)).unwrap();
     let payload_len = 65530;
     let header = Header::new(flag, opcode, Some(mask), payload_len);
 
@@ -145,7 +148,7 @@ fn serialize_header_with_mask_data_greather_than_126_less_than_65535() {
 fn serialize_header_with_mask_data_equal_65535() {
     let flag = FLAG::FIN;
     let opcode = OPCODE::TEXT;
-    let mask = gen_mask();
+    let mask = gen_mask(&mut SeededRandom::new(1)).unwrap();
     let payload_len = 65535;
     let header = Header::new(flag, opcode, Some(mask), payload_len);
 
@@ -185,7 +188,7 @@ fn serialize_header_with_mask_data_equal_65536() {
     let flag = FLAG::FIN;
     let opcode = OPCODE::TEXT;
     let payload_len = 65536;
-    let mask = gen_mask();
+    let mask = gen_mask(&mut SeededRandom::new(1)).unwrap();
     let header = Header::new(flag, opcode, Some(mask), payload_len);
 
     let expected_result: Vec<u8> = [0x81, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, mask[0], mask[1], mask[2], mask[3]].to_vec();
@@ -198,7 +201,7 @@ fn serialize_header_with_mask_data_greather_than_65536() {
     let flag = FLAG::FIN;
     let opcode = OPCODE::TEXT;
     let payload_len = 1<<60;
-    let mask = gen_mask();
+    let mask = gen_mask(&mut SeededRandom::new(1)).unwrap();
     let header = Header::new(flag, opcode, Some(mask), payload_len);
 
     let expected_result: Vec<u8> = [0x81, 0xFF, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, mask[0], mask[1], mask[2], mask[3]].to_vec();
@@ -223,7 +226,7 @@ fn serialize_header_with_mask_data_equal_to_max_value() {
     let flag = FLAG::FIN;
     let opcode = OPCODE::TEXT;
     let payload_len = u64::MAX;
-    let mask = gen_mask();
+    let mask = gen_mask(&mut SeededRandom::new(1)).unwrap();
     let header = Header::new(flag, opcode, Some(mask), payload_len);
 
     let expected_result: Vec<u8> = [0x81, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, mask[0], mask[1], mask[2], mask[3]].to_vec();
@@ -257,7 +260,7 @@ fn serialize_data_masked() {
     let opcode = OPCODE::TEXT;
     let data = String::from("hello");
 
-    let dataframe = DataFrame::new(flag, opcode, data.as_bytes().to_vec(), true, None);
+    let dataframe = DataFrame::new(flag, opcode, data.as_bytes().to_vec(), Some(gen_mask(&mut SeededRandom::new(1)).unwrap()));
     let mask = dataframe.get_header().get_mask().unwrap();
     let serialized_frame = dataframe.serialize();
     let serialized_data = serialized_frame[serialized_frame.len() - data.len()..serialized_frame.len()].to_vec();
@@ -282,7 +285,7 @@ fn serialize_data_unmasked() {
     let opcode = OPCODE::TEXT;
     let data = String::from("hello");
 
-    let dataframe = DataFrame::new(flag, opcode, data.as_bytes().to_vec(), false, None);
+    let dataframe = DataFrame::new(flag, opcode, data.as_bytes().to_vec(), None);
 
     let serialized_frame = dataframe.serialize();
     let serialized_data = serialized_frame[serialized_frame.len() - data.len()..serialized_frame.len()].to_vec();
@@ -295,8 +298,59 @@ fn serialize_data_unmasked() {
     assert!(equals(serialized_frame, expected_frame));
 }
 
+#[test]
+fn serialized_frame_parts_match_serialize() {
+    let data: Vec<u8> = (0..300u16).map(|i| i as u8).collect();
+    let mask = [0x01, 0x02, 0x03, 0x04];
+
+    let expected = DataFrame::new(FLAG::FIN, OPCODE::BINARY, data.clone(), Some(mask)).serialize();
+    let frame: Box<dyn Frame> = Box::new(DataFrame::new(FLAG::FIN, OPCODE::BINARY, data, Some(mask)));
+    let (header, head, payload) = frame.into_serialized().into_parts();
+
+    assert_eq!(header.get_payload_len(), 300);
+    assert_eq!(head.len(), 8);
+    assert_eq!([head, payload].concat(), expected);
+}
+
+// ------------------- Masking ------------------- //
+use super::mask::{apply_mask as mask_in_place, unmask, apply_mask_reference};
+
+#[test]
+fn word_masking_matches_the_reference() {
+    let mask = [0x12, 0x34, 0x56, 0x78];
+    let payload: Vec<u8> = (0..100u8).collect();
+
+    // All the alignments of the start, lengths shorter and longer than a word and all the offsets
+    for start in 0..8 {
+        for len in 0..payload.len() - start {
+            for offset in 0..4 {
+                let mut masked = payload.clone();
+                let mut expected = payload.clone();
+                mask_in_place(&mut masked[start..start + len], mask, offset);
+                apply_mask_reference(&mut expected[start..start + len], mask, offset);
+                assert_eq!(masked, expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn unmask_restores_the_payload() {
+    let mask = gen_mask(&mut SeededRandom::new(1)).unwrap();
+    let payload = b"The payload is masked in two parts".to_vec();
+    let mut masked = payload.clone();
+
+    // Masked in two calls, unmasked in one
+    mask_in_place(&mut masked[..7], mask, 0);
+    mask_in_place(&mut masked[7..], mask, 7);
+    assert_eq!(masked, apply_mask(&payload, &mask));
+
+    unmask(&mut masked, mask, 0);
+    assert_eq!(masked, payload);
+}
+
 // ------------------- Control Frames ------------------- //
-use super::super::core::binary::bytes_to_u16;
+use super::binary::bytes_to_u16;
 
 #[test]
 fn serialize_controlframe_unmasked_without_status_code() {
@@ -304,7 +358,7 @@ fn serialize_controlframe_unmasked_without_status_code() {
     let opcode = OPCODE::PING;
     let data = String::from("hello");
 
-    let dataframe = ControlFrame::new(flag, opcode, None, data.as_bytes().to_vec(), false, None);
+    let dataframe = ControlFrame::new(flag, opcode, None, data.as_bytes().to_vec(), None);
     let serialized_frame = dataframe.serialize();
     let serialized_data = serialized_frame[serialized_frame.len() - data.len()..serialized_frame.len()].to_vec();
 
@@ -323,7 +377,7 @@ fn serialize_controlframe_unmasked_with_status_code() {
     let data = String::from("hello");
     let status_code = 1000;
 
-    let dataframe = ControlFrame::new(flag, opcode, Some(status_code), data.as_bytes().to_vec(), false, None);
+    let dataframe = ControlFrame::new(flag, opcode, Some(status_code), data.as_bytes().to_vec(), None);
     let serialized_frame = dataframe.serialize();
     let serialized_data = serialized_frame[serialized_frame.len() - data.len()..serialized_frame.len()].to_vec();
 
@@ -341,9 +395,9 @@ fn serialize_controlframe_masked_without_status_code() {
     let flag = FLAG::FIN;
     let opcode = OPCODE::PONG;
     let data = String::from("hello");
-    let mask = gen_mask();
+    let mask = gen_mask(&mut SeededRandom::new(1)).unwrap();
 
-    let dataframe = ControlFrame::new(flag, opcode, None, data.as_bytes().to_vec(), false, Some(mask));
+    let dataframe = ControlFrame::new(flag, opcode, None, data.as_bytes().to_vec(), Some(mask));
     let serialized_frame = dataframe.serialize();
     let serialized_data = serialized_frame[serialized_frame.len() - data.len()..serialized_frame.len()].to_vec();
     let _d = apply_mask(serialized_data.as_slice(), &mask);
@@ -365,10 +419,10 @@ fn serialize_controlframe_masked_with_status_code() {
     let flag = FLAG::FIN;
     let opcode = OPCODE::PONG;
     let data = String::from("hello");
-    let mask = gen_mask();
+    let mask = gen_mask(&mut SeededRandom::new(1)).unwrap();
     let status_code = 1000;
 
-    let dataframe = ControlFrame::new(flag, opcode, Some(status_code), data.as_bytes().to_vec(), false, Some(mask));
+    let dataframe = ControlFrame::new(flag, opcode, Some(status_code), data.as_bytes().to_vec(), Some(mask));
     let serialized_frame = dataframe.serialize();
     let serialized_data = serialized_frame[serialized_frame.len() - data.len() - 2..serialized_frame.len()].to_vec();
 
@@ -390,4 +444,194 @@ fn serialize_controlframe_masked_with_status_code() {
 
     assert_eq!(status_code, _status);
     assert_eq!(data, _data);
-}
\ No newline at end of file
+}
+
+// ------------------- Payload length from header ------------------- //
+
+#[test]
+fn peek_payload_len_incomplete_header() {
+    assert_eq!(peek_payload_len(&[0x81]), None);
+    assert_eq!(peek_payload_len(&[0x81, 0x7E, 0x01]), None);
+    assert_eq!(peek_payload_len(&[0x82, 0x7F, 0x00, 0x00, 0x00]), None);
+}
+
+#[test]
+fn peek_payload_len_without_payload() {
+    assert_eq!(peek_payload_len(&[0x81, 0x05]), Some(5));
+    assert_eq!(peek_payload_len(&[0x81, 0x7E, 0xFF, 0xFA]), Some(65530));
+    assert_eq!(peek_payload_len(&[0x82, 0x7F, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]), Some(4294967296));
+}
+
+// ------------------- Close frame parsing ------------------- //
+use super::frame::parse_frame;
+
+#[test]
+fn parse_close_frame_without_body() {
+    let frame = parse_frame(&[0x88, 0x00]).unwrap().unwrap();
+    assert_eq!(frame.frame_len(), 2);
+    let frame = frame.to_control_frame().unwrap();
+
+    assert_eq!(frame.get_status_code(), None);
+    assert!(frame.get_data().is_empty());
+}
+
+#[test]
+fn parse_close_frame_with_status_and_reason() {
+    let frame = parse_frame(&[0x88, 0x04, 0x03, 0xE8, b'o', b'k', 0x81, 0x00]).unwrap().unwrap();
+    assert_eq!(frame.frame_len(), 6);
+    let frame = frame.to_control_frame().unwrap();
+
+    assert_eq!(frame.get_status_code(), Some(1000));
+    assert_eq!(&frame.get_data()[2..], b"ok");
+}
+
+#[test]
+fn parse_close_frame_with_one_byte_body() {
+    assert!(parse_frame(&[0x88, 0x01, 0x03]).unwrap().unwrap().to_control_frame().is_err());
+}
+
+#[test]
+fn parse_ping_frame_keeps_the_payload() {
+    let frame = parse_frame(&[0x89, 0x03, 0x01, 0x02, 0x03]).unwrap().unwrap().to_control_frame().unwrap();
+
+    assert_eq!(frame.get_status_code(), None);
+    assert_eq!(frame.get_data(), &[0x01, 0x02, 0x03]);
+}
+
+#[test]
+fn parse_data_frame_borrows_the_payload() {
+    let bytes = [0x01, 0x02, b'h', b'i', 0x80, 0x01, b'!'];
+    let frame = parse_frame(&bytes).unwrap().unwrap();
+
+    assert!(frame.get_opcode() == OPCODE::TEXT && !frame.get_flag().contains(FLAG::FIN));
+    assert_eq!(frame.payload().as_ptr(), bytes[2..].as_ptr());
+    assert_eq!(frame.frame_len(), 4);
+
+    let frame = parse_frame(&bytes[4..]).unwrap().unwrap();
+    assert!(frame.get_opcode() == OPCODE::CONTINUATION && frame.get_flag().contains(FLAG::FIN));
+    assert_eq!(frame.payload(), b"!");
+}
+
+#[test]
+fn parse_incomplete_and_masked_frames() {
+    assert!(parse_frame(&[0x81, 0x05, b'h', b'i']).unwrap().is_none());
+    assert!(parse_frame(&[0x81, 0x7E, 0x01]).unwrap().is_none());
+    assert!(parse_frame(&[0x81, 0x81, 0x01, 0x02, 0x03, 0x04, b'h']).is_err());
+}
+
+// ------------------- Close codes ------------------- //
+use super::status_code::CloseCode;
+use std::convert::TryFrom;
+
+#[test]
+fn close_code_from_u16() {
+    assert_eq!(CloseCode::try_from(1000), Ok(CloseCode::NormalClosure));
+    assert_eq!(CloseCode::try_from(1014), Ok(CloseCode::BadGateway));
+    assert_eq!(CloseCode::try_from(2000), Ok(CloseCode::Protocol(2000)));
+    assert_eq!(CloseCode::try_from(3001), Ok(CloseCode::Registered(3001)));
+    assert_eq!(CloseCode::try_from(4999), Ok(CloseCode::Private(4999)));
+    assert_eq!(CloseCode::try_from(999), Err(999));
+    assert_eq!(CloseCode::try_from(5000), Err(5000));
+}
+
+#[test]
+fn close_code_round_trip() {
+    for code in 1000..5000u16 {
+        assert_eq!(u16::from(CloseCode::try_from(code).unwrap()), code);
+    }
+}
+
+#[test]
+fn close_codes_allowed_on_the_wire() {
+    for code in [1000, 1001, 1002, 1003, 1007, 1011, 1014, 3000, 4999] {
+        assert!(CloseCode::try_from(code).unwrap().may_send(), "{code}");
+    }
+
+    for code in [1004, 1005, 1006, 1015, 1016, 2999] {
+        assert!(CloseCode::try_from(code).unwrap().is_reserved(), "{code}");
+        assert!(!CloseCode::try_from(code).unwrap().may_send(), "{code}");
+    }
+}
+
+#[test]
+fn close_codes_out_of_their_range_are_not_sent() {
+    for code in [CloseCode::Private(7), CloseCode::Private(3000), CloseCode::Registered(1000), CloseCode::Registered(4000), CloseCode::Protocol(5000)] {
+        assert!(!code.is_valid(), "{code}");
+        assert!(!code.may_send(), "{code}");
+    }
+    assert!(CloseCode::Registered(3000).is_valid() && CloseCode::Private(4999).may_send());
+}
+
+#[test]
+fn close_code_is_error() {
+    assert!(!CloseCode::NormalClosure.is_error());
+    assert!(!CloseCode::GoingAway.is_error());
+    assert!(!CloseCode::NoStatusReceived.is_error());
+    assert!(CloseCode::ProtocolError.is_error());
+    assert!(CloseCode::AbnormalClosure.is_error());
+    assert!(CloseCode::MessageTooBig.is_error());
+}
+// -------------------------------------------------------------------------------------------------------- //
+//                                               binary.rs
+// -------------------------------------------------------------------------------------------------------- //
+use super::binary::*;
+
+#[test]
+fn valid_bytes_to_u16 () {
+    let bytes = [67,218];
+    let res = bytes_to_u16(bytes.as_slice());
+    assert!(res.is_ok());
+    let value: u16 = (256u16.pow(1) * 67) + (256u16.pow(0) * 218);
+    assert_eq!(res.unwrap(), value);
+}
+
+#[test]
+fn invalid_bytes_to_u16 () {
+    let bytes = [8,0,1];
+    let res = bytes_to_u16(bytes.as_slice());
+    assert!(res.is_err());
+}
+
+#[test]
+fn valid_bytes_to_u64 () {
+    let bytes = [1,0,0,0,0,0,5,255];
+    let res = bytes_to_u64(bytes.as_slice());
+    assert!(res.is_ok());
+    let value: u64 = (256u64.pow(7) * 1) + (256u64.pow(1) * 5) + (256u64.pow(0) * 255);
+    assert_eq!(res.unwrap(), value);
+}
+
+#[test]
+fn invalid_bytes_to_u64 () {
+    let bytes = [8,0,1];
+    let res = bytes_to_u64(bytes.as_slice());
+    assert!(res.is_err());
+}
+
+// The same seed gives the same masks, the frames can be compared byte by byte
+#[test]
+fn serialize_is_byte_exact_with_a_seeded_random() {
+    let mut random = SeededRandom::new(7);
+
+    let dataframe = DataFrame::new(FLAG::FIN, OPCODE::TEXT, b"hello".to_vec(), Some(gen_mask(&mut random).unwrap()));
+    assert_eq!(dataframe.serialize(), [0x81, 0x85, 0xD7, 0x0D, 0x32, 0x59, 0xBF, 0x68, 0x5E, 0x35, 0xB8]);
+
+    let close_frame = ControlFrame::new(FLAG::FIN, OPCODE::CLOSE, Some(1000), b"bye".to_vec(), Some(gen_mask(&mut random).unwrap()));
+    assert_eq!(close_frame.serialize(), [0x88, 0x85, 0x1C, 0x66, 0x3C, 0xF4, 0x1F, 0x8E, 0x5E, 0x8D, 0x79]);
+}
+
+#[test]
+fn mask_and_key_fail_without_random_bytes() {
+    struct FailingRandom;
+
+    impl RandomSource for FailingRandom {
+        fn fill(&mut self, _bytes: &mut [u8]) -> Result<(), CodecError> {
+            Err(CodecError::Random)
+        }
+    }
+
+    // Never a mask or a key of zeros
+    assert_eq!(gen_mask(&mut FailingRandom), Err(CodecError::Random));
+    assert_eq!(gen_key(&mut FailingRandom), Err(CodecError::Random));
+    assert_eq!(gen_key(&mut SeededRandom::new(7)).unwrap(), "1w0yWeThy2McZjz01zxMBA==");
+}
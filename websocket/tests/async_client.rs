@@ -0,0 +1,285 @@
+#![cfg(feature = "async")]
+use std::net::{TcpListener, TcpStream};
+use std::io::{Write, Read};
+use std::thread;
+use std::time::Duration;
+use websocket_std::asynchronous::client::{AsyncWSClient, AsyncConfig};
+use websocket_std::sync::client::{Reason, Message};
+use websocket_std::codec::{FrameEncoder, RandomSource, SeededRandom, OPCODE, FLAG, shared_random};
+use websocket_std::result::{WebSocketError, HandShakeError, ProtocolError};
+use websocket_std::CloseCode;
+use futures_util::{SinkExt, StreamExt};
+use sha1_smol::Sha1;
+
+// Globally Unique Identifier
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+fn setup() -> (TcpListener, u16) {
+    let listener = TcpListener::bind("localhost:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    (listener, port)
+}
+
+// Answer the handshake, if accept is false the Sec-WebSocket-Accept header is not sent
+fn mock_accept_connection(listener: TcpListener, accept: bool) -> TcpStream {
+    let (mut conn, _) = listener.accept().unwrap();
+
+    let mut request = Vec::new();
+    while !request.ends_with(b"\r\n\r\n") {
+        let mut byte = [0u8; 1];
+        conn.read_exact(&mut byte).unwrap();
+        request.push(byte[0]);
+    }
+
+    let request = String::from_utf8(request).unwrap();
+    let key_line = request.split("\r\n").find(|line| line.to_lowercase().starts_with("sec-websocket-key")).unwrap();
+    let key = key_line[key_line.find(":").unwrap() + 1..].trim();
+
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{}{}", key, GUID).as_bytes());
+    let accept_key = base64::encode(&hasher.digest().bytes());
+
+    let accept_header = if accept { format!("Sec-WebSocket-Accept: {}\r\n", accept_key) } else { String::new() };
+    let response = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n{}\r\n", accept_header);
+    conn.write_all(response.as_bytes()).unwrap();
+
+    conn
+}
+
+// Read a frame sent by the client (payload < 126 bytes), returns the opcode and the payload unmasked
+fn mock_read_frame(conn: &mut TcpStream) -> (u8, Vec<u8>) {
+    let mut header = [0u8; 6];
+    conn.read_exact(&mut header).unwrap();
+
+    let mut payload = vec![0u8; (header[1] & 0x7F) as usize];
+    conn.read_exact(&mut payload).unwrap();
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= header[2 + i % 4];
+    }
+
+    (header[0] & 0x0F, payload)
+}
+
+#[tokio::test]
+async fn async_send_recv_and_server_close() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener, true);
+        assert_eq!(mock_read_frame(&mut conn), (0x1, b"Hello".to_vec()));
+
+        // Ping, echo the message and close the connection
+        conn.write_all(&[0x89, 0x02, b'h', b'i']).unwrap();
+        conn.write_all(&[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]).unwrap();
+        conn.write_all(&[0x88, 0x05, 0x03, 0xE8, b'b', b'y', b'e']).unwrap();
+
+        assert_eq!(mock_read_frame(&mut conn), (0xA, b"hi".to_vec()));
+        assert_eq!(mock_read_frame(&mut conn), (0x8, vec![0x03, 0xE8]));
+    });
+
+    let mut client = AsyncWSClient::connect("localhost", port, "/", None).await.unwrap();
+    client.send("Hello").await.unwrap();
+
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::TEXT(String::from("Hello")));
+    assert!(client.next().await.is_none());
+    assert_eq!(client.close_reason(), Some(&Reason::SERVER_CLOSE(CloseCode::NormalClosure, String::from("bye"))));
+    assert!(client.send("Hello").await.err().unwrap() == WebSocketError::ConnectionClose);
+    server.join().unwrap();
+}
+
+#[tokio::test]
+async fn async_sink_fragments_messages_and_client_close() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener, true);
+        assert_eq!(mock_read_frame(&mut conn), (0x1, b"abc".to_vec()));
+        assert_eq!(mock_read_frame(&mut conn), (0x0, b"de".to_vec()));
+        assert_eq!(mock_read_frame(&mut conn), (0x8, vec![0x03, 0xE8]));
+
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+    });
+
+    let mut client = AsyncWSClient::connect("localhost", port, "/", None).await.unwrap();
+    client.set_message_size(3);
+    SinkExt::send(&mut client, String::from("abcde")).await.unwrap();
+    client.close().await.unwrap();
+
+    assert_eq!(client.close_reason(), Some(&Reason::CLIENT_CLOSE(CloseCode::NormalClosure, String::new())));
+    server.join().unwrap();
+}
+
+#[tokio::test]
+async fn async_connection_lost_reports_1006() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let conn = mock_accept_connection(listener, true);
+        drop(conn);
+    });
+
+    let mut client = AsyncWSClient::connect("localhost", port, "/", None).await.unwrap();
+    assert!(client.recv().await.unwrap().err().unwrap() == WebSocketError::ConnectionClose);
+    assert!(client.recv().await.is_none());
+    assert_eq!(client.close_reason(), Some(&Reason::SERVER_CLOSE(CloseCode::AbnormalClosure, String::new())));
+    server.join().unwrap();
+}
+
+#[tokio::test]
+async fn async_handshake_error() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let conn = mock_accept_connection(listener, false);
+        thread::sleep(Duration::from_millis(200));
+        drop(conn);
+    });
+
+    let error = AsyncWSClient::connect("localhost", port, "/", None).await.err().unwrap();
+    assert!(error == WebSocketError::HandShake(HandShakeError::MissingAcceptKey));
+    server.join().unwrap();
+}
+
+#[tokio::test]
+async fn async_handshake_timeout() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let (conn, _) = listener.accept().unwrap();
+        thread::sleep(Duration::from_millis(500));
+        drop(conn);
+    });
+
+    let error = AsyncWSClient::connect_timeout("localhost", port, "/", None, Duration::from_millis(100)).await.err().unwrap();
+    assert!(error == WebSocketError::Timeout);
+    server.join().unwrap();
+}
+
+#[tokio::test]
+async fn async_binary_message_not_utf8_is_delivered() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener, true);
+        // Binary message in two frames, the bytes are not UTF-8
+        conn.write_all(&[0x02, 0x02, 0xFF, 0xFE]).unwrap();
+        conn.write_all(&[0x80, 0x01, 0x00]).unwrap();
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        assert_eq!(mock_read_frame(&mut conn), (0x8, vec![0x03, 0xE8]));
+    });
+
+    let mut client = AsyncWSClient::connect("localhost", port, "/", None).await.unwrap();
+    assert_eq!(client.recv().await.unwrap().unwrap(), Message::BINARY(vec![0xFF, 0xFE, 0x00]));
+    assert!(client.recv().await.is_none());
+    server.join().unwrap();
+}
+
+#[tokio::test]
+async fn async_seeded_random_makes_the_key_and_the_frames_predictable() {
+    let (listener, port) = setup();
+
+    // The client takes the key from the seed first and then the mask of the frame
+    let mut random = SeededRandom::new(7);
+    let mut key = [0u8; 16];
+    random.fill(&mut key).unwrap();
+    let key = base64::encode(&key);
+    let mut expected = Vec::new();
+    FrameEncoder::new(random).encode(FLAG::FIN, OPCODE::TEXT, b"hello", &mut expected).unwrap();
+
+    let server = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8; 1];
+            conn.read_exact(&mut byte).unwrap();
+            request.push(byte[0]);
+        }
+        assert!(String::from_utf8(request).unwrap().contains(&format!("\r\nSec-WebSocket-Key: {}\r\n", key)));
+
+        let mut hasher = Sha1::new();
+        hasher.update(format!("{}{}", key, GUID).as_bytes());
+        let response = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", base64::encode(&hasher.digest().bytes()));
+        conn.write_all(response.as_bytes()).unwrap();
+
+        let mut frame = vec![0u8; expected.len()];
+        conn.read_exact(&mut frame).unwrap();
+        assert_eq!(frame, expected);
+    });
+
+    let config = AsyncConfig { random: Some(shared_random(SeededRandom::new(7))), ..AsyncConfig::default() };
+    let mut client = AsyncWSClient::connect_with("localhost", port, "/", config).await.unwrap();
+    client.send("hello").await.unwrap();
+    server.join().unwrap();
+}
+
+#[tokio::test]
+async fn async_binary_round_trip_and_ping() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener, true);
+        let (opcode, payload) = mock_read_frame(&mut conn);
+        assert_eq!((opcode, payload.clone()), (0x2, vec![0x00, 0xFF, 0x80]));
+
+        // Echo the binary message without mask
+        let mut frame = vec![0x82, payload.len() as u8];
+        frame.extend_from_slice(&payload);
+        conn.write_all(&frame).unwrap();
+
+        assert_eq!(mock_read_frame(&mut conn), (0x2, vec![0x01]));
+        assert_eq!(mock_read_frame(&mut conn), (0x9, b"ping".to_vec()));
+        assert_eq!(mock_read_frame(&mut conn), (0x8, vec![0x03, 0xE8]));
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+    });
+
+    let mut client = AsyncWSClient::connect("localhost", port, "/", None).await.unwrap();
+    SinkExt::send(&mut client, Message::BINARY(vec![0x00, 0xFF, 0x80])).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::BINARY(vec![0x00, 0xFF, 0x80]));
+
+    client.send_binary(&[0x01]).await.unwrap();
+    assert!(client.ping(&[0u8; 126]).await.err().unwrap() == WebSocketError::Protocol(ProtocolError::InvalidFrame));
+    client.ping(b"ping").await.unwrap();
+    client.close().await.unwrap();
+    server.join().unwrap();
+}
+
+#[tokio::test]
+async fn async_close_without_response_times_out() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener, true);
+        // The close is never answered
+        assert_eq!(mock_read_frame(&mut conn), (0x8, vec![0x03, 0xE8]));
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = AsyncWSClient::connect("localhost", port, "/", None).await.unwrap();
+    client.set_timeout(Duration::from_millis(100));
+    assert!(client.close().await.err().unwrap() == WebSocketError::Timeout);
+    assert_eq!(client.close_reason(), Some(&Reason::CLIENT_CLOSE(CloseCode::AbnormalClosure, String::new())));
+    server.join().unwrap();
+}
+
+#[tokio::test]
+async fn async_invalid_fragment_sequences_close_connection() {
+    // New text frame while a fragmented message is open, and a continuation frame without a message
+    let cases: [&[u8]; 2] = [&[0x01, 0x01, b'a', 0x81, 0x01, b'b'], &[0x80, 0x01, b'a']];
+
+    for frames in cases {
+        let (listener, port) = setup();
+
+        let server = thread::spawn(move || {
+            let mut conn = mock_accept_connection(listener, true);
+            conn.write_all(frames).unwrap();
+            assert_eq!(mock_read_frame(&mut conn), (0x8, vec![0x03, 0xEA]));
+        });
+
+        let mut client = AsyncWSClient::connect("localhost", port, "/", None).await.unwrap();
+        assert!(client.recv().await.unwrap().err().unwrap() == WebSocketError::Protocol(ProtocolError::InvalidFrame));
+        assert!(client.recv().await.is_none());
+        assert_eq!(client.close_reason(), Some(&Reason::CLIENT_CLOSE(CloseCode::ProtocolError, String::new())));
+        server.join().unwrap();
+    }
+}
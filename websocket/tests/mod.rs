@@ -1,2 +1,6 @@
 #[cfg(test)]
-mod sync_client;
\ No newline at end of file
+mod sync_client;
+#[cfg(test)]
+mod async_client;
+#[cfg(test)]
+mod result;
\ No newline at end of file
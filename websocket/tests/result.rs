@@ -0,0 +1,40 @@
+use std::error::Error;
+use std::io;
+use websocket_std::CloseCode;
+use websocket_std::result::{WebSocketError, HandShakeError, ProtocolError, CapacityError};
+
+#[test]
+fn io_error_keeps_kind_and_message() {
+    let error: WebSocketError = io::Error::new(io::ErrorKind::ConnectionReset, "reset by peer").into();
+
+    let source = error.source().unwrap().downcast_ref::<io::Error>().unwrap();
+    assert_eq!(source.kind(), io::ErrorKind::ConnectionReset);
+    assert_eq!(source.to_string(), "reset by peer");
+    assert!(error.to_string().contains("reset by peer"));
+}
+
+#[test]
+fn protocol_and_capacity_errors_have_close_code() {
+    assert_eq!(WebSocketError::from(ProtocolError::InvalidFrame).close_code(), Some(CloseCode::ProtocolError));
+    assert_eq!(WebSocketError::from(ProtocolError::InvalidUTF8).close_code(), Some(CloseCode::InvalidPayload));
+    assert_eq!(WebSocketError::from(CapacityError::MessageTooBig { max: 10 }).close_code(), Some(CloseCode::MessageTooBig));
+    assert_eq!(WebSocketError::from(CapacityError::SendBufferTooSmall { max: 10 }).close_code(), Some(CloseCode::MessageTooBig));
+    assert_eq!(WebSocketError::Timeout.close_code(), None);
+    assert!(WebSocketError::from(ProtocolError::ReservedBits).source().is_none());
+}
+
+#[test]
+fn retryable_errors() {
+    assert!(WebSocketError::Timeout.is_retryable());
+    assert!(WebSocketError::ConnectionClose.is_retryable());
+    assert!(WebSocketError::UnreachableHost(io::Error::from(io::ErrorKind::ConnectionRefused)).is_retryable());
+    assert!(WebSocketError::from(io::Error::from(io::ErrorKind::BrokenPipe)).is_retryable());
+    assert!(WebSocketError::HandShake(HandShakeError::StatusCode(503)).is_retryable());
+
+    assert!(!WebSocketError::from(io::Error::from(io::ErrorKind::PermissionDenied)).is_retryable());
+    assert!(!WebSocketError::HandShake(HandShakeError::StatusCode(404)).is_retryable());
+    assert!(!WebSocketError::HandShake(HandShakeError::InvalidAcceptKey).is_retryable());
+    assert!(!WebSocketError::from(ProtocolError::InvalidFrame).is_retryable());
+    assert!(!WebSocketError::from(CapacityError::FrameTooBig { max: 10 }).is_retryable());
+    assert!(!WebSocketError::QueueFull.is_retryable());
+}
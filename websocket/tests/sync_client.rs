@@ -1,8 +1,15 @@
 use std::net::{TcpListener, TcpStream};
-use websocket_std::sync::client::{Config, Reason, WSEvent, WSClient};
-use websocket_std::result::WebSocketError;
+use websocket_std::sync::client::{Config, Reason, WSEvent, WSClient, MessageKind, Message, ConnectionState};
+use websocket_std::sync::blocking::WSBlockingClient;
+use websocket_std::sync::stats::Stats;
+use websocket_std::sync::split::{WSReader, WSWriter};
+use websocket_std::result::{WebSocketError, WebSocketResult, HandShakeError, ProtocolError, CapacityError};
+use websocket_std::CloseCode;
+use websocket_std::testing::{CountingAllocator, Allocations, count_allocations};
+use websocket_std::extension::{Extension, ExtensionFrame, WebSocketExtension, RSV2};
+use websocket_std::codec::{CodecError, FrameEncoder, RandomSource, SeededRandom, OPCODE, FLAG, shared_random};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use std::io::{Write, Read};
 use std::net::Shutdown;
 use core::array::TryFromSliceError;
@@ -57,26 +64,26 @@ fn mock_accept_connection_no_websocket_key(listener: TcpListener) -> TcpStream {
 }
 
 fn mock_accept_connection(listener: TcpListener) -> TcpStream {
+    return mock_accept_connection_with_headers(listener, "");
+}
+
+// Accept the connection adding the given headers to the response, each header must end with \r\n
+fn mock_accept_connection_with_headers(listener: TcpListener, headers: &str) -> TcpStream {
     let (mut conn, _) = listener.accept().unwrap();
     
     let request = read_all_sync(&mut conn);
     print!("{}\n", String::from_utf8(request.clone()).unwrap());
 
     let request = String::from_utf8(request).unwrap();
-    let request_lower = request.replace(" ", "").to_lowercase();
-    let mut i = request_lower.find("sec-websocket-key").unwrap();
-    let mut key = request.get(i..request.len()).unwrap();
-    i = key.find(":").unwrap();
-    key = key.get(i+1..key.len()).unwrap();
-    i = key.find("\r\n").unwrap();
-    key = key.get(0..i).unwrap();
-    key = key.trim_start();
+    let key_line = request.split("\r\n").find(|line| line.to_lowercase().starts_with("sec-websocket-key")).unwrap();
+    let i = key_line.find(":").unwrap();
+    let key = key_line.get(i+1..key_line.len()).unwrap().trim();
 
     print!("key: {}\n", key);
 
     let accept_key = sec_websocket_accept(key);
 
-    let http_resonse = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n Connection: Upgrade\r\n sec-websocket-accept: {}\r\n\r\n", accept_key);
+    let http_resonse = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n Connection: Upgrade\r\n sec-websocket-accept: {}\r\n{}\r\n", accept_key, headers);
 
     conn.write_all(http_resonse.as_bytes()).unwrap();
 
@@ -119,6 +126,27 @@ fn mock_unmask_data(data: &Vec<u8>) -> Vec<u8> {
     data
 }
 
+// Read one frame sent by the client (payload < 126 bytes), the frames can arrive together.
+// Returns the first byte of the header and the payload unmasked
+fn mock_read_frame(conn: &mut TcpStream) -> (u8, Vec<u8>) {
+    let mut header = [0u8; 2];
+    conn.read_exact(&mut header).unwrap();
+
+    let len = match header[1] & 0x7F {
+        126 => { let mut len = [0u8; 2]; conn.read_exact(&mut len).unwrap(); u16::from_be_bytes(len) as usize },
+        127 => { let mut len = [0u8; 8]; conn.read_exact(&mut len).unwrap(); u64::from_be_bytes(len) as usize },
+        len => len as usize
+    };
+
+    let mut mask = [0u8; 4];
+    conn.read_exact(&mut mask).unwrap();
+    let mut payload = vec![0u8; len];
+    conn.read_exact(&mut payload).unwrap();
+    for (i, byte) in payload.iter_mut().enumerate() { *byte ^= mask[i % 4] }
+
+    (header[0], payload)
+}
+
 pub fn bytes_to_u16(bytes: &[u8]) -> Result<u16, TryFromSliceError> {
     let res: Result<[u8; 2], _> = bytes.try_into();
     if res.is_err() { return Err(res.err().unwrap()); }
@@ -181,14 +209,18 @@ fn connection_success_handshake_error_no_sec_websocket_received() {
         match event {
             WSEvent::ON_CONNECT(_) => {},
             WSEvent::ON_TEXT(_) => {},
-            WSEvent::ON_CLOSE(_) => {} 
+            WSEvent::ON_BINARY(_) => {},
+            WSEvent::ON_CLOSE(_) => {},
+            WSEvent::ON_DRAINED => {}
+            WSEvent::ON_FRAGMENT { .. } => {}
         }
     } 
 
     let config  = Config { 
         callback: Some(websocket_handler),
         data: Some(data.clone()),
-        protocols: None 
+        protocols: None,
+        random: None
     };
 
     let config: Option<Config<WSData>> = Some(config);
@@ -200,7 +232,7 @@ fn connection_success_handshake_error_no_sec_websocket_received() {
         match client.event_loop() {
             Ok(_) => {},
             Err(e) => {
-                assert!(e == WebSocketError::HandShake);
+                assert!(e == WebSocketError::HandShake(HandShakeError::MissingAcceptKey));
                 break;
             }
         }
@@ -244,8 +276,8 @@ fn connection_success_handshake_error_no_sec_websocket_received() {
 
 //     fn on_close(reason: &Reason, _data: Option<WSData>) {
 //         match reason {
-//             Reason::CLIENT_CLOSE(_) => assert!(true),
-//             Reason::SERVER_CLOSE(_) => assert!(false)
+//             Reason::CLIENT_CLOSE(_, _) => assert!(true),
+//             Reason::SERVER_CLOSE(_, _) => assert!(false)
 //         }
 //     }
 
@@ -358,7 +390,10 @@ fn send_data_success_on_one_frame() {
         match event {
             WSEvent::ON_CONNECT(_) => {},
             WSEvent::ON_TEXT(msg) => on_message(ws, msg, data),
-            WSEvent::ON_CLOSE(reason) => on_close(reason, data)
+            WSEvent::ON_BINARY(_) => {},
+            WSEvent::ON_CLOSE(reason) => on_close(reason, data),
+            WSEvent::ON_DRAINED => {}
+            WSEvent::ON_FRAGMENT { .. } => {}
         }
     } 
 
@@ -371,22 +406,23 @@ fn send_data_success_on_one_frame() {
 
     fn on_close(reason: &Reason, _data: Option<WSData>) {
         match reason {
-            Reason::CLIENT_CLOSE(_) => assert!(true),
-            Reason::SERVER_CLOSE(_) => assert!(false)
+            Reason::CLIENT_CLOSE(_, _) => assert!(true),
+            Reason::SERVER_CLOSE(_, _) => assert!(false)
         }
     }
 
     let config  = Config { 
         callback: Some(websocket_handler),
         data: Some(data.clone()),
-        protocols: None 
+        protocols: None,
+        random: None
     };
 
     let config: Option<Config<WSData>> = Some(config);
     let mut client = WSClient::new();
     client.set_timeout(Duration::from_secs(10));
     client.init("localhost", port, "/", config);
-    client.send("Hello");
+    client.send("Hello").unwrap();
 
     while *data.borrow() < 1 {
         client.event_loop().unwrap();
@@ -430,7 +466,10 @@ fn send_data_success_more_than_one_frame() {
         match event {
             WSEvent::ON_CONNECT(_) => {},
             WSEvent::ON_TEXT(msg) => on_message(ws, msg, data),
-            WSEvent::ON_CLOSE(reason) => on_close(reason, data)
+            WSEvent::ON_BINARY(_) => {},
+            WSEvent::ON_CLOSE(reason) => on_close(reason, data),
+            WSEvent::ON_DRAINED => {}
+            WSEvent::ON_FRAGMENT { .. } => {}
         }
     } 
 
@@ -442,15 +481,16 @@ fn send_data_success_more_than_one_frame() {
 
     fn on_close(reason: &Reason, _data: Option<WSData>) {
         match reason {
-            Reason::CLIENT_CLOSE(_) => assert!(true),
-            Reason::SERVER_CLOSE(_) => assert!(false)
+            Reason::CLIENT_CLOSE(_, _) => assert!(true),
+            Reason::SERVER_CLOSE(_, _) => assert!(false)
         }
     }
 
     let config  = Config { 
         callback: Some(websocket_handler),
         data: Some(data.clone()),
-        protocols: None 
+        protocols: None,
+        random: None
     };
 
     let config: Option<Config<WSData>> = Some(config);
@@ -458,7 +498,7 @@ fn send_data_success_more_than_one_frame() {
     client.set_timeout(Duration::from_secs(1));
     client.set_message_size(3);
     client.init("localhost", port, "/", config);
-    client.send("Hello");
+    client.send("Hello").unwrap();
 
     while *data.borrow() < 1 {
         client.event_loop().unwrap();
@@ -504,7 +544,10 @@ fn connect_send_and_client_close_successfully() {
         match event {
             WSEvent::ON_CONNECT(_) => {},
             WSEvent::ON_TEXT(msg) => on_message(ws, msg, data),
-            WSEvent::ON_CLOSE(reason) => on_close(reason, data)
+            WSEvent::ON_BINARY(_) => {},
+            WSEvent::ON_CLOSE(reason) => on_close(reason, data),
+            WSEvent::ON_DRAINED => {}
+            WSEvent::ON_FRAGMENT { .. } => {}
         }
     } 
 
@@ -516,22 +559,23 @@ fn connect_send_and_client_close_successfully() {
 
     fn on_close(reason: &Reason, _data: Option<WSData>) {
         match reason {
-            Reason::CLIENT_CLOSE(_) => assert!(true),
-            Reason::SERVER_CLOSE(_) => assert!(false)
+            Reason::CLIENT_CLOSE(_, _) => assert!(true),
+            Reason::SERVER_CLOSE(_, _) => assert!(false)
         }
     }
 
     let config  = Config { 
         callback: Some(websocket_handler),
         data: Some(data.clone()),
-        protocols: None 
+        protocols: None,
+        random: None
     };
 
     let config: Option<Config<WSData>> = Some(config);
     let mut client = WSClient::new();
     client.set_timeout(Duration::from_secs(1));
     client.init("localhost", port, "/", config);
-    client.send("Hello");
+    client.send("Hello").unwrap();
 
     while *data.borrow() < 1 {
         client.event_loop().unwrap();
@@ -599,4 +643,1809 @@ fn connect_send_and_client_close_successfully() {
 
 // // Test Control frames can be interjected in the middle of a fragmented message.
 
-// // Test accept protocol
\ No newline at end of file
+// // Test accept protocol
+#[test]
+fn handshake_without_response_times_out() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let (conn, _) = listener.accept().unwrap();
+        thread::sleep(Duration::from_millis(1000));
+        drop(conn);
+    });
+
+    let mut client = WSClient::<u32>::new();
+    client.set_timeout(Duration::from_millis(200));
+    client.init("localhost", port, "/", None);
+
+    let error = loop {
+        if let Err(e) = client.event_loop() { break e }
+    };
+
+    assert!(error == WebSocketError::Timeout);
+    assert!(error.is_retryable());
+    assert!(client.event_loop().err().unwrap() == WebSocketError::ConnectionClose);
+    server.join().unwrap();
+}
+
+// -------------------- Close handshake -------------------- //
+
+type CloseData = Rc<RefCell<Vec<Reason>>>;
+
+fn close_handler(_ws: &mut WSClient<CloseData>, event: &WSEvent, data: Option<CloseData>) {
+    if let WSEvent::ON_CLOSE(reason) = event {
+        data.unwrap().borrow_mut().push(reason.clone());
+    }
+}
+
+// Connect to the mock server and run the event loop until the first error, returns the error and the close events received
+fn run_until_error(port: u16) -> (WebSocketError, Vec<Reason>) {
+    let data: CloseData = Rc::new(RefCell::new(Vec::new()));
+    let config = Config { callback: Some(close_handler), data: Some(data.clone()), protocols: None, random: None };
+
+    let mut client = WSClient::new();
+    client.set_timeout(Duration::from_secs(1));
+    client.init("localhost", port, "/", Some(config));
+
+    let error = loop {
+        if let Err(e) = client.event_loop() { break e }
+    };
+
+    // The close is reported only once
+    assert!(client.event_loop().err().unwrap() == WebSocketError::ConnectionClose);
+    drop(client);
+
+    let reasons = data.borrow().clone();
+    (error, reasons)
+}
+
+#[test]
+fn server_close_with_reason_is_reported() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(500));
+
+        conn.write_all(&[0x88, 0x05, 0x03, 0xE8, b'b', b'y', b'e']).unwrap();
+
+        let close_frame = mock_wait_for_frame_sync(&mut conn);
+        let (status, _) = mock_unmask_control_frame(&close_frame);
+        assert_eq!(status, 1000);
+    });
+
+    let (error, reasons) = run_until_error(port);
+
+    assert!(error == WebSocketError::ConnectionClose);
+    assert_eq!(reasons, vec![Reason::SERVER_CLOSE(CloseCode::NormalClosure, String::from("bye"))]);
+    server.join().unwrap();
+}
+
+#[test]
+fn server_close_without_status_code_reports_1005() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(500));
+
+        conn.write_all(&[0x88, 0x00]).unwrap();
+
+        // The response doesn't have a status code either (only the header and the mask)
+        let close_frame = mock_wait_for_frame_sync(&mut conn);
+        assert_eq!(close_frame[0], 0x88);
+        assert_eq!(close_frame.len(), 6);
+    });
+
+    let (error, reasons) = run_until_error(port);
+
+    assert!(error == WebSocketError::ConnectionClose);
+    assert_eq!(reasons, vec![Reason::SERVER_CLOSE(CloseCode::NoStatusReceived, String::new())]);
+    server.join().unwrap();
+}
+
+#[test]
+fn connection_lost_reports_1006() {
+    let (listener, port) = setup();
+
+    thread::spawn(move || {
+        let conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(500));
+        conn.shutdown(Shutdown::Both).unwrap();
+    });
+
+    let (error, reasons) = run_until_error(port);
+
+    assert!(error == WebSocketError::ConnectionClose);
+    assert_eq!(reasons, vec![Reason::SERVER_CLOSE(CloseCode::AbnormalClosure, String::new())]);
+}
+
+#[test]
+fn reserved_close_code_received_fails_connection() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(500));
+
+        // 1005 can't be sent in a close frame
+        conn.write_all(&[0x88, 0x02, 0x03, 0xED]).unwrap();
+
+        let close_frame = mock_wait_for_frame_sync(&mut conn);
+        let (status, _) = mock_unmask_control_frame(&close_frame);
+        assert_eq!(status, 1002);
+    });
+
+    let (error, reasons) = run_until_error(port);
+
+    assert!(error == WebSocketError::Protocol(ProtocolError::InvalidCloseFrame));
+    assert_eq!(reasons, vec![Reason::CLIENT_CLOSE(CloseCode::ProtocolError, String::new())]);
+    server.join().unwrap();
+}
+
+#[test]
+fn close_reason_not_utf8_fails_connection() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(500));
+
+        conn.write_all(&[0x88, 0x04, 0x03, 0xE8, 0xFF, 0xFE]).unwrap();
+
+        let close_frame = mock_wait_for_frame_sync(&mut conn);
+        let (status, _) = mock_unmask_control_frame(&close_frame);
+        assert_eq!(status, 1007);
+    });
+
+    let (error, reasons) = run_until_error(port);
+
+    assert!(error == WebSocketError::Protocol(ProtocolError::InvalidUTF8));
+    assert_eq!(reasons, vec![Reason::CLIENT_CLOSE(CloseCode::InvalidPayload, String::new())]);
+    server.join().unwrap();
+}
+
+// -------------------- Size limits -------------------- //
+
+#[test]
+fn frame_bigger_than_max_frame_size_closes_connection() {
+    type WSData = Rc<RefCell<Option<u16>>>;
+    type WebSocket<'a> = WSClient<'a, WSData>;
+    let data: WSData = Rc::new(RefCell::new(None));
+
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(500)); // Don't send the frame with the handshake response
+
+        // Binary frame announcing a payload of 2ˆ32 bytes, only the header and a few bytes are sent
+        let header: Vec<u8> = [0x82, 0x7F, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02].to_vec();
+        conn.write_all(header.as_slice()).unwrap();
+
+        let close_frame = mock_wait_for_frame_sync(&mut conn);
+        let (status, _) = mock_unmask_control_frame(&close_frame);
+        assert_eq!(status, 1009);
+    });
+
+    fn websocket_handler(_ws: &mut WebSocket, event: &WSEvent, data: Option<WSData>) {
+        match event {
+            WSEvent::ON_CONNECT(_) => {},
+            WSEvent::ON_TEXT(_) => assert!(false),
+            WSEvent::ON_BINARY(_) => assert!(false),
+            WSEvent::ON_CLOSE(reason) => {
+                match reason {
+                    Reason::CLIENT_CLOSE(status, _) => *data.unwrap().borrow_mut() = Some(u16::from(*status)),
+                    Reason::SERVER_CLOSE(_, _) => assert!(false)
+                }
+            },
+            WSEvent::ON_DRAINED => {}
+            WSEvent::ON_FRAGMENT { .. } => {}
+        }
+    }
+
+    let config  = Config { 
+        callback: Some(websocket_handler),
+        data: Some(data.clone()),
+        protocols: None,
+        random: None
+    };
+
+    let mut client = WSClient::new();
+    client.set_timeout(Duration::from_secs(1));
+    client.set_max_frame_size(1024);
+    client.init("localhost", port, "/", Some(config));
+
+    let error = loop {
+        if let Err(e) = client.event_loop() { break e }
+    };
+
+    assert!(error == WebSocketError::Capacity(CapacityError::FrameTooBig { max: 1024 }));
+    assert_eq!(*data.borrow(), Some(1009));
+    assert!(client.event_loop().err().unwrap() == WebSocketError::ConnectionClose);
+    server.join().unwrap();
+}
+
+#[test]
+fn message_bigger_than_max_message_size_closes_connection() {
+    type WSData = Rc<RefCell<Option<u16>>>;
+    type WebSocket<'a> = WSClient<'a, WSData>;
+    let data: WSData = Rc::new(RefCell::new(None));
+
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(500));
+
+        // Two frames of 5 bytes of the same message ("Hello" twice) 
+        let first_frame: Vec<u8> = [0x01, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f].to_vec();
+        let last_frame: Vec<u8> = [0x80, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f].to_vec();
+        conn.write_all(first_frame.as_slice()).unwrap();
+        conn.write_all(last_frame.as_slice()).unwrap();
+
+        let close_frame = mock_wait_for_frame_sync(&mut conn);
+        let (status, _) = mock_unmask_control_frame(&close_frame);
+        assert_eq!(status, 1009);
+    });
+
+    fn websocket_handler(_ws: &mut WebSocket, event: &WSEvent, data: Option<WSData>) {
+        match event {
+            WSEvent::ON_CONNECT(_) => {},
+            WSEvent::ON_TEXT(_) => assert!(false),
+            WSEvent::ON_BINARY(_) => assert!(false),
+            WSEvent::ON_CLOSE(reason) => {
+                match reason {
+                    Reason::CLIENT_CLOSE(status, _) => *data.unwrap().borrow_mut() = Some(u16::from(*status)),
+                    Reason::SERVER_CLOSE(_, _) => assert!(false)
+                }
+            },
+            WSEvent::ON_DRAINED => {}
+            WSEvent::ON_FRAGMENT { .. } => {}
+        }
+    }
+
+    let config  = Config { 
+        callback: Some(websocket_handler),
+        data: Some(data.clone()),
+        protocols: None,
+        random: None
+    };
+
+    let mut client = WSClient::new();
+    client.set_timeout(Duration::from_secs(1));
+    client.set_max_message_size(8);
+    client.init("localhost", port, "/", Some(config));
+
+    let error = loop {
+        if let Err(e) = client.event_loop() { break e }
+    };
+
+    assert!(error == WebSocketError::Capacity(CapacityError::MessageTooBig { max: 8 }));
+    assert_eq!(*data.borrow(), Some(1009));
+    server.join().unwrap();
+}
+
+// -------------------- Extensions -------------------- //
+
+#[test]
+fn extension_not_offered_handshake_error() {
+    let (listener, port) = setup();
+
+    thread::spawn(move || {
+        let conn = mock_accept_connection_with_headers(listener, "Sec-WebSocket-Extensions: x-not-offered\r\n");
+        thread::sleep(Duration::from_millis(500));
+        conn.shutdown(Shutdown::Both).unwrap();
+    });
+
+    let mut client = WSClient::<u32>::new();
+    client.set_timeout(Duration::from_secs(1));
+    client.init("localhost", port, "/", None);
+
+    let error = loop {
+        if let Err(e) = client.event_loop() { break e }
+    };
+
+    assert!(error == WebSocketError::HandShake(HandShakeError::ExtensionNotOffered(String::from("x-not-offered"))));
+}
+
+#[test]
+fn protocol_not_offered_handshake_error() {
+    let (listener, port) = setup();
+
+    thread::spawn(move || {
+        let conn = mock_accept_connection_with_headers(listener, "Sec-WebSocket-Protocol: chat\r\n");
+        thread::sleep(Duration::from_millis(500));
+        conn.shutdown(Shutdown::Both).unwrap();
+    });
+
+    let protocols = ["superchat"];
+    let mut client = WSClient::<u32>::new();
+    client.set_timeout(Duration::from_secs(1));
+    client.init("localhost", port, "/", Some(Config { callback: None, data: None, protocols: Some(&protocols), random: None }));
+
+    let error = loop {
+        if let Err(e) = client.event_loop() { break e }
+    };
+
+    assert!(error == WebSocketError::HandShake(HandShakeError::ProtocolNotOffered(String::from("chat"))));
+}
+
+#[test]
+fn protocol_required_handshake_error() {
+    let (listener, port) = setup();
+
+    thread::spawn(move || {
+        let conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(500));
+        conn.shutdown(Shutdown::Both).unwrap();
+    });
+
+    let protocols = ["chat"];
+    let mut client = WSClient::<u32>::new();
+    client.set_timeout(Duration::from_secs(1));
+    client.set_require_protocol(true);
+    client.init("localhost", port, "/", Some(Config { callback: None, data: None, protocols: Some(&protocols), random: None }));
+
+    let error = loop {
+        if let Err(e) = client.event_loop() { break e }
+    };
+
+    assert!(error == WebSocketError::HandShake(HandShakeError::ProtocolRequired));
+}
+
+#[test]
+fn protocol_offered_is_accepted() {
+    let (listener, port) = setup();
+
+    thread::spawn(move || {
+        let conn = mock_accept_connection_with_headers(listener, "Sec-WebSocket-Protocol: chat\r\n");
+        thread::sleep(Duration::from_millis(500));
+        conn.shutdown(Shutdown::Both).unwrap();
+    });
+
+    let protocols = ["superchat", "chat"];
+    let mut client = WSClient::<u32>::new();
+    client.set_timeout(Duration::from_secs(1));
+    client.set_require_protocol(true);
+    client.init("localhost", port, "/", Some(Config { callback: None, data: None, protocols: Some(&protocols), random: None }));
+
+    while client.protocol().is_none() {
+        client.event_loop().unwrap();
+    }
+
+    assert_eq!(client.protocol().unwrap(), "chat");
+}
+
+#[test]
+#[cfg(feature = "permessage-deflate")]
+fn permessage_deflate_send_and_receive_compressed_messages() {
+    use websocket_std::extension::deflate::{DeflateConfig, PerMessageDeflate};
+    use flate2::{Decompress, FlushDecompress};
+
+    type WSData = Rc<RefCell<Vec<String>>>;
+    type WebSocket<'a> = WSClient<'a, WSData>;
+    let data: WSData = Rc::new(RefCell::new(Vec::new()));
+
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection_with_headers(listener, "Sec-WebSocket-Extensions: permessage-deflate\r\n");
+
+        let frame = mock_wait_for_frame_sync(&mut conn);
+        assert_eq!(frame[0], 0xC1); // FIN + RSV1 + TEXT
+
+        let mut compressed = mock_unmask_data(&frame);
+        compressed.extend_from_slice(&[0x00, 0x00, 0xFF, 0xFF]);
+        let mut message = Vec::with_capacity(1024);
+        Decompress::new(false).decompress_vec(&compressed, &mut message, FlushDecompress::Sync).unwrap();
+        assert_eq!(String::from_utf8(message).unwrap(), "Hello");
+
+        // RFC 7692 7.2.3.1: "Hello" compressed
+        let echo_frame: Vec<u8> = [0xC1, 0x07, 0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00].to_vec();
+        conn.write_all(echo_frame.as_slice()).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    fn websocket_handler(ws: &mut WebSocket, event: &WSEvent, data: Option<WSData>) {
+        match event {
+            // Messages are compressed once the extension is negotiated
+            WSEvent::ON_CONNECT(_) => { ws.send("Hello").unwrap(); },
+            WSEvent::ON_TEXT(msg) => data.unwrap().borrow_mut().push(msg.clone()),
+            WSEvent::ON_BINARY(_) => {},
+            WSEvent::ON_CLOSE(_) => {},
+            WSEvent::ON_DRAINED => {}
+            WSEvent::ON_FRAGMENT { .. } => {}
+        }
+    }
+
+    let config  = Config { 
+        callback: Some(websocket_handler),
+        data: Some(data.clone()),
+        protocols: None,
+        random: None
+    };
+
+    let mut client = WSClient::new();
+    client.set_timeout(Duration::from_secs(1));
+    client.add_extension(Box::new(PerMessageDeflate::new(DeflateConfig::default())));
+    client.init("localhost", port, "/", Some(config));
+
+    while data.borrow().is_empty() {
+        client.event_loop().unwrap();
+    }
+
+    assert_eq!(data.borrow()[0], "Hello");
+    assert_eq!(client.extensions().len(), 1);
+    server.join().unwrap();
+}
+
+// Extension that reverses the payload of the messages and marks them with RSV2
+struct ReverseExtension;
+
+impl WebSocketExtension for ReverseExtension {
+    fn name(&self) -> &str { "x-reverse" }
+
+    fn offer(&self) -> Extension { Extension::new("x-reverse".to_string(), vec![]) }
+
+    fn box_clone(&self) -> Box<dyn WebSocketExtension> { Box::new(ReverseExtension) }
+
+    fn accept(&mut self, response: &Extension) -> WebSocketResult<()> {
+        if !response.params().is_empty() { return Err(WebSocketError::HandShake(HandShakeError::ExtensionRejected(String::from("x-reverse")))) }
+        Ok(())
+    }
+
+    fn rsv_bits(&self) -> u8 { RSV2 }
+
+    fn encode(&mut self, frame: &mut ExtensionFrame) -> WebSocketResult<()> {
+        frame.payload.reverse();
+        frame.rsv |= RSV2;
+        Ok(())
+    }
+
+    fn decode(&mut self, frame: &mut ExtensionFrame, _max_size: u64) -> WebSocketResult<()> {
+        if frame.rsv & RSV2 == 0 { return Ok(()) }
+        frame.payload.reverse();
+        frame.rsv &= !RSV2;
+        Ok(())
+    }
+}
+
+#[test]
+fn custom_extension_transforms_frames() {
+    type WSData = Rc<RefCell<Vec<String>>>;
+    type WebSocket<'a> = WSClient<'a, WSData>;
+    let data: WSData = Rc::new(RefCell::new(Vec::new()));
+
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection_with_headers(listener, "Sec-WebSocket-Extensions: x-reverse\r\n");
+
+        let frame = mock_wait_for_frame_sync(&mut conn);
+        assert_eq!(frame[0], 0xA1); // FIN + RSV2 + TEXT
+        assert_eq!(String::from_utf8(mock_unmask_data(&frame)).unwrap(), "olleH");
+
+        let echo_frame: Vec<u8> = [0xA1, 0x05, 0x6f, 0x6c, 0x6c, 0x65, 0x48].to_vec();
+        conn.write_all(echo_frame.as_slice()).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    fn websocket_handler(ws: &mut WebSocket, event: &WSEvent, data: Option<WSData>) {
+        match event {
+            WSEvent::ON_CONNECT(_) => { ws.send("Hello").unwrap(); },
+            WSEvent::ON_TEXT(msg) => data.unwrap().borrow_mut().push(msg.clone()),
+            WSEvent::ON_BINARY(_) => {},
+            WSEvent::ON_CLOSE(_) => {},
+            WSEvent::ON_DRAINED => {}
+            WSEvent::ON_FRAGMENT { .. } => {}
+        }
+    }
+
+    let config  = Config { 
+        callback: Some(websocket_handler),
+        data: Some(data.clone()),
+        protocols: None,
+        random: None
+    };
+
+    let mut client = WSClient::new();
+    client.set_timeout(Duration::from_secs(1));
+    client.add_extension(Box::new(ReverseExtension));
+    client.init("localhost", port, "/", Some(config));
+
+    while data.borrow().is_empty() {
+        client.event_loop().unwrap();
+    }
+
+    assert_eq!(data.borrow()[0], "Hello");
+    assert_eq!(client.extensions()[0].name(), "x-reverse");
+    server.join().unwrap();
+}
+
+#[test]
+fn extensions_are_offered_again_after_reconnecting() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection_with_headers(listener.try_clone().unwrap(), "Sec-WebSocket-Extensions: x-reverse\r\n");
+        thread::sleep(Duration::from_millis(100));
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        assert_eq!(mock_read_frame(&mut conn).0, 0x88);
+
+        // The extension is offered in the new handshake, the server doesn't accept it this time
+        let (mut conn, _) = listener.accept().unwrap();
+        let request = String::from_utf8(read_all_sync(&mut conn)).unwrap();
+        assert!(request.contains("Sec-WebSocket-Extensions: x-reverse\r\n"));
+        let key_line = request.split("\r\n").find(|line| line.starts_with("Sec-WebSocket-Key")).unwrap();
+        let accept_key = sec_websocket_accept(key_line[18..].trim());
+        let response = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept_key);
+        conn.write_all(response.as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(300));
+    });
+
+    let mut client = WSClient::new();
+    client.add_extension(Box::new(ReverseExtension));
+    client.init("localhost", port, "/", count_config(&data));
+    while client.state() != ConnectionState::CLOSED {
+        let _ = client.event_loop_timeout(Duration::from_secs(1));
+    }
+    assert_eq!(client.extensions()[0].name(), "x-reverse");
+
+    data.borrow_mut().connected = false;
+    client.init("localhost", port, "/", count_config(&data));
+    assert!(client.extensions().is_empty());
+    wait_until_open(&mut client, &data);
+
+    assert!(client.extensions().is_empty());
+    server.join().unwrap();
+}
+
+#[test]
+fn reserved_bit_not_negotiated_closes_connection() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(500));
+
+        // RSV1 set without extensions
+        let frame: Vec<u8> = [0xC1, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f].to_vec();
+        conn.write_all(frame.as_slice()).unwrap();
+
+        let close_frame = mock_wait_for_frame_sync(&mut conn);
+        let (status, _) = mock_unmask_control_frame(&close_frame);
+        assert_eq!(status, 1002);
+    });
+
+    let mut client = WSClient::<u32>::new();
+    client.set_timeout(Duration::from_secs(1));
+    client.init("localhost", port, "/", None);
+
+    let error = loop {
+        if let Err(e) = client.event_loop() { break e }
+    };
+
+    assert!(error == WebSocketError::Protocol(ProtocolError::ReservedBits));
+    server.join().unwrap();
+}
+
+#[test]
+fn invalid_fragment_sequences_close_connection() {
+    // New text frame while a fragmented message is open, and a continuation frame without a message
+    let cases: [&[u8]; 2] = [&[0x01, 0x01, b'a', 0x81, 0x01, b'b'], &[0x80, 0x01, b'a']];
+
+    for frames in cases {
+        let (listener, port) = setup();
+
+        let server = thread::spawn(move || {
+            let mut conn = mock_accept_connection(listener);
+            thread::sleep(Duration::from_millis(100));
+            conn.write_all(frames).unwrap();
+
+            let close_frame = mock_wait_for_frame_sync(&mut conn);
+            let (status, _) = mock_unmask_control_frame(&close_frame);
+            assert_eq!(status, 1002);
+        });
+
+        let data: CountData = Rc::new(RefCell::new(Counters::default()));
+        let mut client = WSClient::new();
+        client.init("localhost", port, "/", count_config(&data));
+
+        let error = loop {
+            if let Err(e) = client.event_loop_timeout(Duration::from_secs(1)) { break e }
+        };
+
+        assert!(error == WebSocketError::Protocol(ProtocolError::InvalidFrame));
+        assert_eq!(data.borrow().messages, 0);
+        assert_eq!(client.close_reason(), Some(&Reason::CLIENT_CLOSE(CloseCode::ProtocolError, String::new())));
+        server.join().unwrap();
+    }
+}
+
+// -------------------- Waiting for events -------------------- //
+
+#[derive(Default)]
+struct Counters {
+    connected: bool,
+    messages: usize,
+    binaries: Vec<Vec<u8>>,
+    drained: usize,
+    fragments: Vec<(MessageKind, Vec<u8>, bool)>,
+    reports: Vec<Stats>
+}
+
+type CountData = Rc<RefCell<Counters>>;
+
+fn count_handler(_ws: &mut WSClient<CountData>, event: &WSEvent, data: Option<CountData>) {
+    match event {
+        WSEvent::ON_CONNECT(_) => data.unwrap().borrow_mut().connected = true,
+        WSEvent::ON_TEXT(_) => data.unwrap().borrow_mut().messages += 1,
+        WSEvent::ON_BINARY(binary) => data.unwrap().borrow_mut().binaries.push(binary.clone()),
+        WSEvent::ON_CLOSE(_) => {},
+        WSEvent::ON_DRAINED => data.unwrap().borrow_mut().drained += 1,
+        WSEvent::ON_FRAGMENT { kind, data: payload, is_final } => data.unwrap().borrow_mut().fragments.push((*kind, payload.clone(), *is_final))
+    }
+}
+
+fn count_config(data: &CountData) -> Option<Config<'static, CountData>> {
+    Some(Config { callback: Some(count_handler), data: Some(data.clone()), protocols: None, random: None })
+}
+
+// Run the event loop until the handshake is done
+fn wait_until_open(client: &mut WSClient<CountData>, data: &CountData) {
+    while !data.borrow().connected {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+}
+
+#[test]
+fn event_loop_timeout_waits_until_data_is_received() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(700));
+        conn.write_all(&[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+
+    // Nothing to do, the call waits until the timeout expires
+    let start = Instant::now();
+    client.event_loop_timeout(Duration::from_millis(100)).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert_eq!(data.borrow().messages, 0);
+
+    // The message wakes up the call before the timeout
+    let start = Instant::now();
+    while data.borrow().messages == 0 {
+        client.event_loop_timeout(Duration::from_secs(5)).unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_secs(2));
+    server.join().unwrap();
+}
+
+#[test]
+fn drain_processes_all_the_frames_ready() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(500));
+
+        let mut frames = Vec::new();
+        for _ in 0..5 { frames.extend_from_slice(&[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]) }
+        conn.write_all(&frames).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    client.set_drain(true);
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+
+    while data.borrow().messages == 0 {
+        client.event_loop_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    assert_eq!(data.borrow().messages, 5);
+    server.join().unwrap();
+}
+
+// -------------------- Blocking client -------------------- //
+
+#[test]
+fn blocking_client_send_recv_and_server_close() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+
+        let frame = mock_wait_for_frame_sync(&mut conn);
+        assert_eq!(String::from_utf8(mock_unmask_data(&frame)).unwrap(), "Hello");
+
+        // Echo the message and close the connection
+        conn.write_all(&[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]).unwrap();
+        conn.write_all(&[0x88, 0x05, 0x03, 0xE8, b'b', b'y', b'e']).unwrap();
+
+        let close_frame = mock_wait_for_frame_sync(&mut conn);
+        let (status, _) = mock_unmask_control_frame(&close_frame);
+        assert_eq!(status, 1000);
+    });
+
+    let mut client = WSBlockingClient::connect("localhost", port, "/", None).unwrap();
+    client.send("Hello").unwrap();
+
+    assert_eq!(client.recv().unwrap(), Message::TEXT(String::from("Hello")));
+    assert!(client.recv().err().unwrap() == WebSocketError::ConnectionClose);
+    assert_eq!(client.close_reason(), Some(&Reason::SERVER_CLOSE(CloseCode::NormalClosure, String::from("bye"))));
+    assert!(client.send("Hello").err().unwrap() == WebSocketError::ConnectionClose);
+    server.join().unwrap();
+}
+
+#[test]
+fn blocking_client_recv_timeout_waits_without_messages() {
+    let (listener, port) = setup();
+
+    thread::spawn(move || {
+        let conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(1000));
+        drop(conn);
+    });
+
+    let mut client = WSBlockingClient::connect("localhost", port, "/", None).unwrap();
+
+    let start = Instant::now();
+    assert_eq!(client.recv_timeout(Duration::from_millis(300)).unwrap(), None);
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(300));
+    assert!(elapsed < Duration::from_millis(900));
+}
+
+#[test]
+fn blocking_client_connect_handshake_error() {
+    let (listener, port) = setup();
+
+    thread::spawn(move || {
+        let conn = mock_accept_connection_no_websocket_key(listener);
+        thread::sleep(Duration::from_millis(500));
+        drop(conn);
+    });
+
+    let error = WSBlockingClient::connect("localhost", port, "/", None).err().unwrap();
+    assert!(error == WebSocketError::HandShake(HandShakeError::MissingAcceptKey));
+}
+
+// -------------------- External reactor -------------------- //
+
+#[test]
+#[cfg(unix)]
+fn mio_reactor_drives_the_connection() {
+    use mio::{Events, Interest, Poll, Token};
+    use mio::unix::SourceFd;
+
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+
+        let frame = mock_wait_for_frame_sync(&mut conn);
+        assert_eq!(String::from_utf8(mock_unmask_data(&frame)).unwrap(), "Hello");
+
+        conn.write_all(&[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]).unwrap();
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+
+        let close_frame = mock_wait_for_frame_sync(&mut conn);
+        let (status, _) = mock_unmask_control_frame(&close_frame);
+        assert_eq!(status, 1000);
+    });
+
+    let mut client = WSClient::new();
+    assert!(!client.wants_read() && !client.wants_write());
+    assert!(client.raw_fd().is_none());
+
+    // The first call connects the socket and queues the handshake request
+    client.init("localhost", port, "/", count_config(&data));
+    client.on_writable().unwrap();
+    assert!(client.wants_read() && client.wants_write());
+
+    const CLIENT: Token = Token(0);
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(8);
+    let fd = client.raw_fd().unwrap();
+    poll.registry().register(&mut SourceFd(&fd), CLIENT, Interest::READABLE | Interest::WRITABLE).unwrap();
+
+    let mut sent = false;
+    while client.wants_read() {
+        poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+        assert!(!events.is_empty(), "the reactor didn't report readiness");
+
+        for event in events.iter() {
+            if event.is_readable() { let _ = client.on_readable(); }
+            if event.is_writable() && client.wants_write() { let _ = client.on_writable(); }
+        }
+
+        if data.borrow().connected && !sent {
+            client.send("Hello").unwrap();
+            sent = true;
+        }
+
+        // Only ask for writable events while there's data to send
+        if client.wants_read() {
+            let interest = if client.wants_write() { Interest::READABLE | Interest::WRITABLE } else { Interest::READABLE };
+            poll.registry().reregister(&mut SourceFd(&fd), CLIENT, interest).unwrap();
+        }
+    }
+
+    assert_eq!(data.borrow().messages, 1);
+    assert_eq!(client.close_reason(), Some(&Reason::SERVER_CLOSE(CloseCode::NormalClosure, String::new())));
+    // The socket was closed with the connection
+    assert!(client.raw_fd().is_none());
+    server.join().unwrap();
+}
+
+// -------------------- Sender handle -------------------- //
+
+#[test]
+fn sender_queues_frames_from_other_threads() {
+    fn assert_send_sync<S: Send + Sync + Clone>(_: &S) {}
+
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+
+        let frames: Vec<(u8, Vec<u8>)> = (0..4).map(|_| mock_read_frame(&mut conn)).collect();
+
+        assert_eq!(frames[0], (0x81, b"text".to_vec()));
+        assert_eq!(frames[1], (0x82, vec![1, 2, 3]));
+        assert_eq!(frames[2], (0x89, b"ping".to_vec()));
+        assert_eq!(frames[3], (0x88, [&[0x03, 0xE9][..], b"bye"].concat()));
+
+        conn.write_all(&[0x8A, 0x04, b'p', b'i', b'n', b'g']).unwrap();
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE9]).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    let sender = client.sender();
+    assert_send_sync(&sender);
+
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+
+    let handle = sender.clone();
+    thread::spawn(move || {
+        handle.send_text("text").unwrap();
+        handle.send_binary(&[1, 2, 3]).unwrap();
+        handle.ping(b"ping").unwrap();
+        handle.close(CloseCode::GoingAway, "bye").unwrap();
+    }).join().unwrap();
+
+    assert!(sender.close(CloseCode::AbnormalClosure, "").err().unwrap() == WebSocketError::Protocol(ProtocolError::InvalidCloseFrame));
+    assert!(sender.close(CloseCode::Private(7), "").err().unwrap() == WebSocketError::Protocol(ProtocolError::InvalidCloseFrame));
+
+    while client.close_reason().is_none() {
+        client.event_loop_timeout(Duration::from_millis(50)).unwrap();
+    }
+
+    assert_eq!(client.close_reason(), Some(&Reason::CLIENT_CLOSE(CloseCode::GoingAway, String::new())));
+    assert!(sender.is_closed());
+    assert!(sender.send_text("late").err().unwrap() == WebSocketError::ConnectionClose);
+    server.join().unwrap();
+}
+
+#[test]
+fn sender_wakes_up_event_loop_timeout() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn), (0x81, b"now".to_vec()));
+        thread::sleep(Duration::from_millis(100));
+    });
+
+    let mut client = WSClient::new();
+    let sender = client.sender();
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        sender.send_text("now").unwrap();
+    });
+
+    // The message is sent long before the timeout expires
+    let start = Instant::now();
+    client.event_loop_timeout(Duration::from_secs(10)).unwrap();
+    assert!(start.elapsed() < Duration::from_secs(2));
+    while client.stats().frames_sent == 0 { client.event_loop_timeout(Duration::from_millis(50)).unwrap() }
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    handle.join().unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn sender_fails_once_the_client_is_dropped() {
+    let mut client = WSClient::<()>::new();
+    let sender = client.sender();
+    drop(client);
+
+    assert!(sender.send_text("Hello").err().unwrap() == WebSocketError::ConnectionClose);
+}
+
+#[test]
+fn sender_message_that_can_not_be_queued_is_reported_by_the_event_loop() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn), (0x81, b"next".to_vec()));
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    client.set_fixed_buffers(512, 512);
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+
+    let sender = client.sender();
+    sender.send_binary(&[0; 600]).unwrap();
+    sender.send_text("next").unwrap();
+
+    let error = client.event_loop().err().unwrap();
+    assert!(error == WebSocketError::Capacity(CapacityError::SendBufferTooSmall { max: 373 }));
+
+    // The connection is still open, the next message is sent
+    assert!(client.is_open());
+    while data.borrow().drained == 0 {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+    server.join().unwrap();
+}
+
+// -------------------- Outbound queue -------------------- //
+
+#[test]
+fn send_fails_when_the_queue_is_full() {
+    let mut client = WSClient::<()>::new();
+    client.set_max_queued_messages(2);
+
+    assert_eq!(client.send("Hello").unwrap(), 0);
+    assert_eq!(client.send("World").unwrap(), 1);
+    assert!(client.send("!").err().unwrap() == WebSocketError::QueueFull);
+    assert_eq!((client.queued_messages(), client.queued_bytes()), (2, 10));
+
+    let mut client = WSClient::<()>::new();
+    client.set_max_queued_bytes(8);
+
+    // A message bigger than the limit fits in an empty queue
+    assert_eq!(client.send("Hello world").unwrap(), 0);
+    assert!(client.send("Hello").err().unwrap() == WebSocketError::QueueFull);
+    assert_eq!(client.queued_bytes(), 11);
+}
+
+#[test]
+fn drained_is_notified_when_the_queue_is_sent() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn), (0x01, b"Hel".to_vec()));
+        assert_eq!(mock_read_frame(&mut conn), (0x80, b"lo".to_vec()));
+        assert_eq!(mock_read_frame(&mut conn), (0x81, b"abc".to_vec()));
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    client.set_message_size(3);
+    client.init("localhost", port, "/", count_config(&data));
+
+    // Queued before the handshake, sent once the connection is open
+    assert_eq!(client.send("Hello").unwrap(), 0);
+    assert_eq!(client.send("abc").unwrap(), 1);
+    assert_eq!(client.queued_bytes(), 8);
+
+    while data.borrow().drained == 0 {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+    assert_eq!(data.borrow().drained, 1);
+    assert_eq!((client.queued_messages(), client.queued_bytes()), (0, 0));
+
+    while client.close_reason().is_none() {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+    assert!(client.send("late").err().unwrap() == WebSocketError::ConnectionClose);
+    server.join().unwrap();
+}
+
+// -------------------- Partial writes -------------------- //
+
+#[test]
+#[cfg(unix)]
+fn large_messages_survive_partial_writes() {
+    use std::os::unix::io::AsRawFd;
+
+    fn set_buffer_size(fd: i32, option: i32, size: i32) {
+        let res = unsafe {
+            libc::setsockopt(fd, libc::SOL_SOCKET, option, &size as *const i32 as *const libc::c_void, std::mem::size_of::<i32>() as libc::socklen_t)
+        };
+        assert_eq!(res, 0);
+    }
+
+    let messages: Vec<String> = (0..3u8).map(|i| ((b'a' + i) as char).to_string().repeat(512 * 1024 + i as usize)).collect();
+    let expected = messages.clone();
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        set_buffer_size(conn.as_raw_fd(), libc::SO_RCVBUF, 16 * 1024);
+
+        // Slow reader, the client fills the socket buffer before the first read
+        thread::sleep(Duration::from_millis(200));
+        for message in expected {
+            let (header, payload) = mock_read_frame(&mut conn);
+            assert_eq!(header, 0x81);
+            assert!(payload == message.as_bytes());
+        }
+
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    client.set_message_size(1024 * 1024);
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+    set_buffer_size(client.raw_fd().unwrap(), libc::SO_SNDBUF, 16 * 1024);
+
+    for message in messages.iter() { client.send(message).unwrap(); }
+
+    while client.close_reason().is_none() {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+
+    assert_eq!(data.borrow().drained, 1);
+    assert_eq!(client.close_reason(), Some(&Reason::SERVER_CLOSE(CloseCode::NormalClosure, String::new())));
+    server.join().unwrap();
+}
+
+#[test]
+fn owned_binary_messages_are_not_copied_into_the_frames() {
+    let first: Vec<u8> = (0..200 * 1024).map(|i| i as u8).collect();
+    let second = vec![7u8; 50 * 1024];
+    let (expected_first, expected_second) = (first.clone(), second.clone());
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+
+        // Fragmented in frames of message_size
+        let mut message = Vec::new();
+        for header in [0x02, 0x00, 0x00, 0x80] {
+            let (received, payload) = mock_read_frame(&mut conn);
+            assert_eq!(received, header);
+            message.extend(payload);
+        }
+        assert!(message == expected_first);
+
+        // Fits in a frame, the buffer of the message is the payload of the frame
+        let (header, payload) = mock_read_frame(&mut conn);
+        assert_eq!(header, 0x82);
+        assert!(payload == expected_second);
+
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    client.set_message_size(64 * 1024);
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+
+    assert_eq!(client.send_binary(first).unwrap(), 0);
+    assert_eq!(client.send_binary(second).unwrap(), 1);
+    assert_eq!(client.queued_bytes(), 250 * 1024);
+
+    while client.close_reason().is_none() {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+
+    assert_eq!(data.borrow().drained, 1);
+    server.join().unwrap();
+}
+
+// -------------------- Streaming send -------------------- //
+
+#[test]
+fn streamed_message_lets_control_frames_between_and_holds_messages() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn), (0x01, b"Hel".to_vec()));
+        assert_eq!(mock_read_frame(&mut conn), (0x89, b"p".to_vec()));
+        assert_eq!(mock_read_frame(&mut conn), (0x00, b"lo".to_vec()));
+        assert_eq!(mock_read_frame(&mut conn), (0x80, Vec::new()));
+        // Message sent while the other one was streamed
+        assert_eq!(mock_read_frame(&mut conn), (0x81, b"after".to_vec()));
+
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    client.set_max_queued_bytes(3);
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+    let sender = client.sender();
+
+    let mut writer = client.begin_message(MessageKind::TEXT).unwrap();
+    writer.write_all(b"Hel").unwrap();
+    sender.ping(b"p").unwrap();
+    sender.send_text("after").unwrap();
+
+    // The queue is full, the write runs the event loop until "Hel" is sent
+    writer.write_all(b"lo").unwrap();
+    assert_eq!(writer.finish().unwrap(), 0);
+
+    while client.close_reason().is_none() {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+
+    assert_eq!(data.borrow().drained, 1);
+    server.join().unwrap();
+}
+
+#[test]
+fn streamed_message_counts_in_the_queue() {
+    let mut client = WSClient::<()>::new();
+    client.set_message_size(4);
+
+    let mut writer = client.begin_message(MessageKind::BINARY).unwrap();
+    writer.write_all(b"0123456789").unwrap();
+    assert_eq!(writer.finish().unwrap(), 0);
+    assert_eq!((client.queued_messages(), client.queued_bytes()), (1, 10));
+
+    // Dropping the writer finishes the message
+    drop(client.begin_message(MessageKind::TEXT).unwrap());
+    assert_eq!(client.send("next").unwrap(), 2);
+
+    client.set_max_queued_messages(3);
+    assert!(client.begin_message(MessageKind::TEXT).err().unwrap() == WebSocketError::QueueFull);
+}
+
+#[test]
+fn finish_timed_out_still_ends_the_message() {
+    let mut client = WSClient::<()>::new();
+    client.set_max_queued_bytes(3);
+    client.set_timeout(Duration::ZERO);
+
+    let mut writer = client.begin_message(MessageKind::BINARY).unwrap();
+    writer.write_all(b"0123456789").unwrap();
+    // The queue is over the limit and nothing can be sent before the timeout
+    assert!(writer.finish().err().unwrap() == WebSocketError::Timeout);
+
+    // The message was ended when the writer was dropped, the client can send again
+    client.set_max_queued_bytes(usize::MAX);
+    assert_eq!(client.send("next").unwrap(), 1);
+    assert_eq!(client.begin_message(MessageKind::TEXT).unwrap().id(), 2);
+}
+
+// -------------------- Streaming receive -------------------- //
+
+#[test]
+fn fragments_are_delivered_as_they_arrive() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn), (0x81, b"go".to_vec()));
+        // "café!" with the "é" (0xC3 0xA9) split between two frames, bigger than max_message_size
+        conn.write_all(&[0x01, 0x04, b'c', b'a', b'f', 0xC3]).unwrap();
+        conn.write_all(&[0x00, 0x01, 0xA9]).unwrap();
+        conn.write_all(&[0x80, 0x01, b'!']).unwrap();
+        conn.write_all(&[0x82, 0x02, 0xFF, 0x00]).unwrap();
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    client.set_stream_fragments(true);
+    client.set_max_message_size(4);
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+    client.send("go").unwrap();
+
+    while client.close_reason().is_none() {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+
+    let expected = vec![
+        (MessageKind::TEXT, vec![b'c', b'a', b'f', 0xC3], false),
+        (MessageKind::TEXT, vec![0xA9], false),
+        (MessageKind::TEXT, b"!".to_vec(), true),
+        (MessageKind::BINARY, vec![0xFF, 0x00], true),
+    ];
+    assert_eq!(data.borrow().fragments, expected);
+    assert_eq!(data.borrow().messages, 0);
+    assert_eq!(client.close_reason(), Some(&Reason::SERVER_CLOSE(CloseCode::NormalClosure, String::new())));
+    server.join().unwrap();
+}
+
+#[test]
+fn invalid_utf8_between_fragments_fails_connection() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn), (0x81, b"go".to_vec()));
+        // The character started in the first frame is not continued in the second one
+        conn.write_all(&[0x01, 0x02, b'a', 0xC3]).unwrap();
+        conn.write_all(&[0x80, 0x01, b'b']).unwrap();
+        assert_eq!(mock_read_frame(&mut conn), (0x88, vec![0x03, 0xEF]));
+    });
+
+    let mut client = WSClient::new();
+    client.set_stream_fragments(true);
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+    client.send("go").unwrap();
+
+    let error = loop {
+        if let Err(e) = client.event_loop_timeout(Duration::from_secs(1)) { break e }
+    };
+
+    assert!(error == WebSocketError::Protocol(ProtocolError::InvalidUTF8));
+    assert_eq!(data.borrow().fragments, vec![(MessageKind::TEXT, vec![b'a', 0xC3], false)]);
+    server.join().unwrap();
+}
+
+// -------------------- Binary messages -------------------- //
+
+#[test]
+fn binary_message_not_utf8_is_delivered() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(100));
+        // Binary message in two frames that is not UTF-8, then a text message
+        conn.write_all(&[0x02, 0x02, 0xFF, 0xFE, 0x80, 0x01, 0x80]).unwrap();
+        conn.write_all(&[0x81, 0x02, b'o', b'k']).unwrap();
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    client.init("localhost", port, "/", count_config(&data));
+
+    while client.close_reason().is_none() {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+
+    assert_eq!(data.borrow().binaries, vec![vec![0xFF, 0xFE, 0x80]]);
+    assert_eq!(data.borrow().messages, 1);
+    assert_eq!(client.close_reason().unwrap().code(), CloseCode::NormalClosure);
+    server.join().unwrap();
+}
+
+#[test]
+fn blocking_client_receives_binary_messages() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(100));
+        conn.write_all(&[0x82, 0x03, 0x00, 0xC3, 0x28]).unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSBlockingClient::connect("localhost", port, "/", None).unwrap();
+    assert_eq!(client.recv().unwrap(), Message::BINARY(vec![0x00, 0xC3, 0x28]));
+    drop(client);
+    server.join().unwrap();
+}
+
+// -------------------- Split -------------------- //
+
+fn split_client(port: u16) -> (WSReader, WSWriter) {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let mut client = WSClient::new();
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+    client.split().unwrap()
+}
+
+#[test]
+fn split_reader_and_writer_in_different_threads() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn), (0x81, b"request".to_vec()));
+
+        // The reader answers the ping through the writer
+        conn.write_all(&[0x89, 0x01, b'p']).unwrap();
+        conn.write_all(&[0x81, 0x08, b'r', b'e', b's', b'p', b'o', b'n', b's', b'e']).unwrap();
+        assert_eq!(mock_read_frame(&mut conn), (0x8A, b"p".to_vec()));
+
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        assert_eq!(mock_read_frame(&mut conn), (0x88, vec![0x03, 0xE8]));
+        thread::sleep(Duration::from_millis(200));
+    });
+
+    let (mut reader, mut writer) = split_client(port);
+
+    let reader_thread = thread::spawn(move || {
+        assert_eq!(reader.recv().unwrap(), Message::TEXT(String::from("response")));
+        assert!(reader.recv().err().unwrap() == WebSocketError::ConnectionClose);
+        reader
+    });
+
+    writer.send("request").unwrap();
+    let reader = reader_thread.join().unwrap();
+
+    assert_eq!(reader.close_reason(), Some(Reason::SERVER_CLOSE(CloseCode::NormalClosure, String::new())));
+    assert!(writer.send("late").err().unwrap() == WebSocketError::ConnectionClose);
+    server.join().unwrap();
+}
+
+#[test]
+fn split_reader_rejects_invalid_fragment_sequences() {
+    // New binary frame while a fragmented message is open, and a continuation frame without a message
+    let cases: [&[u8]; 2] = [&[0x02, 0x01, 0x01, 0x82, 0x01, 0x02], &[0x80, 0x01, 0x01]];
+
+    for frames in cases {
+        let (listener, port) = setup();
+
+        let server = thread::spawn(move || {
+            let mut conn = mock_accept_connection(listener);
+            thread::sleep(Duration::from_millis(100));
+            conn.write_all(frames).unwrap();
+            assert_eq!(mock_read_frame(&mut conn), (0x88, vec![0x03, 0xEA]));
+        });
+
+        let (mut reader, _writer) = split_client(port);
+        assert!(reader.recv().err().unwrap() == WebSocketError::Protocol(ProtocolError::InvalidFrame));
+        assert_eq!(reader.close_reason(), Some(Reason::CLIENT_CLOSE(CloseCode::ProtocolError, String::new())));
+        server.join().unwrap();
+    }
+}
+
+#[test]
+fn split_writer_close_reaches_reader() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn), (0x88, [&[0x03, 0xE8][..], b"bye"].concat()));
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        thread::sleep(Duration::from_millis(200));
+    });
+
+    let (mut reader, mut writer) = split_client(port);
+
+    // The reader is waiting when the writer starts the close
+    let reader_thread = thread::spawn(move || reader.recv().err().unwrap());
+    thread::sleep(Duration::from_millis(100));
+    writer.close(CloseCode::NormalClosure, "bye").unwrap();
+
+    assert!(reader_thread.join().unwrap() == WebSocketError::ConnectionClose);
+    assert_eq!(writer.close_reason(), Some(Reason::CLIENT_CLOSE(CloseCode::NormalClosure, String::new())));
+    assert!(writer.send("late").err().unwrap() == WebSocketError::ConnectionClose);
+    server.join().unwrap();
+}
+
+#[test]
+fn split_reader_close_stops_writer() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn), (0x88, vec![0x03, 0xE9]));
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE9]).unwrap();
+        thread::sleep(Duration::from_millis(200));
+    });
+
+    let (mut reader, mut writer) = split_client(port);
+    assert!(writer.close(CloseCode::Registered(1000), "").err().unwrap() == WebSocketError::Protocol(ProtocolError::InvalidCloseFrame));
+    reader.close(CloseCode::GoingAway, "").unwrap();
+    assert!(writer.send("Hello").err().unwrap() == WebSocketError::ConnectionClose);
+
+    assert!(reader.recv().err().unwrap() == WebSocketError::ConnectionClose);
+    assert_eq!(reader.close_reason(), Some(Reason::CLIENT_CLOSE(CloseCode::GoingAway, String::new())));
+    server.join().unwrap();
+}
+
+#[test]
+fn split_reader_receives_binary_messages() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(100));
+        // Binary message in two frames, the bytes are not UTF-8
+        conn.write_all(&[0x02, 0x02, 0xFF, 0xFE]).unwrap();
+        conn.write_all(&[0x80, 0x01, 0x00]).unwrap();
+        thread::sleep(Duration::from_millis(200));
+    });
+
+    let (mut reader, _writer) = split_client(port);
+    assert_eq!(reader.recv().unwrap(), Message::BINARY(vec![0xFF, 0xFE, 0x00]));
+    server.join().unwrap();
+}
+
+#[test]
+fn split_writer_message_size_0_sends_frames_of_1_byte() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn), (0x01, b"a".to_vec()));
+        assert_eq!(mock_read_frame(&mut conn), (0x80, b"b".to_vec()));
+    });
+
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let mut client = WSClient::new();
+    client.set_message_size(0);
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+
+    let (_reader, mut writer) = client.split().unwrap();
+    writer.send("ab").unwrap();
+    server.join().unwrap();
+}
+
+// -------------------- Fixed buffers -------------------- //
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn fixed_buffers_send_and_receive_without_allocating() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        for round in 0..3 {
+            // The pong of the previous round is sent before the next message
+            if round > 0 { assert_eq!(mock_read_frame(&mut conn), (0x8A, b"hi".to_vec())) }
+            assert_eq!(mock_read_frame(&mut conn), (0x81, b"hello".to_vec()));
+            conn.write_all(&[0x89, 0x02, b'h', b'i']).unwrap();
+            conn.write_all(&[0x81, 0x05, b'h', b'e', b'l', b'l', b'o']).unwrap();
+        }
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    client.set_fixed_buffers(512, 512);
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+
+    // Warm up, the first round can't allocate either but the measure starts with the connection already used
+    let echo = |client: &mut WSClient<CountData>, messages: usize| {
+        client.send("hello").unwrap();
+        while data.borrow().messages < messages {
+            client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+        }
+    };
+    echo(&mut client, 1);
+
+    let (_, allocations) = count_allocations(|| {
+        echo(&mut client, 2);
+        echo(&mut client, 3);
+    });
+    assert_eq!(allocations, Allocations::default());
+    assert!(data.borrow().drained >= 2);
+    server.join().unwrap();
+}
+
+#[test]
+fn fixed_send_buffer_rejects_the_messages_that_do_not_fit() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn).1.len(), 300);
+        assert_eq!(mock_read_frame(&mut conn).1.len(), 300);
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = WSClient::new();
+    client.set_fixed_buffers(512, 512);
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+
+    // The send buffer keeps room for a pong (139 bytes)
+    let error = client.send(&"a".repeat(600)).err().unwrap();
+    assert!(error == WebSocketError::Capacity(CapacityError::SendBufferTooSmall { max: 373 }));
+
+    // The second message fits once the first one is written
+    client.send(&"a".repeat(300)).unwrap();
+    assert!(client.send(&"a".repeat(300)).err().unwrap() == WebSocketError::QueueFull);
+    while client.queued_messages() > 0 {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+    assert_eq!(data.borrow().drained, 1);
+    client.send(&"a".repeat(300)).unwrap();
+    client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn frame_bigger_than_fixed_recv_buffer_closes_connection() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(500)); // Don't send the frame with the handshake response
+
+        // The frame needs 4 + 250 bytes, the buffer has 256 bytes but the limit counts the longest header (10 bytes)
+        conn.write_all(&[0x82, 0x7E, 0x00, 0xFA]).unwrap();
+        conn.write_all(&[0u8; 250]).unwrap();
+        assert_eq!(mock_read_frame(&mut conn), (0x88, vec![0x03, 0xF1]));
+    });
+
+    let mut client = WSClient::new();
+    client.set_fixed_buffers(256, 512);
+    client.init("localhost", port, "/", count_config(&data));
+
+    let error = loop {
+        if let Err(e) = client.event_loop_timeout(Duration::from_secs(1)) { break e }
+    };
+
+    assert!(error == WebSocketError::Capacity(CapacityError::FrameTooBig { max: 246 }));
+    assert_eq!(client.close_reason(), Some(&Reason::CLIENT_CLOSE(CloseCode::MessageTooBig, String::new())));
+    server.join().unwrap();
+}
+
+// -------------------- Random source -------------------- //
+
+struct FailingRandom;
+
+impl RandomSource for FailingRandom {
+    fn fill(&mut self, _bytes: &mut [u8]) -> Result<(), CodecError> {
+        Err(CodecError::Random)
+    }
+}
+
+#[test]
+fn seeded_random_makes_the_key_and_the_frames_predictable() {
+    let (listener, port) = setup();
+
+    // The client takes the key from the seed first and then the mask of the frame
+    let mut random = SeededRandom::new(7);
+    let mut key = [0u8; 16];
+    random.fill(&mut key).unwrap();
+    let key = base64::encode(&key);
+    let mut expected = Vec::new();
+    FrameEncoder::new(random).encode(FLAG::FIN, OPCODE::TEXT, b"hello", &mut expected).unwrap();
+
+    let server = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let request = String::from_utf8(read_all_sync(&mut conn)).unwrap();
+        assert!(request.contains(&format!("\r\nSec-WebSocket-Key: {}\r\n", key)));
+
+        let response = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", sec_websocket_accept(&key));
+        conn.write_all(response.as_bytes()).unwrap();
+
+        let mut frame = vec![0u8; expected.len()];
+        conn.read_exact(&mut frame).unwrap();
+        assert_eq!(frame, expected);
+    });
+
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let mut client = WSClient::new();
+    let mut config = count_config(&data).unwrap();
+    config.random = Some(shared_random(SeededRandom::new(7)));
+    client.init("localhost", port, "/", Some(config));
+    wait_until_open(&mut client, &data);
+
+    client.send("hello").unwrap();
+    while client.queued_messages() > 0 {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn failing_random_source_fails_instead_of_sending_zeros() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        // The connection is closed without a handshake request
+        assert!(read_all_sync(&mut conn).is_empty());
+    });
+
+    let mut client = WSClient::<()>::new();
+    let config = Config { callback: None, data: None, protocols: None, random: Some(shared_random(FailingRandom)) };
+    client.init("localhost", port, "/", Some(config));
+
+    let error = client.event_loop().err().unwrap();
+    assert_eq!(error.to_string(), "WebSocketError: IOError, the random source failed");
+    drop(client);
+    server.join().unwrap();
+}
+
+// -------------------- Connection state -------------------- //
+
+#[test]
+fn state_and_connection_info_follow_the_connection() {
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection_with_headers(listener, "Sec-WebSocket-Protocol: chat\r\n");
+        thread::sleep(Duration::from_millis(200));
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        assert_eq!(mock_read_frame(&mut conn).0, 0x88);
+        conn.peer_addr().unwrap()
+    });
+
+    let protocols = ["chat"];
+    let mut client = WSClient::<u32>::new();
+    assert_eq!(client.state(), ConnectionState::NOT_INIT);
+    client.init("localhost", port, "/", Some(Config { callback: None, data: None, protocols: Some(&protocols), random: None }));
+    assert_eq!(client.state(), ConnectionState::CONNECTING);
+    client.event_loop().unwrap();
+    assert_eq!(client.state(), ConnectionState::HANDSHAKE);
+    assert!(client.connect_time().is_none() && client.uptime().is_none());
+
+    while !client.is_open() {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+
+    assert_eq!(client.state(), ConnectionState::OPEN);
+    assert_eq!(client.peer_addr().unwrap().port(), port);
+    let local_addr = client.local_addr().unwrap();
+    assert!(client.connect_time().unwrap() <= SystemTime::now());
+    assert_eq!(client.protocol(), Some("chat"));
+    assert!(client.extensions().is_empty());
+
+    // The server closes the connection after 200 ms
+    while client.state() != ConnectionState::CLOSED {
+        let _ = client.event_loop_timeout(Duration::from_secs(1));
+    }
+
+    assert_eq!(server.join().unwrap(), local_addr);
+    assert!(!client.is_open());
+    assert!(client.peer_addr().is_none() && client.local_addr().is_none());
+
+    // The uptime stops with the close
+    let uptime = client.uptime().unwrap();
+    assert!(uptime >= Duration::from_millis(100));
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(client.uptime().unwrap(), uptime);
+}
+
+// -------------------- Statistics -------------------- //
+
+fn metrics_hook(stats: &Stats, data: Option<CountData>) {
+    data.unwrap().borrow_mut().reports.push(*stats);
+}
+
+#[test]
+fn stats_count_the_traffic_of_the_connection() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        assert_eq!(mock_read_frame(&mut conn), (0x81, b"hello".to_vec()));
+        assert_eq!(mock_read_frame(&mut conn), (0x82, vec![1, 2, 3]));
+        assert_eq!(mock_read_frame(&mut conn), (0x89, b"p".to_vec()));
+        thread::sleep(Duration::from_millis(50));
+
+        // Pong, ping, a text message in two frames and a binary message
+        conn.write_all(&[0x8A, 0x01, b'p', 0x89, 0x00, 0x01, 0x01, b'h', 0x80, 0x01, b'i', 0x82, 0x01, 0x07]).unwrap();
+        assert_eq!(mock_read_frame(&mut conn), (0x8A, Vec::new()));
+        thread::sleep(Duration::from_millis(300));
+    });
+
+    let mut client = WSClient::new();
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+    assert!(client.stats().handshake_duration.is_some());
+
+    client.send("hello").unwrap();
+    client.send_binary(vec![1, 2, 3]).unwrap();
+    client.sender().ping(b"p").unwrap();
+
+    while client.stats().pongs_sent == 0 || client.stats().binary_messages_received == 0 {
+        client.event_loop_timeout(Duration::from_secs(1)).unwrap();
+    }
+
+    let stats = client.stats();
+    assert_eq!((stats.frames_sent, stats.frames_received), (4, 5));
+    assert_eq!((stats.text_messages_sent, stats.binary_messages_sent), (1, 1));
+    assert_eq!((stats.text_messages_received, stats.binary_messages_received), (1, 1));
+    assert_eq!((stats.pings_sent, stats.pongs_received, stats.pings_received, stats.pongs_sent), (1, 1, 1, 1));
+    assert!(stats.last_rtt.unwrap() >= Duration::from_millis(50));
+    assert_eq!((stats.queue_high_water_messages, stats.queue_high_water_bytes), (2, 8));
+    // The frames and the handshake
+    assert!(stats.bytes_sent > 6 + 11 + 9 + 7 && stats.bytes_received > 14);
+    assert_eq!(stats.reconnects, 0);
+    server.join().unwrap();
+}
+
+#[test]
+fn metrics_hook_reports_on_the_interval_and_on_close() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(100));
+        conn.write_all(&[0x81, 0x02, b'h', b'i', 0x88, 0x02, 0x03, 0xE8]).unwrap();
+        assert_eq!(mock_read_frame(&mut conn).0, 0x88);
+    });
+
+    let mut client = WSClient::new();
+    client.set_metrics_hook(metrics_hook, Duration::from_secs(3600));
+    client.init("localhost", port, "/", count_config(&data));
+    while client.state() != ConnectionState::CLOSED {
+        let _ = client.event_loop_timeout(Duration::from_secs(1));
+    }
+
+    // The interval didn't pass, only the report of the close
+    assert_eq!(data.borrow().reports, vec![client.stats()]);
+    assert_eq!(client.stats().text_messages_received, 1);
+    server.join().unwrap();
+}
+
+#[test]
+fn reconnection_is_counted_and_the_stats_are_kept() {
+    let data: CountData = Rc::new(RefCell::new(Counters::default()));
+    let (listener, port) = setup();
+
+    let server = thread::spawn(move || {
+        let mut conn = mock_accept_connection(listener.try_clone().unwrap());
+        thread::sleep(Duration::from_millis(100)); // Don't send the frame with the handshake response
+        conn.write_all(&[0x88, 0x02, 0x03, 0xE8]).unwrap();
+        assert_eq!(mock_read_frame(&mut conn).0, 0x88);
+
+        let _conn = mock_accept_connection(listener);
+        thread::sleep(Duration::from_millis(300));
+    });
+
+    let mut client = WSClient::new();
+    client.init("localhost", port, "/", count_config(&data));
+    while client.state() != ConnectionState::CLOSED {
+        let _ = client.event_loop_timeout(Duration::from_secs(1));
+    }
+    let first = client.stats();
+
+    data.borrow_mut().connected = false;
+    client.init("localhost", port, "/", count_config(&data));
+    wait_until_open(&mut client, &data);
+
+    let stats = client.stats();
+    assert_eq!((first.reconnects, stats.reconnects), (0, 1));
+    assert!(stats.bytes_received > first.bytes_received && stats.bytes_sent > first.bytes_sent);
+    assert_eq!(stats.frames_received, first.frames_received);
+    server.join().unwrap();
+}